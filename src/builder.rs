@@ -0,0 +1,562 @@
+use std::fmt;
+
+use crate::{
+    crypto::{CryptoCosts, CryptoOperation, OperationCosts, Operations},
+    latency::TimingConfig,
+    scenario::CostConstants,
+    CommunicationOverheadType, CommunicationType, EnergyConsumptionType, EnergyType,
+    ExchangeCostType, ExchangeType, InvolvedDevicesCount, InvolvedExchangesCount, MetricsType,
+    NodeStatus, NodeType, Role, StateCostType,
+};
+
+// Fluent construction of a MetricsType. Every (status, role) cell starts without any exchange and
+// with the scenario cost constants, so a scheme only describes the exchanges it performs:
+//
+//     MetricsType::builder(&costs)
+//         .cell(NodeStatus::Leaving, Role::Gateway, |cell| {
+//             cell.sent(1)
+//                 .received_from(InvolvedExchangesCount::Neighbors, number_of_neighbors)
+//                 .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+//                 .operation(CryptoOperation::EcdsaSign, 1)
+//         })
+//         .build()
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsBuilder {
+    cells: [[CellBuilder; 3]; 3],
+    crypto: Option<CryptoCosts>,
+    timing: TimingConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CellBuilder {
+    sent: u32,
+    received: u32,
+    messages: (InvolvedExchangesCount, InvolvedExchangesCount),
+    number_of_involved_devices: u32,
+    involved_devices: InvolvedDevicesCount,
+    energy_cost: ExchangeCostType,
+    communication_cost: ExchangeCostType,
+    // Performed by every involved device
+    operations: Operations,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsBuildError {
+    pub status: NodeStatus,
+    pub role: Role,
+    pub reason: String,
+}
+
+impl MetricsType {
+    pub fn builder(costs: &CostConstants) -> MetricsBuilder {
+        MetricsBuilder::new(costs)
+    }
+}
+
+impl Default for MetricsBuilder {
+    fn default() -> Self {
+        Self::new(&CostConstants::default())
+    }
+}
+
+impl MetricsBuilder {
+    pub fn new(costs: &CostConstants) -> Self {
+        let cell = CellBuilder {
+            sent: 0,
+            received: 0,
+            messages: (
+                InvolvedExchangesCount::SameAsDefined,
+                InvolvedExchangesCount::SameAsDefined,
+            ),
+            number_of_involved_devices: 0,
+            involved_devices: InvolvedDevicesCount::SameAsDefined,
+            energy_cost: ExchangeCostType {
+                sent: costs.epsb,
+                received: costs.eprb,
+            },
+            communication_cost: ExchangeCostType {
+                sent: costs.sent_message_size as f32,
+                received: costs.received_message_size as f32,
+            },
+            operations: Operations::default(),
+        };
+        Self {
+            cells: [[cell; 3]; 3],
+            crypto: costs.crypto,
+            timing: costs.timing,
+        }
+    }
+
+    pub fn cell<F>(mut self, status: NodeStatus, role: Role, configure: F) -> Self
+    where
+        F: FnOnce(CellBuilder) -> CellBuilder,
+    {
+        let cell = &mut self.cells[status.index()][role.index()];
+        *cell = configure(*cell);
+        self
+    }
+
+    // Configure the same role identically for several statuses
+    pub fn cells<F>(mut self, statuses: &[NodeStatus], role: Role, configure: F) -> Self
+    where
+        F: Fn(CellBuilder) -> CellBuilder,
+    {
+        for status in statuses {
+            self = self.cell(*status, role, &configure);
+        }
+        self
+    }
+
+    pub fn build(&self) -> Result<MetricsType, MetricsBuildError> {
+        for status in NodeStatus::ALL {
+            for role in Role::ALL {
+                self.cells[status.index()][role.index()]
+                    .validate()
+                    .map_err(|reason| MetricsBuildError {
+                        status,
+                        role,
+                        reason,
+                    })?;
+            }
+        }
+        // The gateway role runs on a gateway, the other ones on constrained nodes
+        let energy = |status: NodeStatus| {
+            let cells = &self.cells[status.index()];
+            let crypto = self.crypto.as_ref();
+            EnergyConsumptionType {
+                constrained: cells[Role::Constrained.index()]
+                    .energy_state(crypto.map(|crypto| crypto.of(NodeType::Constrained))),
+                gateway: cells[Role::Gateway.index()]
+                    .energy_state(crypto.map(|crypto| crypto.of(NodeType::Gateway))),
+                left: cells[Role::Left.index()]
+                    .energy_state(crypto.map(|crypto| crypto.of(NodeType::Constrained))),
+            }
+        };
+        let communication = |status: NodeStatus| {
+            let cells = &self.cells[status.index()];
+            CommunicationOverheadType {
+                constrained: cells[Role::Constrained.index()].communication_state(),
+                gateway: cells[Role::Gateway.index()].communication_state(),
+                left: cells[Role::Left.index()].communication_state(),
+            }
+        };
+        Ok(MetricsType::new(
+            EnergyType {
+                compromised: energy(NodeStatus::Compromised),
+                leaving: energy(NodeStatus::Leaving),
+                draining: energy(NodeStatus::Draining),
+            },
+            CommunicationType {
+                compromised: communication(NodeStatus::Compromised),
+                leaving: communication(NodeStatus::Leaving),
+                draining: communication(NodeStatus::Draining),
+            },
+            self.timing,
+        ))
+    }
+}
+
+impl CellBuilder {
+    pub fn sent(self, count: u32) -> Self {
+        self.sent_to(InvolvedExchangesCount::SameAsDefined, count)
+    }
+
+    pub fn received(self, count: u32) -> Self {
+        self.received_from(InvolvedExchangesCount::SameAsDefined, count)
+    }
+
+    // Messages sent to a set of devices computed from the network; `count` is only used by
+    // SameAsDefined
+    pub fn sent_to(mut self, kind: InvolvedExchangesCount, count: u32) -> Self {
+        self.sent = count;
+        self.messages.0 = kind;
+        self
+    }
+
+    pub fn received_from(mut self, kind: InvolvedExchangesCount, count: u32) -> Self {
+        self.received = count;
+        self.messages.1 = kind;
+        self
+    }
+
+    pub fn involved_devices(mut self, kind: InvolvedDevicesCount, count: u32) -> Self {
+        self.involved_devices = kind;
+        self.number_of_involved_devices = count;
+        self
+    }
+
+    pub fn energy_cost(mut self, sent: f32, received: f32) -> Self {
+        self.energy_cost = ExchangeCostType { sent, received };
+        self
+    }
+
+    pub fn communication_cost(mut self, sent: f32, received: f32) -> Self {
+        self.communication_cost = ExchangeCostType { sent, received };
+        self
+    }
+
+    // Cryptographic operations each involved device performs for one affected node
+    pub fn operation(mut self, operation: CryptoOperation, count: u32) -> Self {
+        self.operations.set(operation, count);
+        self
+    }
+
+    fn exchanges_messages(&self) -> bool {
+        self.sent > 0
+            || self.received > 0
+            || self.messages.0 != InvolvedExchangesCount::SameAsDefined
+            || self.messages.1 != InvolvedExchangesCount::SameAsDefined
+    }
+
+    fn involves_devices(&self) -> bool {
+        match self.involved_devices {
+            InvolvedDevicesCount::Neighbors | InvolvedDevicesCount::All => true,
+            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => {
+                self.number_of_involved_devices > 0
+            }
+        }
+    }
+
+    // A cell either exchanges messages or computes between a non empty set of devices, or does
+    // nothing at all; anything in between is a description mistake that would silently cost
+    // nothing
+    fn validate(&self) -> Result<(), String> {
+        if self.exchanges_messages() && !self.involves_devices() {
+            return Err("exchanges messages but involves no devices".to_string());
+        }
+        if !self.operations.is_empty() && !self.involves_devices() {
+            return Err("performs operations but involves no devices".to_string());
+        }
+        if !self.exchanges_messages() && self.operations.is_empty() && self.involves_devices() {
+            return Err("involves devices but exchanges no messages".to_string());
+        }
+        for (name, cost) in [
+            ("energy cost", self.energy_cost),
+            ("communication cost", self.communication_cost),
+        ] {
+            for value in [cost.sent, cost.received] {
+                if !value.is_finite() || value < 0.0 {
+                    return Err(format!("{} {} is not a non-negative number", name, value));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn state(&self, exchange_cost: ExchangeCostType, computation_cost: f64) -> StateCostType {
+        StateCostType {
+            exchange: ExchangeType {
+                sent: self.sent,
+                received: self.received,
+                messages: self.messages,
+            },
+            exchange_cost,
+            number_of_involved_devices: self.number_of_involved_devices,
+            involved_devices: self.involved_devices,
+            computation_cost,
+        }
+    }
+
+    fn energy_state(&self, crypto: Option<&OperationCosts>) -> StateCostType {
+        let computation_cost = crypto.map_or(0.0, |costs| costs.energy(&self.operations));
+        self.state(self.energy_cost, computation_cost)
+    }
+
+    fn communication_state(&self) -> StateCostType {
+        self.state(self.communication_cost, 0.0)
+    }
+}
+
+impl fmt::Display for MetricsBuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} cell {}",
+            self.status.label(),
+            self.role.label(),
+            self.reason
+        )
+    }
+}
+
+impl std::error::Error for MetricsBuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::methods::{bkrsc, others};
+    use InvolvedDevicesCount as Devices;
+    use InvolvedExchangesCount as Exchanges;
+
+    const NODES: u32 = 40;
+    const MEMBERS: u32 = 3;
+    const NEIGHBORS: u32 = 5;
+
+    // A cell of the removed EnergyType::new and CommunicationType::new constructors: the
+    // exchanges, the involved devices and whether the radio of the role was free
+    type Baseline = (u32, u32, (Exchanges, Exchanges), u32, Devices, bool);
+
+    const IDLE: Baseline = (
+        0,
+        0,
+        (Exchanges::SameAsDefined, Exchanges::SameAsDefined),
+        0,
+        Devices::SameAsDefined,
+        false,
+    );
+
+    fn costs() -> CostConstants {
+        CostConstants {
+            epsb: 0.0002,
+            eprb: 0.0003,
+            sent_message_size: 24,
+            received_message_size: 32,
+            ..CostConstants::default()
+        }
+    }
+
+    fn baseline(cells: [[Baseline; 3]; 3]) -> MetricsType {
+        let costs = costs();
+        let state = |cell: Baseline, exchange_cost: ExchangeCostType| StateCostType {
+            exchange: ExchangeType {
+                sent: cell.0,
+                received: cell.1,
+                messages: cell.2,
+            },
+            exchange_cost,
+            number_of_involved_devices: cell.3,
+            involved_devices: cell.4,
+            computation_cost: 0.0,
+        };
+        let energy = |cells: [Baseline; 3]| {
+            let cost = |cell: Baseline| {
+                if cell.5 {
+                    ExchangeCostType {
+                        sent: 0.0,
+                        received: 0.0,
+                    }
+                } else {
+                    ExchangeCostType {
+                        sent: costs.epsb,
+                        received: costs.eprb,
+                    }
+                }
+            };
+            EnergyConsumptionType {
+                constrained: state(cells[0], cost(cells[0])),
+                gateway: state(cells[1], cost(cells[1])),
+                left: state(cells[2], cost(cells[2])),
+            }
+        };
+        let size = ExchangeCostType {
+            sent: costs.sent_message_size as f32,
+            received: costs.received_message_size as f32,
+        };
+        let communication = |cells: [Baseline; 3]| CommunicationOverheadType {
+            constrained: state(cells[0], size),
+            gateway: state(cells[1], size),
+            left: state(cells[2], size),
+        };
+        MetricsType::new(
+            EnergyType {
+                compromised: energy(cells[0]),
+                leaving: energy(cells[1]),
+                draining: energy(cells[2]),
+            },
+            CommunicationType {
+                compromised: communication(cells[0]),
+                leaving: communication(cells[1]),
+                draining: communication(cells[2]),
+            },
+            costs.timing,
+        )
+    }
+
+    fn rejected(configure: impl FnOnce(CellBuilder) -> CellBuilder) -> MetricsBuildError {
+        MetricsType::builder(&costs())
+            .cell(NodeStatus::Leaving, Role::Left, configure)
+            .build()
+            .unwrap_err()
+    }
+
+    fn rejection(reason: &str) -> MetricsBuildError {
+        MetricsBuildError {
+            status: NodeStatus::Leaving,
+            role: Role::Left,
+            reason: reason.to_string(),
+        }
+    }
+
+    #[test]
+    fn messages_without_devices_are_rejected() {
+        let reason = "exchanges messages but involves no devices";
+        assert_eq!(rejected(|cell| cell.sent(1)), rejection(reason));
+        assert_eq!(
+            rejected(|cell| cell
+                .received(1)
+                .involved_devices(Devices::GatewayMembers, 0)),
+            rejection(reason)
+        );
+        // Messages to a set of devices computed from the network count even without a number
+        assert_eq!(
+            rejected(|cell| cell.received_from(Exchanges::Neighbors, 0)),
+            rejection(reason)
+        );
+    }
+
+    #[test]
+    fn operations_without_devices_are_rejected() {
+        assert_eq!(
+            rejected(|cell| cell.operation(CryptoOperation::EcdsaSign, 1)),
+            rejection("performs operations but involves no devices")
+        );
+    }
+
+    #[test]
+    fn devices_doing_nothing_are_rejected() {
+        let reason = "involves devices but exchanges no messages";
+        assert_eq!(
+            rejected(|cell| cell.involved_devices(Devices::SameAsDefined, 1)),
+            rejection(reason)
+        );
+        assert_eq!(
+            rejected(|cell| cell.involved_devices(Devices::All, 0)),
+            rejection(reason)
+        );
+    }
+
+    #[test]
+    fn invalid_costs_are_rejected() {
+        let active = |cell: CellBuilder| cell.sent(1).involved_devices(Devices::SameAsDefined, 1);
+        assert_eq!(
+            rejected(|cell| active(cell).energy_cost(-0.1, 0.0)),
+            rejection("energy cost -0.1 is not a non-negative number")
+        );
+        assert_eq!(
+            rejected(|cell| active(cell).communication_cost(16.0, f32::NAN)),
+            rejection("communication cost NaN is not a non-negative number")
+        );
+        assert_eq!(
+            rejected(|cell| active(cell).energy_cost(0.0, f32::INFINITY)),
+            rejection("energy cost inf is not a non-negative number")
+        );
+    }
+
+    #[test]
+    fn valid_cells_are_accepted() {
+        let builder = MetricsType::builder(&costs());
+        assert!(builder.build().is_ok());
+        assert!(builder
+            .clone()
+            .cell(NodeStatus::Leaving, Role::Left, |cell| {
+                cell.operation(CryptoOperation::EcdsaVerify, 1)
+                    .involved_devices(Devices::Neighbors, 0)
+            })
+            .build()
+            .is_ok());
+        assert!(builder
+            .cell(NodeStatus::Draining, Role::Gateway, |cell| {
+                cell.sent(1)
+                    .involved_devices(Devices::SameAsDefined, 1)
+                    .energy_cost(0.0, 0.0)
+            })
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn first_invalid_cell_is_reported() {
+        let err = MetricsType::builder(&costs())
+            .cell(NodeStatus::Draining, Role::Constrained, |cell| cell.sent(1))
+            .cell(NodeStatus::Leaving, Role::Gateway, |cell| cell.received(1))
+            .build()
+            .unwrap_err();
+        assert_eq!((err.status, err.role), (NodeStatus::Leaving, Role::Gateway));
+    }
+
+    #[test]
+    fn bkrsc_matches_the_baseline_constructors() {
+        let member = (
+            1,
+            1,
+            (Exchanges::SameAsDefined, Exchanges::SameAsDefined),
+            MEMBERS,
+            Devices::GatewayMembers,
+            false,
+        );
+        let gateway = |received: u32, kind: Exchanges| {
+            (
+                1,
+                received,
+                (Exchanges::SameAsDefined, kind),
+                1,
+                Devices::SameAsDefined,
+                true,
+            )
+        };
+        // The baseline gave the left node of a leaving rekey one exchange for the energy and
+        // none for the communication, with no device involved either way
+        let expected = baseline([
+            [member, gateway(NODES, Exchanges::All), IDLE],
+            [member, gateway(NEIGHBORS, Exchanges::Neighbors), IDLE],
+            [member, gateway(MEMBERS, Exchanges::GatewayMembers), IDLE],
+        ]);
+        assert_eq!(
+            bkrsc::get_metrics(NODES, MEMBERS, NEIGHBORS, &costs()),
+            Ok(expected)
+        );
+    }
+
+    #[test]
+    fn others_matches_the_baseline_constructors() {
+        let same = (Exchanges::SameAsDefined, Exchanges::SameAsDefined);
+        let gateway = |received: u32| {
+            (
+                1,
+                received,
+                (Exchanges::SameAsDefined, Exchanges::All),
+                1,
+                Devices::SameAsDefined,
+                true,
+            )
+        };
+        // The baseline disagreed between its energy and communication cells on the gateway of a
+        // leaving rekey, which does nothing, and on the messages the gateway of a draining rekey
+        // receives, which exclude the draining node
+        let expected = baseline([
+            [
+                (1, 1, same, NODES, Devices::All, false),
+                gateway(NODES),
+                IDLE,
+            ],
+            [
+                (1, 1, same, NEIGHBORS, Devices::Neighbors, false),
+                IDLE,
+                (
+                    1,
+                    NEIGHBORS,
+                    (Exchanges::SameAsDefined, Exchanges::Neighbors),
+                    1,
+                    Devices::SameAsDefined,
+                    false,
+                ),
+            ],
+            [
+                (
+                    1,
+                    NODES - 1,
+                    (Exchanges::SameAsDefined, Exchanges::All),
+                    NODES,
+                    Devices::All,
+                    false,
+                ),
+                gateway(NODES - 1),
+                IDLE,
+            ],
+        ]);
+        assert_eq!(
+            others::get_metrics(NODES, MEMBERS, NEIGHBORS, &costs()),
+            Ok(expected)
+        );
+    }
+}
@@ -0,0 +1,493 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use iot_metrics_simulation::{
+    export::ExportFormat,
+    scenario::{Scenario, ScenarioError},
+    sweep::{SweepParameter, SweepValues},
+    topology::{
+        io::{FileConfig, NetworkFormat},
+        TopologyModel,
+    },
+};
+
+pub const DEFAULT_SCENARIO: &str = "scenarios/default.toml";
+
+pub const USAGE: &str = "\
+usage: iot_metrics_simulation <COMMAND> [SCENARIO] [OPTIONS]
+
+commands:
+  run        simulate one scenario
+  sweep      simulate a grid of scenario values and print one table of results
+  topology   generate the network of a scenario and export it without simulating
+  analyze    generate the network of a scenario and report its connectivity and graph metrics
+  compare    run the schemes side by side on several seeds
+  replay     regenerate the events of one iteration and print their cost for every scheme
+  lifetime   drain the batteries with rekeying events and report how long the network lasts
+  help       print this message
+
+scenario options (override the values of the scenario file):
+  --scenario PATH          scenario file, also accepted as first positional argument
+                           (default: scenarios/default.toml)
+  --seed N                 master seed
+  --iterations N
+  --threads N              worker threads (default: all cores), results do not depend on it
+  --nodes N
+  --gateways N
+  --min-neighbors N
+  --max-neighbors N
+  --gateway-members N
+  --min-affected N
+  --max-affected N
+  --epsb X
+  --eprb X
+  --sent-message-size N
+  --received-message-size N
+  --network PATH           simulate on the network of a file instead of generating one, in a
+                           format guessed from its extension (see topology options)
+  --schemes NAME,...       built-in schemes to run
+  --scheme-file PATH       load a scheme definition, may be repeated
+
+sweep options:
+  every scenario value option above except --seed accepts several values, as a comma
+  separated list or an inclusive range, and is added to the [sweep] table of the scenario:
+    --nodes 100,500,1000     a list
+    --epsb 0.00001..0.0001+0.00001
+                             a linear range, from start to end by step
+    --nodes 100..100000*10   a geometric range, from start to end by factor
+  --vary NAME=VALUES       same, with NAME a short name or a scenario path such as
+                           topology.number_of_nodes
+  --latin-hypercube N      run N Latin hypercube samples of the grid instead of all of it
+  the table of averages goes to stdout unless --aggregated is given
+
+result options (run, sweep and compare):
+  --raw PATH               write the cost of every event to PATH (\"-\" for stdout)
+  --aggregated PATH        write the averages over the iterations to PATH (\"-\" for stdout)
+  --format csv|json        format of the result files (default: guessed from the extension,
+                           csv otherwise)
+
+replay options:
+  --iteration N            iteration of the event set (required)
+  --affected N             affected node count of the event set (required)
+
+topology options:
+  --output PATH            write the network to PATH instead of stdout
+  --format FORMAT          adjacency, edge_list, dot, graphml or json (default: guessed from
+                           the extension of --output: .edges, .dot, .graphml or .json, the
+                           adjacency list otherwise)
+
+compare options:
+  --seeds N,...            seeds to compare the schemes on
+  --repetitions N          number of seeds derived from the master seed (default: 5)
+  --baseline NAME          scheme the others are tested against (default: the last one)
+  --comparison PATH        write the paired tests to PATH (\"-\" for stdout)
+
+lifetime options (override the [lifetime] table of the scenario):
+  --capacity X             initial battery of every constrained node, in joules
+  --event-interval X       hours between two rekeying events
+  --low-battery X          share of the capacity under which a node triggers a draining rekey,
+                           instead of random draining events
+  --max-events N           stop after N events";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Run(RunOptions),
+    Sweep(SweepOptions),
+    Topology(TopologyOptions),
+    Analyze(ScenarioOptions),
+    Compare(CompareOptions),
+    Replay(ReplayOptions),
+    Lifetime(LifetimeOptions),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScenarioOptions {
+    pub path: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub iterations: Option<usize>,
+    pub threads: Option<usize>,
+    pub number_of_nodes: Option<u32>,
+    pub number_of_gateways: Option<u32>,
+    pub number_of_min_possible_neighbors: Option<u32>,
+    pub number_of_max_possible_neighbors: Option<u32>,
+    pub number_of_gateway_members: Option<u32>,
+    pub min_affected_nodes: Option<usize>,
+    pub max_affected_nodes: Option<usize>,
+    pub epsb: Option<f32>,
+    pub eprb: Option<f32>,
+    pub sent_message_size: Option<u32>,
+    pub received_message_size: Option<u32>,
+    pub network: Option<PathBuf>,
+    pub schemes: Option<Vec<String>>,
+    pub scheme_files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExportOptions {
+    pub raw: Option<PathBuf>,
+    pub aggregated: Option<PathBuf>,
+    pub format: Option<ExportFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RunOptions {
+    pub scenario: ScenarioOptions,
+    pub export: ExportOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SweepOptions {
+    pub scenario: ScenarioOptions,
+    pub export: ExportOptions,
+    pub parameters: Vec<(SweepParameter, SweepValues)>,
+    pub latin_hypercube: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TopologyOptions {
+    pub scenario: ScenarioOptions,
+    pub output: Option<PathBuf>,
+    pub format: Option<NetworkFormat>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CompareOptions {
+    pub scenario: ScenarioOptions,
+    pub export: ExportOptions,
+    pub seeds: Vec<u64>,
+    pub repetitions: usize,
+    pub baseline: Option<String>,
+    pub comparison: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ReplayOptions {
+    pub scenario: ScenarioOptions,
+    pub iteration: usize,
+    pub affected: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LifetimeOptions {
+    pub scenario: ScenarioOptions,
+    pub capacity: Option<f64>,
+    pub event_interval: Option<f64>,
+    pub low_battery: Option<f64>,
+    pub max_events: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageError(pub String);
+
+// Command line flags in the order they were given, consumed by the command that uses them
+struct Flags {
+    values: Vec<(String, String)>,
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, UsageError> {
+    let mut args = args.into_iter();
+    let command = match args.next() {
+        Some(command) => command,
+        None => return Err(UsageError("missing command".to_string())),
+    };
+    if matches!(command.as_str(), "help" | "--help" | "-h") {
+        return Ok(Command::Help);
+    }
+
+    let mut flags = Flags::parse(args)?;
+    let command = match command.as_str() {
+        "run" => Command::Run(RunOptions {
+            export: ExportOptions::take(&mut flags)?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        "sweep" => Command::Sweep(SweepOptions {
+            parameters: take_sweep_parameters(&mut flags)?,
+            latin_hypercube: flags.take_value("--latin-hypercube")?,
+            export: ExportOptions::take(&mut flags)?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        "topology" => Command::Topology(TopologyOptions {
+            output: flags.take("--output").map(PathBuf::from),
+            format: flags.take_value("--format")?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        "analyze" => Command::Analyze(ScenarioOptions::take(&mut flags)?),
+        "compare" => Command::Compare(CompareOptions {
+            seeds: flags.take_list("--seeds")?,
+            repetitions: flags.take_value("--repetitions")?.unwrap_or(5),
+            baseline: flags.take("--baseline"),
+            comparison: flags.take("--comparison").map(PathBuf::from),
+            export: ExportOptions::take(&mut flags)?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        "replay" => Command::Replay(ReplayOptions {
+            iteration: flags.take_required("--iteration")?,
+            affected: flags.take_required("--affected")?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        "lifetime" => Command::Lifetime(LifetimeOptions {
+            capacity: flags.take_value("--capacity")?,
+            event_interval: flags.take_value("--event-interval")?,
+            low_battery: flags.take_value("--low-battery")?,
+            max_events: flags.take_value("--max-events")?,
+            scenario: ScenarioOptions::take(&mut flags)?,
+        }),
+        _ => return Err(UsageError(format!("unknown command \"{}\"", command))),
+    };
+    flags.finish()?;
+    if let Command::Compare(options) = &command {
+        if options.repetitions == 0 {
+            return Err(UsageError(
+                "--repetitions must be greater than 0".to_string(),
+            ));
+        }
+    }
+    Ok(command)
+}
+
+impl Flags {
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, UsageError> {
+        let mut values = vec![];
+        while let Some(arg) = args.next() {
+            if let Some(flag) = arg.strip_prefix("--") {
+                let (name, value) = match flag.split_once('=') {
+                    Some((name, value)) => (format!("--{}", name), value.to_string()),
+                    None => match args.next() {
+                        Some(value) => (arg.clone(), value),
+                        None => return Err(UsageError(format!("{} expects a value", arg))),
+                    },
+                };
+                values.push((name, value));
+            } else if arg.starts_with('-') {
+                return Err(UsageError(format!("unknown option {}", arg)));
+            } else {
+                // A bare argument is the scenario path
+                values.push(("--scenario".to_string(), arg));
+            }
+        }
+        Ok(Self { values })
+    }
+
+    // Last occurrence wins, like most command line tools
+    fn take(&mut self, name: &str) -> Option<String> {
+        let mut taken = None;
+        self.values.retain(|(flag, value)| {
+            if flag == name {
+                taken = Some(value.clone());
+                false
+            } else {
+                true
+            }
+        });
+        taken
+    }
+
+    fn take_all(&mut self, name: &str) -> Vec<String> {
+        let mut taken = vec![];
+        self.values.retain(|(flag, value)| {
+            if flag == name {
+                taken.push(value.clone());
+                false
+            } else {
+                true
+            }
+        });
+        taken
+    }
+
+    fn take_value<T: FromStr>(&mut self, name: &str) -> Result<Option<T>, UsageError> {
+        match self.take(name) {
+            Some(value) => parse_value(name, &value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn take_required<T: FromStr>(&mut self, name: &str) -> Result<T, UsageError> {
+        self.take_value(name)?
+            .ok_or_else(|| UsageError(format!("missing required option {}", name)))
+    }
+
+    fn take_list<T: FromStr>(&mut self, name: &str) -> Result<Vec<T>, UsageError> {
+        let mut list = vec![];
+        for value in self.take_all(name) {
+            for item in value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+            {
+                list.push(parse_value(name, item)?);
+            }
+        }
+        Ok(list)
+    }
+
+    fn finish(self) -> Result<(), UsageError> {
+        match self.values.first() {
+            Some((flag, _)) if flag == "--scenario" => {
+                Err(UsageError("only one scenario can be given".to_string()))
+            }
+            Some((flag, _)) => Err(UsageError(format!("unknown option {}", flag))),
+            None => Ok(()),
+        }
+    }
+}
+
+// Sweep values given either as `--nodes VALUES` or `--vary nodes=VALUES`
+fn take_sweep_parameters(
+    flags: &mut Flags,
+) -> Result<Vec<(SweepParameter, SweepValues)>, UsageError> {
+    let mut parameters = vec![];
+    for parameter in SweepParameter::ALL {
+        let flag = format!("--{}", parameter.name());
+        for value in flags.take_all(&flag) {
+            parameters.push((parameter, parse_value(&flag, &value)?));
+        }
+    }
+    for variation in flags.take_all("--vary") {
+        let (name, values) = variation.split_once('=').ok_or_else(|| {
+            UsageError(format!("--vary expects NAME=VALUES, got \"{}\"", variation))
+        })?;
+        let parameter = SweepParameter::from_name(name.trim())
+            .ok_or_else(|| UsageError(format!("unknown sweep parameter \"{}\"", name.trim())))?;
+        parameters.push((parameter, parse_value("--vary", values)?));
+    }
+    Ok(parameters)
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, UsageError> {
+    value
+        .parse::<T>()
+        .map_err(|_| UsageError(format!("invalid value \"{}\" for {}", value, name)))
+}
+
+impl ScenarioOptions {
+    fn take(flags: &mut Flags) -> Result<Self, UsageError> {
+        let mut scenarios = flags.take_all("--scenario");
+        if scenarios.len() > 1 {
+            return Err(UsageError("only one scenario can be given".to_string()));
+        }
+        Ok(Self {
+            path: scenarios.pop().map(PathBuf::from),
+            seed: flags.take_value("--seed")?,
+            iterations: flags.take_value("--iterations")?,
+            threads: flags.take_value("--threads")?,
+            number_of_nodes: flags.take_value("--nodes")?,
+            number_of_gateways: flags.take_value("--gateways")?,
+            number_of_min_possible_neighbors: flags.take_value("--min-neighbors")?,
+            number_of_max_possible_neighbors: flags.take_value("--max-neighbors")?,
+            number_of_gateway_members: flags.take_value("--gateway-members")?,
+            min_affected_nodes: flags.take_value("--min-affected")?,
+            max_affected_nodes: flags.take_value("--max-affected")?,
+            epsb: flags.take_value("--epsb")?,
+            eprb: flags.take_value("--eprb")?,
+            sent_message_size: flags.take_value("--sent-message-size")?,
+            received_message_size: flags.take_value("--received-message-size")?,
+            network: flags.take("--network").map(PathBuf::from),
+            schemes: match flags.take_all("--schemes") {
+                schemes if schemes.is_empty() => None,
+                schemes => Some(
+                    schemes
+                        .iter()
+                        .flat_map(|value| value.split(','))
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                ),
+            },
+            scheme_files: flags
+                .take_all("--scheme-file")
+                .into_iter()
+                .map(PathBuf::from)
+                .collect(),
+        })
+    }
+
+    // Read the scenario file and apply the command line values on top of it, without validating
+    pub fn load(&self) -> Result<Scenario, ScenarioError> {
+        let path = self
+            .path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_SCENARIO));
+        let mut scenario = Scenario::read(path)?;
+        let topology = &mut scenario.topology;
+        override_value(&mut topology.number_of_nodes, self.number_of_nodes);
+        override_value(&mut topology.number_of_gateways, self.number_of_gateways);
+        override_value(
+            &mut topology.number_of_min_possible_neighbors,
+            self.number_of_min_possible_neighbors,
+        );
+        override_value(
+            &mut topology.number_of_max_possible_neighbors,
+            self.number_of_max_possible_neighbors,
+        );
+        override_value(
+            &mut topology.number_of_gateway_members,
+            self.number_of_gateway_members,
+        );
+        if let Some(path) = &self.network {
+            topology.model = TopologyModel::File;
+            topology.file = Some(FileConfig {
+                path: path.clone(),
+                format: None,
+            });
+        }
+        let costs = &mut scenario.costs;
+        override_value(&mut costs.epsb, self.epsb);
+        override_value(&mut costs.eprb, self.eprb);
+        override_value(&mut costs.sent_message_size, self.sent_message_size);
+        override_value(&mut costs.received_message_size, self.received_message_size);
+        let simulation = &mut scenario.simulation;
+        if self.seed.is_some() {
+            simulation.seed = self.seed;
+        }
+        override_value(&mut simulation.iterations, self.iterations);
+        if self.threads.is_some() {
+            simulation.threads = self.threads;
+        }
+        override_value(&mut simulation.min_affected_nodes, self.min_affected_nodes);
+        override_value(&mut simulation.max_affected_nodes, self.max_affected_nodes);
+        if let Some(schemes) = &self.schemes {
+            scenario.schemes = schemes.clone();
+        }
+        scenario
+            .scheme_files
+            .extend(self.scheme_files.iter().cloned());
+        Ok(scenario)
+    }
+}
+
+impl ExportOptions {
+    fn take(flags: &mut Flags) -> Result<Self, UsageError> {
+        Ok(Self {
+            raw: flags.take("--raw").map(PathBuf::from),
+            aggregated: flags.take("--aggregated").map(PathBuf::from),
+            format: flags.take_value("--format")?,
+        })
+    }
+
+    // Whether a result file replaces the text report on stdout
+    pub fn writes_to_stdout(&self) -> bool {
+        [&self.raw, &self.aggregated]
+            .iter()
+            .any(|path| path.as_deref() == Some(Path::new("-")))
+    }
+}
+
+fn override_value<T: Copy>(value: &mut T, with: Option<T>) {
+    if let Some(with) = with {
+        *value = with;
+    }
+}
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for UsageError {}
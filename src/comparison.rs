@@ -0,0 +1,351 @@
+use std::cmp::Ordering;
+
+use serde::Serialize;
+
+use crate::{
+    simulation::SimulationResults,
+    stats::{erfc, regularized_incomplete_beta, StreamingStats},
+    NodeStatus,
+};
+
+// Paired comparison of a candidate scheme against a baseline on the same events. Differences are
+// candidate - baseline, so a negative difference and a positive saving mean the candidate is
+// cheaper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairedTest {
+    pub pairs: usize,
+    pub baseline_mean: f64,
+    pub candidate_mean: f64,
+    pub mean_difference: f64,
+    pub saving_percent: f64,
+    pub t_statistic: f64,
+    pub t_test_p: f64,
+    pub wilcoxon_p: f64,
+    // Cohen's d_z: mean difference over the standard deviation of the differences
+    pub effect_size: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemeComparison {
+    pub baseline: String,
+    pub candidate: String,
+    pub status: NodeStatus,
+    pub affected: usize,
+    pub energy: PairedTest,
+    pub computation: PairedTest,
+    pub communication: PairedTest,
+    pub latency: PairedTest,
+    pub relay: PairedTest,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparisonRecord {
+    pub baseline: String,
+    pub candidate: String,
+    pub status: &'static str,
+    pub affected: usize,
+    pub metric: &'static str,
+    pub pairs: usize,
+    pub baseline_mean: f64,
+    pub candidate_mean: f64,
+    pub mean_difference: f64,
+    // Empty when the baseline costs nothing
+    pub saving_percent: Option<f64>,
+    pub t_statistic: f64,
+    pub t_test_p: f64,
+    pub wilcoxon_p: f64,
+    pub effect_size: f64,
+}
+
+impl PairedTest {
+    pub fn new(baseline: &[f64], candidate: &[f64]) -> Self {
+        assert_eq!(
+            baseline.len(),
+            candidate.len(),
+            "paired samples must have the same length"
+        );
+        let differences: Vec<f64> = candidate
+            .iter()
+            .zip(baseline)
+            .map(|(candidate, baseline)| candidate - baseline)
+            .collect();
+        let baseline_mean = baseline.iter().copied().collect::<StreamingStats>().mean();
+        let candidate_mean = candidate.iter().copied().collect::<StreamingStats>().mean();
+        let difference_stats = differences.iter().copied().collect::<StreamingStats>();
+        let mean_difference = difference_stats.mean();
+        let std_dev = difference_stats.std_dev();
+
+        let pairs = differences.len();
+        let (t_statistic, t_test_p, effect_size) = if pairs < 2 {
+            (f64::NAN, f64::NAN, f64::NAN)
+        } else if std_dev == 0.0 {
+            // Every pair differs by the same amount: either no difference at all or a certain one
+            if mean_difference == 0.0 {
+                (0.0, 1.0, 0.0)
+            } else {
+                let infinity = f64::INFINITY.copysign(mean_difference);
+                (infinity, 0.0, infinity)
+            }
+        } else {
+            let t = mean_difference / (std_dev / (pairs as f64).sqrt());
+            // Both tails of the t distribution at once, without the cancellation of 1 - cdf
+            let degrees_of_freedom = (pairs - 1) as f64;
+            let p = regularized_incomplete_beta(
+                degrees_of_freedom / (degrees_of_freedom + t * t),
+                degrees_of_freedom / 2.0,
+                0.5,
+            );
+            (t, p.clamp(0.0, 1.0), mean_difference / std_dev)
+        };
+
+        Self {
+            pairs,
+            baseline_mean,
+            candidate_mean,
+            mean_difference,
+            saving_percent: saving_percent(baseline_mean, candidate_mean),
+            t_statistic,
+            t_test_p,
+            wilcoxon_p: wilcoxon_signed_rank_p(&differences),
+            effect_size,
+        }
+    }
+
+    // Neither scheme spends anything on the metric
+    pub fn is_idle(&self) -> bool {
+        self.baseline_mean == 0.0 && self.candidate_mean == 0.0
+    }
+}
+
+// Share of the baseline cost saved by the candidate: zero when both cost the same, e.g. nothing,
+// and NaN when only the baseline costs nothing
+fn saving_percent(baseline_mean: f64, candidate_mean: f64) -> f64 {
+    if baseline_mean == candidate_mean {
+        0.0
+    } else if baseline_mean == 0.0 {
+        f64::NAN
+    } else {
+        (baseline_mean - candidate_mean) / baseline_mean * 100.0
+    }
+}
+
+// Two sided p-value of the Wilcoxon signed-rank test, using the normal approximation with tie and
+// continuity corrections. Zero differences are dropped (Wilcoxon's method).
+pub fn wilcoxon_signed_rank_p(differences: &[f64]) -> f64 {
+    let mut nonzero: Vec<f64> = differences
+        .iter()
+        .copied()
+        .filter(|difference| *difference != 0.0)
+        .collect();
+    if nonzero.is_empty() {
+        return 1.0;
+    }
+    nonzero.sort_by(|a, b| a.abs().partial_cmp(&b.abs()).unwrap_or(Ordering::Equal));
+
+    let n = nonzero.len() as f64;
+    let mut positive_rank_sum = 0.0;
+    let mut tie_correction = 0.0;
+    let mut start = 0;
+    while start < nonzero.len() {
+        let mut end = start + 1;
+        while end < nonzero.len() && nonzero[end].abs() == nonzero[start].abs() {
+            end += 1;
+        }
+        // Ranks are 1 based, tied values share the average of their ranks
+        let rank = (start + 1 + end) as f64 / 2.0;
+        for difference in &nonzero[start..end] {
+            if *difference > 0.0 {
+                positive_rank_sum += rank;
+            }
+        }
+        let ties = (end - start) as f64;
+        tie_correction += ties * ties * ties - ties;
+        start = end;
+    }
+
+    let expected = n * (n + 1.0) / 4.0;
+    let variance = n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - tie_correction / 48.0;
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    let deviation = (positive_rank_sum - expected).abs() - 0.5;
+    let z = deviation.max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).clamp(0.0, 1.0)
+}
+
+// Compare a candidate scheme against a baseline for every status and affected node count. The
+// pairs are the iterations of each run, which share their network and events between schemes;
+// several runs (e.g. seeds) of the same scenario are pooled.
+pub fn compare_schemes(
+    runs: &[SimulationResults],
+    baseline: usize,
+    candidate: usize,
+) -> Vec<SchemeComparison> {
+    let first = match runs.first() {
+        Some(first) => first,
+        None => return vec![],
+    };
+    let mut comparisons = vec![];
+    for status in NodeStatus::ALL {
+        for affected in first.affected_counts() {
+            let mut samples: [Vec<f64>; 10] = Default::default();
+            for results in runs {
+                let pairs = results
+                    .samples(baseline, status, affected)
+                    .iter()
+                    .zip(results.samples(candidate, status, affected));
+                for (baseline_cost, candidate_cost) in pairs {
+                    samples[0].push(baseline_cost.energy as f64);
+                    samples[1].push(candidate_cost.energy as f64);
+                    samples[2].push(baseline_cost.computation as f64);
+                    samples[3].push(candidate_cost.computation as f64);
+                    samples[4].push(baseline_cost.communication as f64);
+                    samples[5].push(candidate_cost.communication as f64);
+                    samples[6].push(baseline_cost.latency as f64);
+                    samples[7].push(candidate_cost.latency as f64);
+                    samples[8].push(baseline_cost.relay as f64);
+                    samples[9].push(candidate_cost.relay as f64);
+                }
+            }
+            comparisons.push(SchemeComparison {
+                baseline: first.schemes[baseline].clone(),
+                candidate: first.schemes[candidate].clone(),
+                status,
+                affected,
+                energy: PairedTest::new(&samples[0], &samples[1]),
+                computation: PairedTest::new(&samples[2], &samples[3]),
+                communication: PairedTest::new(&samples[4], &samples[5]),
+                latency: PairedTest::new(&samples[6], &samples[7]),
+                relay: PairedTest::new(&samples[8], &samples[9]),
+            });
+        }
+    }
+    comparisons
+}
+
+impl SchemeComparison {
+    // One record per metric, leaving out the metrics that cost nothing in both schemes, e.g. the
+    // computation without [costs.crypto] or the relay without routing
+    pub fn records(&self) -> Vec<ComparisonRecord> {
+        let record = |metric, test: PairedTest| ComparisonRecord {
+            baseline: self.baseline.clone(),
+            candidate: self.candidate.clone(),
+            status: self.status.label(),
+            affected: self.affected,
+            metric,
+            pairs: test.pairs,
+            baseline_mean: test.baseline_mean,
+            candidate_mean: test.candidate_mean,
+            mean_difference: test.mean_difference,
+            saving_percent: Some(test.saving_percent).filter(|saving| saving.is_finite()),
+            t_statistic: test.t_statistic,
+            t_test_p: test.t_test_p,
+            wilcoxon_p: test.wilcoxon_p,
+            effect_size: test.effect_size,
+        };
+        [
+            ("energy", self.energy),
+            ("computation", self.computation),
+            ("communication", self.communication),
+            ("latency", self.latency),
+            ("relay", self.relay),
+        ]
+        .into_iter()
+        .filter(|(_, test)| !test.is_idle())
+        .map(|(metric, test)| record(metric, test))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn wilcoxon_matches_the_textbook_example() {
+        // The ten pairs of the Wikipedia example: one zero difference is dropped, two differences
+        // of 5 share the ranks 1 and 2, W+ = 27 and W- = 18 over nine pairs
+        let before = [
+            125.0, 115.0, 130.0, 140.0, 140.0, 115.0, 140.0, 125.0, 140.0, 135.0,
+        ];
+        let after = [
+            110.0, 122.0, 125.0, 120.0, 140.0, 124.0, 123.0, 137.0, 135.0, 145.0,
+        ];
+        let differences: Vec<f64> = before.iter().zip(after).map(|(x, y)| x - y).collect();
+        // z = (|27 - 22.5| - 0.5) / sqrt(71.25 - 6 / 48)
+        assert_close(wilcoxon_signed_rank_p(&differences), 0.635_289_3, 1e-6);
+        let mirrored: Vec<f64> = differences.iter().map(|difference| -difference).collect();
+        assert_close(wilcoxon_signed_rank_p(&mirrored), 0.635_289_3, 1e-6);
+        assert_eq!(wilcoxon_signed_rank_p(&[0.0, 0.0]), 1.0);
+    }
+
+    #[test]
+    fn paired_test_gives_the_t_test_and_cohens_dz() {
+        let baseline = [10.0, 12.0, 14.0, 16.0];
+        let candidate = [9.0, 10.0, 13.0, 13.0];
+        let test = PairedTest::new(&baseline, &candidate);
+        assert_eq!(test.pairs, 4);
+        assert_close(test.baseline_mean, 13.0, 1e-12);
+        assert_close(test.candidate_mean, 11.25, 1e-12);
+        assert_close(test.mean_difference, -1.75, 1e-12);
+        assert_close(test.saving_percent, 1.75 / 13.0 * 100.0, 1e-9);
+        // Differences -1, -2, -1, -3 with a standard deviation of sqrt(2.75 / 3)
+        assert_close(test.effect_size, -1.827_815_387_534_827, 1e-12);
+        assert_close(test.t_statistic, -3.655_630_775_069_655, 1e-12);
+        assert_close(test.t_test_p, 0.035_352_847_002_517_39, 1e-9);
+    }
+
+    #[test]
+    fn paired_test_handles_constant_differences() {
+        let same = PairedTest::new(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]);
+        assert_eq!(
+            (
+                same.t_statistic,
+                same.t_test_p,
+                same.effect_size,
+                same.wilcoxon_p
+            ),
+            (0.0, 1.0, 0.0, 1.0)
+        );
+        let cheaper = PairedTest::new(&[2.0, 3.0, 4.0], &[1.0, 2.0, 3.0]);
+        assert_eq!(cheaper.t_statistic, f64::NEG_INFINITY);
+        assert_eq!(cheaper.effect_size, f64::NEG_INFINITY);
+        assert_eq!(cheaper.t_test_p, 0.0);
+        let single = PairedTest::new(&[2.0], &[1.0]);
+        assert!(single.t_statistic.is_nan() && single.t_test_p.is_nan());
+    }
+
+    #[test]
+    fn metrics_without_cost_are_left_out_of_the_records() {
+        let test = |baseline: &[f64], candidate: &[f64]| PairedTest::new(baseline, candidate);
+        let comparison = SchemeComparison {
+            baseline: "others".to_string(),
+            candidate: "bkrsc".to_string(),
+            status: NodeStatus::Compromised,
+            affected: 1,
+            energy: test(&[4.0, 6.0], &[1.0, 2.0]),
+            computation: test(&[0.0, 0.0], &[0.0, 0.0]),
+            communication: test(&[0.0, 0.0], &[1.0, 3.0]),
+            latency: test(&[2.0, 2.0], &[2.0, 2.0]),
+            relay: test(&[0.0, 0.0], &[0.0, 0.0]),
+        };
+        assert_eq!(comparison.computation.saving_percent, 0.0);
+        assert!(comparison.communication.saving_percent.is_nan());
+        let records = comparison.records();
+        let metrics: Vec<_> = records.iter().map(|record| record.metric).collect();
+        assert_eq!(metrics, ["energy", "communication", "latency"]);
+        assert_eq!(records[0].saving_percent, Some(70.0));
+        assert_eq!(records[1].saving_percent, None);
+        assert_eq!(records[2].saving_percent, Some(0.0));
+    }
+}
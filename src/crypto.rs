@@ -0,0 +1,232 @@
+use serde::Deserialize;
+
+use crate::{
+    InvolvedDevicesCount, MetricsType, Node, NodeStatus, NodeType, NodesVec, StateCostType,
+};
+
+// Cryptographic work a device performs during a rekeying, besides sending and receiving
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CryptoOperation {
+    // One 16 byte block encrypted or decrypted
+    Aes128Block,
+    // One 64 byte block hashed
+    Sha256Block,
+    EcdhP256,
+    EcdsaSign,
+    EcdsaVerify,
+    // A P-256 key pair
+    KeyGeneration,
+}
+
+// Operations a device of a cell performs for one affected node, by operation
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Operations {
+    counts: [u32; CryptoOperation::ALL.len()],
+}
+
+// Energy of one operation in joules on a class of device. Every operation of a table must be
+// given.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OperationCosts {
+    pub aes128_block: f64,
+    pub sha256_block: f64,
+    pub ecdh_p256: f64,
+    pub ecdsa_sign: f64,
+    pub ecdsa_verify: f64,
+    pub key_generation: f64,
+}
+
+// Computation costs of the constrained nodes and of the gateways, set in [costs.crypto]. Without
+// this table the computations cost nothing.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CryptoCosts {
+    #[serde(default = "OperationCosts::cortex_m3")]
+    pub constrained: OperationCosts,
+    #[serde(default = "OperationCosts::gateway_cpu")]
+    pub gateway: OperationCosts,
+}
+
+impl CryptoOperation {
+    pub const ALL: [CryptoOperation; 6] = [
+        CryptoOperation::Aes128Block,
+        CryptoOperation::Sha256Block,
+        CryptoOperation::EcdhP256,
+        CryptoOperation::EcdsaSign,
+        CryptoOperation::EcdsaVerify,
+        CryptoOperation::KeyGeneration,
+    ];
+
+    pub fn index(&self) -> usize {
+        match self {
+            CryptoOperation::Aes128Block => 0,
+            CryptoOperation::Sha256Block => 1,
+            CryptoOperation::EcdhP256 => 2,
+            CryptoOperation::EcdsaSign => 3,
+            CryptoOperation::EcdsaVerify => 4,
+            CryptoOperation::KeyGeneration => 5,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CryptoOperation::Aes128Block => "aes128_block",
+            CryptoOperation::Sha256Block => "sha256_block",
+            CryptoOperation::EcdhP256 => "ecdh_p256",
+            CryptoOperation::EcdsaSign => "ecdsa_sign",
+            CryptoOperation::EcdsaVerify => "ecdsa_verify",
+            CryptoOperation::KeyGeneration => "key_generation",
+        }
+    }
+}
+
+impl Operations {
+    pub fn count(&self, operation: CryptoOperation) -> u32 {
+        self.counts[operation.index()]
+    }
+
+    pub fn set(&mut self, operation: CryptoOperation, count: u32) -> &mut Self {
+        self.counts[operation.index()] = count;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.iter().all(|count| *count == 0)
+    }
+}
+
+impl OperationCosts {
+    // Software implementations on a Cortex-M3 at 72 MHz drawing about 30 mW
+    pub fn cortex_m3() -> Self {
+        Self {
+            aes128_block: 0.7e-6,
+            sha256_block: 1.6e-6,
+            ecdh_p256: 1.7e-3,
+            ecdsa_sign: 1.8e-3,
+            ecdsa_verify: 2.0e-3,
+            key_generation: 1.5e-3,
+        }
+    }
+
+    // Gateway-class application processor with cryptographic extensions drawing about 1 W
+    pub fn gateway_cpu() -> Self {
+        Self {
+            aes128_block: 2e-8,
+            sha256_block: 2e-7,
+            ecdh_p256: 1.5e-4,
+            ecdsa_sign: 5e-5,
+            ecdsa_verify: 1.5e-4,
+            key_generation: 4e-5,
+        }
+    }
+
+    pub fn cost(&self, operation: CryptoOperation) -> f64 {
+        match operation {
+            CryptoOperation::Aes128Block => self.aes128_block,
+            CryptoOperation::Sha256Block => self.sha256_block,
+            CryptoOperation::EcdhP256 => self.ecdh_p256,
+            CryptoOperation::EcdsaSign => self.ecdsa_sign,
+            CryptoOperation::EcdsaVerify => self.ecdsa_verify,
+            CryptoOperation::KeyGeneration => self.key_generation,
+        }
+    }
+
+    // Energy of a set of operations
+    pub fn energy(&self, operations: &Operations) -> f64 {
+        CryptoOperation::ALL
+            .iter()
+            .map(|operation| operations.count(*operation) as f64 * self.cost(*operation))
+            .sum()
+    }
+}
+
+impl Default for CryptoCosts {
+    fn default() -> Self {
+        Self {
+            constrained: OperationCosts::cortex_m3(),
+            gateway: OperationCosts::gateway_cpu(),
+        }
+    }
+}
+
+impl CryptoCosts {
+    pub fn of(&self, kind: NodeType) -> &OperationCosts {
+        match kind {
+            NodeType::Gateway => &self.gateway,
+            NodeType::Constrained => &self.constrained,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (class, costs) in [("constrained", self.constrained), ("gateway", self.gateway)] {
+            for operation in CryptoOperation::ALL {
+                let value = costs.cost(operation);
+                if !value.is_finite() || value < 0.0 {
+                    return Err(format!(
+                        "{}.{} = {} is not a non-negative number",
+                        class,
+                        operation.label(),
+                        value
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Energy of the cryptographic operations of a rekeying once the nodes flagged with `status` are
+// removed. Every affected constrained node brings the operations of each role of the scheme,
+// performed by as many devices as the role involves in the energy cost of its messages.
+pub fn computation_cost(nodes: &NodesVec, status: NodeStatus, metrics: &MetricsType) -> f32 {
+    let cells = match status {
+        NodeStatus::Compromised => metrics.energy.compromised,
+        NodeStatus::Leaving => metrics.energy.leaving,
+        NodeStatus::Draining => metrics.energy.draining,
+    };
+    let total: f64 = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained && node.has_only_status(status))
+        .flat_map(|node| {
+            [cells.constrained, cells.gateway, cells.left]
+                .map(|state| involved_devices(nodes, node, status, &state) * state.computation_cost)
+        })
+        .sum();
+    total as f32
+}
+
+fn involved_devices(
+    nodes: &NodesVec,
+    node: &Node,
+    status: NodeStatus,
+    state: &StateCostType,
+) -> f64 {
+    if state.computation_cost == 0.0 {
+        return 0.0;
+    }
+    let flagged = |node: &Node| match status {
+        NodeStatus::Compromised => node.is_compromised,
+        NodeStatus::Leaving => node.is_leaving,
+        NodeStatus::Draining => node.is_draining,
+    };
+    let devices = match state.involved_devices {
+        InvolvedDevicesCount::All => nodes.len(),
+        InvolvedDevicesCount::SameAsDefined => state.number_of_involved_devices as usize,
+        // The members of the clusters of the affected node
+        InvolvedDevicesCount::GatewayMembers => nodes
+            .involved_devices(
+                node,
+                state.involved_devices,
+                state.number_of_involved_devices,
+            )
+            .len(),
+        // Each flagged node and its neighbors
+        InvolvedDevicesCount::Neighbors => nodes
+            .iter()
+            .filter(|node| node.kind == NodeType::Constrained && flagged(node))
+            .map(|node| 1 + nodes.degree(node.id))
+            .sum(),
+    };
+    devices as f64
+}
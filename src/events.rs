@@ -0,0 +1,74 @@
+use rand::seq::index;
+
+use crate::{
+    rng::{derive_rng, EVENTS_STREAM},
+    NodeStatus, NodesVec,
+};
+
+// The nodes hit by each kind of event for one iteration and affected node count. An event set
+// only depends on the master seed, the iteration, the affected count and the network size, so
+// every scheme is evaluated on exactly the same draws and any of them can be regenerated later
+// without running the iterations before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventSet {
+    pub iteration: usize,
+    pub affected: usize,
+    nodes: [Vec<usize>; 3],
+}
+
+impl EventSet {
+    pub fn generate(number_of_nodes: usize, seed: u64, iteration: usize, affected: usize) -> Self {
+        let mut rng = derive_rng(seed, &[EVENTS_STREAM, iteration as u64, affected as u64]);
+        let affected_by_status = affected.min(number_of_nodes);
+        let nodes = NodeStatus::ALL
+            .map(|_| index::sample(&mut rng, number_of_nodes, affected_by_status).into_vec());
+        Self {
+            iteration,
+            affected,
+            nodes,
+        }
+    }
+
+    pub fn nodes(&self, status: NodeStatus) -> &[usize] {
+        &self.nodes[status.index()]
+    }
+
+    // Mark the nodes of one status on a clean network
+    pub fn apply(&self, status: NodeStatus, nodes: &mut NodesVec) {
+        nodes.reset();
+        nodes.affect_node_ids(status, self.nodes(status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_only_depend_on_seed_iteration_affected_and_size() {
+        let set = EventSet::generate(50, 11, 3, 4);
+        // Other sets generated before do not change the draws of this one
+        for iteration in 0..3 {
+            EventSet::generate(50, 11, iteration, 4);
+        }
+        assert_eq!(EventSet::generate(50, 11, 3, 4), set);
+        for status in NodeStatus::ALL {
+            let mut nodes = set.nodes(status).to_vec();
+            assert_eq!(nodes.len(), 4);
+            nodes.sort_unstable();
+            nodes.dedup();
+            assert_eq!(nodes.len(), 4);
+            assert!(nodes.iter().all(|id| *id < 50));
+        }
+        for other in [
+            EventSet::generate(50, 12, 3, 4),
+            EventSet::generate(50, 11, 4, 4),
+            EventSet::generate(51, 11, 3, 4),
+        ] {
+            assert_ne!(other.nodes, set.nodes);
+        }
+        // More affected nodes than the network has
+        let all = EventSet::generate(5, 11, 0, 8);
+        assert_eq!(all.nodes(NodeStatus::Leaving).len(), 5);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::Serialize;
+
+use crate::{scenario::Scenario, simulation::SimulationResults, NodeStatus};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+// The cost of one event: one scheme, status, affected node count and iteration. Every record
+// carries the seed, topology, routing and costs it was produced with so files from several runs
+// can be concatenated without losing track of where a row comes from.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RawRecord {
+    pub scheme: String,
+    pub status: &'static str,
+    pub affected: usize,
+    pub iteration: usize,
+    pub energy: f32,
+    pub computation: f32,
+    pub communication: f32,
+    pub latency: f32,
+    pub relay: f32,
+    pub seed: u64,
+    pub number_of_nodes: u32,
+    pub number_of_gateways: u32,
+    pub number_of_min_possible_neighbors: u32,
+    pub number_of_max_possible_neighbors: u32,
+    pub number_of_gateway_members: u32,
+    pub topology_model: &'static str,
+    pub cluster_strategy: &'static str,
+    pub routing_strategy: &'static str,
+    pub radio_model: &'static str,
+    pub epsb: f32,
+    pub eprb: f32,
+    pub sent_message_size: u32,
+    pub received_message_size: u32,
+}
+
+// Statistics over all iterations of one scheme, status and affected node count. `energy`,
+// `computation`, `communication`, `latency` and `relay` are the means, the 95% confidence interval
+// is the one of the mean.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AggregatedRecord {
+    pub scheme: String,
+    pub status: &'static str,
+    pub affected: usize,
+    pub iterations: usize,
+    pub energy: f64,
+    pub energy_std_dev: f64,
+    pub energy_min: f64,
+    pub energy_max: f64,
+    pub energy_median: f64,
+    pub energy_p5: f64,
+    pub energy_p95: f64,
+    pub energy_ci_low: f64,
+    pub energy_ci_high: f64,
+    pub computation: f64,
+    pub computation_std_dev: f64,
+    pub computation_min: f64,
+    pub computation_max: f64,
+    pub computation_median: f64,
+    pub computation_p5: f64,
+    pub computation_p95: f64,
+    pub computation_ci_low: f64,
+    pub computation_ci_high: f64,
+    pub communication: f64,
+    pub communication_std_dev: f64,
+    pub communication_min: f64,
+    pub communication_max: f64,
+    pub communication_median: f64,
+    pub communication_p5: f64,
+    pub communication_p95: f64,
+    pub communication_ci_low: f64,
+    pub communication_ci_high: f64,
+    pub latency: f64,
+    pub latency_std_dev: f64,
+    pub latency_min: f64,
+    pub latency_max: f64,
+    pub latency_median: f64,
+    pub latency_p5: f64,
+    pub latency_p95: f64,
+    pub latency_ci_low: f64,
+    pub latency_ci_high: f64,
+    pub relay: f64,
+    pub relay_std_dev: f64,
+    pub relay_min: f64,
+    pub relay_max: f64,
+    pub relay_median: f64,
+    pub relay_p5: f64,
+    pub relay_p95: f64,
+    pub relay_ci_low: f64,
+    pub relay_ci_high: f64,
+    pub seed: u64,
+    pub number_of_nodes: u32,
+    pub number_of_gateways: u32,
+    pub number_of_min_possible_neighbors: u32,
+    pub number_of_max_possible_neighbors: u32,
+    pub number_of_gateway_members: u32,
+    pub topology_model: &'static str,
+    pub cluster_strategy: &'static str,
+    pub routing_strategy: &'static str,
+    pub radio_model: &'static str,
+    pub epsb: f32,
+    pub eprb: f32,
+    pub sent_message_size: u32,
+    pub received_message_size: u32,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Csv {
+        path: PathBuf,
+        source: csv::Error,
+    },
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+impl ExportFormat {
+    // Guess the format from the file extension, defaulting to CSV
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!(
+                "unknown format \"{}\" (expected csv or json)",
+                value
+            )),
+        }
+    }
+}
+
+pub fn raw_records(results: &SimulationResults, scenario: &Scenario) -> Vec<RawRecord> {
+    let (topology, costs) = (&scenario.topology, &scenario.costs);
+    let mut records = vec![];
+    for (scheme_index, scheme) in results.schemes.iter().enumerate() {
+        for status in NodeStatus::ALL {
+            for affected in results.affected_counts() {
+                let samples = results.samples(scheme_index, status, affected);
+                for (iteration, sample) in samples.iter().enumerate() {
+                    records.push(RawRecord {
+                        scheme: scheme.clone(),
+                        status: status.label(),
+                        affected,
+                        iteration,
+                        energy: sample.energy,
+                        computation: sample.computation,
+                        communication: sample.communication,
+                        latency: sample.latency,
+                        relay: sample.relay,
+                        seed: results.seed,
+                        number_of_nodes: topology.number_of_nodes,
+                        number_of_gateways: topology.number_of_gateways,
+                        number_of_min_possible_neighbors: topology.number_of_min_possible_neighbors,
+                        number_of_max_possible_neighbors: topology.number_of_max_possible_neighbors,
+                        number_of_gateway_members: topology.number_of_gateway_members,
+                        topology_model: topology.model.label(),
+                        cluster_strategy: topology.clusters.strategy.label(),
+                        routing_strategy: scenario.routing.strategy.label(),
+                        radio_model: costs.radio.label(),
+                        epsb: costs.epsb,
+                        eprb: costs.eprb,
+                        sent_message_size: costs.sent_message_size,
+                        received_message_size: costs.received_message_size,
+                    });
+                }
+            }
+        }
+    }
+    records
+}
+
+pub fn aggregated_records(
+    results: &SimulationResults,
+    scenario: &Scenario,
+) -> Vec<AggregatedRecord> {
+    let (topology, costs) = (&scenario.topology, &scenario.costs);
+    let mut records = vec![];
+    for (scheme_index, scheme) in results.schemes.iter().enumerate() {
+        for status in NodeStatus::ALL {
+            for affected in results.affected_counts() {
+                let summary = results.summary(scheme_index, status, affected);
+                let (energy, computation, communication, latency, relay) = (
+                    summary.energy,
+                    summary.computation,
+                    summary.communication,
+                    summary.latency,
+                    summary.relay,
+                );
+                records.push(AggregatedRecord {
+                    scheme: scheme.clone(),
+                    status: status.label(),
+                    affected,
+                    iterations: results.iterations,
+                    energy: energy.mean,
+                    energy_std_dev: energy.std_dev,
+                    energy_min: energy.min,
+                    energy_max: energy.max,
+                    energy_median: energy.median,
+                    energy_p5: energy.p5,
+                    energy_p95: energy.p95,
+                    energy_ci_low: energy.ci_low,
+                    energy_ci_high: energy.ci_high,
+                    computation: computation.mean,
+                    computation_std_dev: computation.std_dev,
+                    computation_min: computation.min,
+                    computation_max: computation.max,
+                    computation_median: computation.median,
+                    computation_p5: computation.p5,
+                    computation_p95: computation.p95,
+                    computation_ci_low: computation.ci_low,
+                    computation_ci_high: computation.ci_high,
+                    communication: communication.mean,
+                    communication_std_dev: communication.std_dev,
+                    communication_min: communication.min,
+                    communication_max: communication.max,
+                    communication_median: communication.median,
+                    communication_p5: communication.p5,
+                    communication_p95: communication.p95,
+                    communication_ci_low: communication.ci_low,
+                    communication_ci_high: communication.ci_high,
+                    latency: latency.mean,
+                    latency_std_dev: latency.std_dev,
+                    latency_min: latency.min,
+                    latency_max: latency.max,
+                    latency_median: latency.median,
+                    latency_p5: latency.p5,
+                    latency_p95: latency.p95,
+                    latency_ci_low: latency.ci_low,
+                    latency_ci_high: latency.ci_high,
+                    relay: relay.mean,
+                    relay_std_dev: relay.std_dev,
+                    relay_min: relay.min,
+                    relay_max: relay.max,
+                    relay_median: relay.median,
+                    relay_p5: relay.p5,
+                    relay_p95: relay.p95,
+                    relay_ci_low: relay.ci_low,
+                    relay_ci_high: relay.ci_high,
+                    seed: results.seed,
+                    number_of_nodes: topology.number_of_nodes,
+                    number_of_gateways: topology.number_of_gateways,
+                    number_of_min_possible_neighbors: topology.number_of_min_possible_neighbors,
+                    number_of_max_possible_neighbors: topology.number_of_max_possible_neighbors,
+                    number_of_gateway_members: topology.number_of_gateway_members,
+                    topology_model: topology.model.label(),
+                    cluster_strategy: topology.clusters.strategy.label(),
+                    routing_strategy: scenario.routing.strategy.label(),
+                    radio_model: costs.radio.label(),
+                    epsb: costs.epsb,
+                    eprb: costs.eprb,
+                    sent_message_size: costs.sent_message_size,
+                    received_message_size: costs.received_message_size,
+                });
+            }
+        }
+    }
+    records
+}
+
+// Write the records to `path` as one CSV table or one JSON array; "-" writes to stdout
+pub fn write_records<T: Serialize>(
+    path: &Path,
+    format: ExportFormat,
+    records: &[T],
+) -> Result<(), ExportError> {
+    let io_error = |source| ExportError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let writer: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(io::stdout())
+    } else {
+        Box::new(io::BufWriter::new(
+            fs::File::create(path).map_err(io_error)?,
+        ))
+    };
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for record in records {
+                writer
+                    .serialize(record)
+                    .map_err(|source| ExportError::Csv {
+                        path: path.to_path_buf(),
+                        source,
+                    })?;
+            }
+            writer.flush().map_err(io_error)
+        }
+        ExportFormat::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, records).map_err(|source| {
+                ExportError::Json {
+                    path: path.to_path_buf(),
+                    source,
+                }
+            })?;
+            writeln!(writer).map_err(io_error)?;
+            writer.flush().map_err(io_error)
+        }
+    }
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io { path, source } => {
+                write!(f, "cannot write {}: {}", path.display(), source)
+            }
+            ExportError::Csv { path, source } => {
+                write!(f, "cannot write CSV to {}: {}", path.display(), source)
+            }
+            ExportError::Json { path, source } => {
+                write!(f, "cannot write JSON to {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Io { source, .. } => Some(source),
+            ExportError::Csv { source, .. } => Some(source),
+            ExportError::Json { source, .. } => Some(source),
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use serde::Deserialize;
+
+use crate::{
+    InvolvedDevicesCount, InvolvedExchangesCount, MetricsType, Node, NodeStatus, NodeType,
+    NodesVec, StateCostType,
+};
+
+// How long the messages of a rekeying take, set in [costs.timing]. Message sizes are the ones of
+// the communication cells, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct TimingConfig {
+    // Bits per second on every link, 250 kbps for IEEE 802.15.4 at 2.4 GHz
+    pub data_rate: f64,
+    // Seconds a node takes to handle a message before answering or forwarding it
+    pub hop_delay: f64,
+    // Seconds a gateway takes to process the rekeying of one affected node, e.g. to update its
+    // ledger
+    pub gateway_processing: f64,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            data_rate: 250_000.0,
+            hop_delay: 0.002,
+            gateway_processing: 0.01,
+        }
+    }
+}
+
+impl TimingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.data_rate.is_finite() || self.data_rate <= 0.0 {
+            return Err(format!(
+                "data_rate = {} is not a positive number",
+                self.data_rate
+            ));
+        }
+        for (field, value) in [
+            ("hop_delay", self.hop_delay),
+            ("gateway_processing", self.gateway_processing),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!(
+                    "{} = {} is not a non-negative number",
+                    field, value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Seconds to deliver a message over a number of links, each relay receiving it whole before
+    // sending it on
+    pub fn delivery(&self, bytes: f32, hops: usize) -> f64 {
+        hops as f64 * (bytes as f64 * 8.0 / self.data_rate + self.hop_delay)
+    }
+}
+
+// Seconds until the network is rekeyed once the nodes flagged with `status` are removed. Every
+// gateway handles the affected nodes it serves one after the other while the gateways work in
+// parallel, so the rekeying is over when the busiest gateway is done. A gateway receives its
+// messages one after the other too: a gateway cell receiving from every node makes the time of
+// each affected node, whatever the devices exchange, and the same for the schemes sharing it.
+pub fn time_to_rekey(nodes: &NodesVec, status: NodeStatus, metrics: &MetricsType) -> f32 {
+    // One queue per gateway, and one for the nodes served by none
+    let mut queues = vec![0.0; nodes.len() + 1];
+    for node in nodes.iter().filter(|node| node.has_only_status(status)) {
+        if node.kind != NodeType::Constrained {
+            continue;
+        }
+        let gateway = serving_gateway(nodes, node.id).unwrap_or(nodes.len());
+        queues[gateway] += node_time(nodes, node, status, metrics);
+    }
+    queues.into_iter().fold(0.0, f64::max) as f32
+}
+
+// Seconds the gateway takes to rekey the network around one affected node: it processes the
+// event, then every party of the exchange runs its round at the same time and the rekeying of the
+// node is over when the slowest one is done. A party sends and receives its messages one after
+// the other; the devices exchange theirs with a gateway over their route and with their neighbors
+// over a single link, the gateway and the node that left over a single link.
+fn node_time(nodes: &NodesVec, node: &Node, status: NodeStatus, metrics: &MetricsType) -> f64 {
+    let cells = match status {
+        NodeStatus::Compromised => metrics.communication.compromised,
+        NodeStatus::Leaving => metrics.communication.leaving,
+        NodeStatus::Draining => metrics.communication.draining,
+    };
+    let timing = &metrics.timing;
+    let gateway = round(nodes, node, &cells.gateway, timing, 1);
+    let left = round(nodes, node, &cells.left, timing, 1);
+    let state = &cells.constrained;
+    let devices = match state.involved_devices {
+        InvolvedDevicesCount::SameAsDefined => {
+            state.number_of_involved_devices as f64
+                * round(nodes, node, state, timing, hops(nodes, node.id))
+        }
+        _ => nodes
+            .involved_devices(
+                node,
+                state.involved_devices,
+                state.number_of_involved_devices,
+            )
+            .into_iter()
+            .map(|device| round(nodes, node, state, timing, hops(nodes, device)))
+            .fold(0.0, f64::max),
+    };
+    let processing = if gateway > 0.0 {
+        timing.gateway_processing
+    } else {
+        0.0
+    };
+    processing + gateway.max(left).max(devices)
+}
+
+// Gateway handling the rekeying of a node: the end of its route, or the first gateway of its
+// cluster
+fn serving_gateway(nodes: &NodesVec, id: usize) -> Option<usize> {
+    nodes
+        .routes()
+        .gateway(id)
+        .or_else(|| nodes.clusters().gateways(id).first().copied())
+}
+
+// Seconds a party takes to send and receive the messages of a cell when its route to the gateway
+// has this many links. The messages are counted for the affected node, as in its energy cost.
+fn round(
+    nodes: &NodesVec,
+    node: &Node,
+    state: &StateCostType,
+    timing: &TimingConfig,
+    route: usize,
+) -> f64 {
+    let hops = |kind: InvolvedExchangesCount| {
+        if kind == InvolvedExchangesCount::Neighbors {
+            1
+        } else {
+            route
+        }
+    };
+    let (sent, received) = state.exchange.messages;
+    let sent_messages = node.calculate_involved_messages(nodes, sent, state.exchange.sent);
+    let received_messages =
+        node.calculate_involved_messages(nodes, received, state.exchange.received);
+    sent_messages as f64 * timing.delivery(state.exchange_cost.sent, hops(sent))
+        + received_messages as f64 * timing.delivery(state.exchange_cost.received, hops(received))
+}
+
+// Links between a device and its gateway, a single one when messages go straight to it
+fn hops(nodes: &NodesVec, id: usize) -> usize {
+    if nodes.routes().is_routed(id) {
+        nodes.routes().hops(id)
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{methods::SchemeRegistry, scenario::Scenario, topology};
+
+    // The affected nodes of a gateway queue up, those of different gateways are rekeyed together
+    #[test]
+    fn gateways_rekey_their_nodes_in_parallel() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            schemes = ["bkrsc", "others"]
+
+            [topology]
+            number_of_nodes = 30
+            number_of_gateways = 3
+            number_of_min_possible_neighbors = 2
+            number_of_max_possible_neighbors = 4
+            number_of_gateway_members = 9
+            clusters = { strategy = "nearest" }
+            "#,
+        )
+        .unwrap();
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let mut nodes = topology::generate(&scenario.topology, 3).unwrap();
+        let constrained: Vec<usize> = nodes
+            .iter()
+            .filter(|node| node.kind == NodeType::Constrained)
+            .map(|node| node.id)
+            .collect();
+        let gateway = |id: usize| serving_gateway(&nodes, id).unwrap();
+        let a = constrained[0];
+        let same = *constrained[1..]
+            .iter()
+            .find(|id| gateway(**id) == gateway(a))
+            .unwrap();
+        let other = *constrained
+            .iter()
+            .find(|id| gateway(**id) != gateway(a))
+            .unwrap();
+        for scheme in registry.iter() {
+            let metrics = scheme.metrics();
+            for status in NodeStatus::ALL {
+                for (b, parallel) in [(same, false), (other, true)] {
+                    nodes.affect_node_ids(status, &[a, b]);
+                    let times = (
+                        node_time(&nodes, &nodes[a], status, &metrics),
+                        node_time(&nodes, &nodes[b], status, &metrics),
+                    );
+                    let expected = if parallel {
+                        times.0.max(times.1)
+                    } else {
+                        times.0 + times.1
+                    };
+                    let time = time_to_rekey(&nodes, status, &metrics) as f64;
+                    assert!(times.0 > 0.0 && times.1 > 0.0);
+                    assert!((time - expected).abs() <= 1e-6 * expected);
+                    nodes.reset();
+                }
+            }
+        }
+    }
+}
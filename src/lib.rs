@@ -1,8 +1,27 @@
-use std::ops::{Deref, DerefMut};
-
-use rand::{rngs::ThreadRng, seq::SliceRandom, thread_rng, Rng};
-
+use std::ops::Deref;
+
+use latency::TimingConfig;
+use radio::RadioModel;
+use routing::Routes;
+use serde::Deserialize;
+use topology::clusters::ClusterMap;
+
+pub mod builder;
+pub mod comparison;
+pub mod crypto;
+pub mod events;
+pub mod export;
+pub mod latency;
+pub mod lifetime;
 pub mod methods;
+pub mod radio;
+pub mod rng;
+pub mod routing;
+pub mod scenario;
+pub mod simulation;
+pub mod stats;
+pub mod sweep;
+pub mod topology;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum NodeType {
@@ -10,7 +29,24 @@ pub enum NodeType {
     Constrained,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+impl NodeType {
+    pub fn index(&self) -> usize {
+        match self {
+            NodeType::Gateway => 0,
+            NodeType::Constrained => 1,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NodeType::Gateway => "gateway",
+            NodeType::Constrained => "constrained",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InvolvedDevicesCount {
     Neighbors,
     GatewayMembers,
@@ -18,7 +54,8 @@ pub enum InvolvedDevicesCount {
     SameAsDefined,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum InvolvedExchangesCount {
     Neighbors,
     GatewayMembers,
@@ -59,6 +96,8 @@ pub struct StateCostType {
     pub exchange_cost: ExchangeCostType,
     pub number_of_involved_devices: u32,
     pub involved_devices: InvolvedDevicesCount,
+    // Energy of the cryptographic operations of each involved device, in joules
+    pub computation_cost: f64,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -79,353 +118,15 @@ pub struct CommunicationType {
 pub struct MetricsType {
     pub energy: EnergyType,
     pub communication: CommunicationType,
-}
-
-impl EnergyType {
-    pub fn new(
-        compromised_sent: u32,
-        compromised_received: u32,
-        compromised_sent_cost: f32,
-        compromised_received_cost: f32,
-        leaving_sent: u32,
-        leaving_received: u32,
-        leaving_sent_cost: f32,
-        leaving_received_cost: f32,
-        draining_sent: u32,
-        draining_received: u32,
-        draining_sent_cost: f32,
-        draining_received_cost: f32,
-        cc_involved_devices: u32,
-        cg_involved_devices: u32,
-        cl_involved_devices: u32,
-        lc_involved_devices: u32,
-        lg_involved_devices: u32,
-        ll_involved_devices: u32,
-        dc_involved_devices: u32,
-        dg_involved_devices: u32,
-        dl_involved_devices: u32,
-        cc_involved_devices_t: InvolvedDevicesCount,
-        cg_involved_devices_t: InvolvedDevicesCount,
-        cl_involved_devices_t: InvolvedDevicesCount,
-        lc_involved_devices_t: InvolvedDevicesCount,
-        lg_involved_devices_t: InvolvedDevicesCount,
-        ll_involved_devices_t: InvolvedDevicesCount,
-        dc_involved_devices_t: InvolvedDevicesCount,
-        dg_involved_devices_t: InvolvedDevicesCount,
-        dl_involved_devices_t: InvolvedDevicesCount,
-        cc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        cg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        cl_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        lc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        lg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        ll_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dl_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-    ) -> Self {
-        Self {
-            compromised: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cc_involved_devices,
-                    involved_devices: cc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cg_involved_devices,
-                    involved_devices: cg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cl_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cl_involved_devices,
-                    involved_devices: cl_involved_devices_t,
-                },
-            },
-            leaving: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: lc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: lc_involved_devices,
-                    involved_devices: lc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: lg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: lg_involved_devices,
-                    involved_devices: lg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: ll_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: ll_involved_devices,
-                    involved_devices: ll_involved_devices_t,
-                },
-            },
-            draining: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dc_involved_devices,
-                    involved_devices: dc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dg_involved_devices,
-                    involved_devices: dg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dl_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dl_involved_devices,
-                    involved_devices: dl_involved_devices_t,
-                },
-            },
-        }
-    }
-}
-
-impl CommunicationType {
-    pub fn new(
-        compromised_sent: u32,
-        compromised_received: u32,
-        compromised_sent_cost: f32,
-        compromised_received_cost: f32,
-        leaving_sent: u32,
-        leaving_received: u32,
-        leaving_sent_cost: f32,
-        leaving_received_cost: f32,
-        draining_sent: u32,
-        draining_received: u32,
-        draining_sent_cost: f32,
-        draining_received_cost: f32,
-        cc_involved_devices: u32,
-        cg_involved_devices: u32,
-        cl_involved_devices: u32,
-        lc_involved_devices: u32,
-        lg_involved_devices: u32,
-        ll_involved_devices: u32,
-        dc_involved_devices: u32,
-        dg_involved_devices: u32,
-        dl_involved_devices: u32,
-        cc_involved_devices_t: InvolvedDevicesCount,
-        cg_involved_devices_t: InvolvedDevicesCount,
-        cl_involved_devices_t: InvolvedDevicesCount,
-        lc_involved_devices_t: InvolvedDevicesCount,
-        lg_involved_devices_t: InvolvedDevicesCount,
-        ll_involved_devices_t: InvolvedDevicesCount,
-        dc_involved_devices_t: InvolvedDevicesCount,
-        dg_involved_devices_t: InvolvedDevicesCount,
-        dl_involved_devices_t: InvolvedDevicesCount,
-        cc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        cg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        cl_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        lc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        lg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        ll_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dc_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dg_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-        dl_involved_exchanges_t: (InvolvedExchangesCount, InvolvedExchangesCount),
-    ) -> Self {
-        Self {
-            compromised: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cc_involved_devices,
-                    involved_devices: cc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cg_involved_devices,
-                    involved_devices: cg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: compromised_sent,
-                        received: compromised_received,
-                        messages: cl_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: compromised_sent_cost,
-                        received: compromised_received_cost,
-                    },
-                    number_of_involved_devices: cl_involved_devices,
-                    involved_devices: cl_involved_devices_t,
-                },
-            },
-            leaving: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: lc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: lc_involved_devices,
-                    involved_devices: lc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: lg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: lg_involved_devices,
-                    involved_devices: lg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: leaving_sent,
-                        received: leaving_received,
-                        messages: ll_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: leaving_sent_cost,
-                        received: leaving_received_cost,
-                    },
-                    number_of_involved_devices: ll_involved_devices,
-                    involved_devices: ll_involved_devices_t,
-                },
-            },
-            draining: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dc_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dc_involved_devices,
-                    involved_devices: dc_involved_devices_t,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dg_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dg_involved_devices,
-                    involved_devices: dg_involved_devices_t,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: draining_sent,
-                        received: draining_received,
-                        messages: dl_involved_exchanges_t,
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: draining_sent_cost,
-                        received: draining_received_cost,
-                    },
-                    number_of_involved_devices: dl_involved_devices,
-                    involved_devices: dl_involved_devices_t,
-                },
-            },
-        }
-    }
+    pub timing: TimingConfig,
 }
 
 impl MetricsType {
-    pub fn new(energy: EnergyType, communication: CommunicationType) -> Self {
+    pub fn new(energy: EnergyType, communication: CommunicationType, timing: TimingConfig) -> Self {
         Self {
             energy,
             communication,
+            timing,
         }
     }
 }
@@ -437,6 +138,58 @@ pub enum NodeStatus {
     Draining,
 }
 
+impl NodeStatus {
+    pub const ALL: [NodeStatus; 3] = [
+        NodeStatus::Compromised,
+        NodeStatus::Leaving,
+        NodeStatus::Draining,
+    ];
+
+    pub fn index(&self) -> usize {
+        match self {
+            NodeStatus::Compromised => 0,
+            NodeStatus::Leaving => 1,
+            NodeStatus::Draining => 2,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NodeStatus::Compromised => "compromised",
+            NodeStatus::Leaving => "leaving",
+            NodeStatus::Draining => "draining",
+        }
+    }
+}
+
+// Part a device plays in a rekeying exchange
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Role {
+    Constrained,
+    Gateway,
+    Left,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Constrained, Role::Gateway, Role::Left];
+
+    pub fn index(&self) -> usize {
+        match self {
+            Role::Constrained => 0,
+            Role::Gateway => 1,
+            Role::Left => 2,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Role::Constrained => "constrained",
+            Role::Gateway => "gateway",
+            Role::Left => "left",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum MetricsFor {
     Constrained,
@@ -444,15 +197,20 @@ pub enum MetricsFor {
     All,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Node {
     pub id: usize,
     pub kind: NodeType,
-    pub neighbors: Vec<usize>,
     pub max_possible_neighbors: usize,
+    // Coordinates in the deployment area, for geometric topologies
+    pub position: Option<[f64; 3]>,
     pub is_compromised: bool,
     pub is_leaving: bool,
     pub is_draining: bool,
+    // Battery in joules, infinite for mains powered nodes. A node whose battery is empty is dead
+    // and stays inactive.
+    pub capacity: f64,
+    pub remaining: f64,
 }
 
 // Create a trait for energy consumption in case of compromised node and another trait for energy consumption in case of leaving node, and another one for energy consumption in case of draining node
@@ -492,30 +250,36 @@ impl TotalEnergyConsumption<MetricsType> for NodesVec {
     ) -> f32 {
         let mut total_energy_consumption = 0.0;
         let mut _involved_devices = 0;
-        let filtered_nodes: _ = match metrics_for {
+        let mut cluster_members = false;
+        let filtered_nodes = match metrics_for {
             MetricsFor::Constrained => {
                 let constrained_nodes: Vec<&Node> = match status {
                     NodeStatus::Compromised => {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == true)
-                        .filter(|node| node.is_leaving == false)
-                        .filter(|node| node.is_draining == false)
+                        .filter(|node| node.is_compromised)
+                        .filter(|node| !node.is_leaving)
+                        .filter(|node| !node.is_draining)
                         .clone()
                         .collect();
 
 
                         _involved_devices = match metrics.energy.compromised.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.energy.compromised.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => metrics.energy.compromised.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_compromised == true {
+                                    if node.kind == NodeType::Constrained && node.is_compromised {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -528,22 +292,27 @@ impl TotalEnergyConsumption<MetricsType> for NodesVec {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == false)
-                        .filter(|node| node.is_leaving == true)
-                        .filter(|node| node.is_draining == false)
+                        .filter(|node| !node.is_compromised)
+                        .filter(|node| node.is_leaving)
+                        .filter(|node| !node.is_draining)
                         .clone()
                         .collect();
 
                         _involved_devices = match metrics.energy.leaving.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.energy.leaving.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => metrics.energy.leaving.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_leaving == true {
+                                    if node.kind == NodeType::Constrained && node.is_leaving {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -556,22 +325,27 @@ impl TotalEnergyConsumption<MetricsType> for NodesVec {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == false)
-                        .filter(|node| node.is_leaving == false)
-                        .filter(|node| node.is_draining == true)
+                        .filter(|node| !node.is_compromised)
+                        .filter(|node| !node.is_leaving)
+                        .filter(|node| node.is_draining)
                         .clone()
                         .collect();
 
                         _involved_devices = match metrics.energy.draining.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.energy.draining.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => metrics.energy.draining.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_draining == true {
+                                    if node.kind == NodeType::Constrained && node.is_draining {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -592,14 +366,25 @@ impl TotalEnergyConsumption<MetricsType> for NodesVec {
 
                 _involved_devices = match metrics.energy.compromised.gateway.involved_devices {
                     InvolvedDevicesCount::All => self.len() as u32,
-                    InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.energy.compromised.gateway.number_of_involved_devices,
+                    InvolvedDevicesCount::SameAsDefined => {
+                        metrics
+                            .energy
+                            .compromised
+                            .gateway
+                            .number_of_involved_devices
+                    }
+                    InvolvedDevicesCount::GatewayMembers => {
+                        // Counted for each node, on its own clusters
+                        cluster_members = true;
+                        0
+                    }
                     InvolvedDevicesCount::Neighbors => {
                         // Count devices and their neighbors
                         let mut devices = 0;
                         for node in self.iter() {
-                            if node.kind == NodeType::Constrained && node.is_compromised == true {
+                            if node.kind == NodeType::Constrained && node.is_compromised {
                                 devices += 1;
-                                devices += node.neighbors.len();
+                                devices += self.degree(node.id);
                             }
                         }
                         devices as u32
@@ -617,12 +402,18 @@ impl TotalEnergyConsumption<MetricsType> for NodesVec {
             }
         };
         for node in filtered_nodes.iter() {
-            if metrics_for == MetricsFor::Constrained && node.kind == NodeType::Constrained {
-                total_energy_consumption += node.energy_consumption(&*self, status, metrics) * _involved_devices as f32;
-            } else if metrics_for == MetricsFor::Gateway && node.kind == NodeType::Gateway {
-                total_energy_consumption += node.energy_consumption(&*self, status, metrics) * _involved_devices as f32;
-            } else if metrics_for == MetricsFor::All {
-                total_energy_consumption += node.energy_consumption(&*self, status, metrics) * _involved_devices as f32;
+            if (metrics_for == MetricsFor::Constrained && node.kind == NodeType::Constrained)
+                || (metrics_for == MetricsFor::Gateway && node.kind == NodeType::Gateway)
+                || metrics_for == MetricsFor::All
+            {
+                let involved_devices = if cluster_members {
+                    self.involved_devices(node, InvolvedDevicesCount::GatewayMembers, 0)
+                        .len() as u32
+                } else {
+                    _involved_devices
+                };
+                total_energy_consumption +=
+                    node.energy_consumption(self, status, metrics) * involved_devices as f32;
             }
         }
         total_energy_consumption
@@ -638,29 +429,41 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
     ) -> f32 {
         let mut total_communication_overhead = 0.0;
         let mut _involved_devices = 0;
-        let filtered_nodes: _ = match metrics_for {
+        let mut cluster_members = false;
+        let filtered_nodes = match metrics_for {
             MetricsFor::Constrained => {
                 let constrained_nodes: Vec<&Node> = match status {
                     NodeStatus::Compromised => {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == true)
-                        .filter(|node| node.is_leaving == false)
-                        .filter(|node| node.is_draining == false)
+                        .filter(|node| node.is_compromised)
+                        .filter(|node| !node.is_leaving)
+                        .filter(|node| !node.is_draining)
                         .clone()
                         .collect();
 
                         _involved_devices = match metrics.communication.compromised.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.communication.compromised.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => {
+                                metrics
+                                    .communication
+                                    .compromised
+                                    .constrained
+                                    .number_of_involved_devices
+                            }
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_compromised == true {
+                                    if node.kind == NodeType::Constrained && node.is_compromised {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -673,22 +476,27 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == false)
-                        .filter(|node| node.is_leaving == true)
-                        .filter(|node| node.is_draining == false)
+                        .filter(|node| !node.is_compromised)
+                        .filter(|node| node.is_leaving)
+                        .filter(|node| !node.is_draining)
                         .clone()
                         .collect();
 
                         _involved_devices = match metrics.communication.leaving.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.communication.leaving.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => metrics.communication.leaving.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_leaving == true {
+                                    if node.kind == NodeType::Constrained && node.is_leaving {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -701,22 +509,27 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
                         let devices = self
                         .iter()
                         .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.is_compromised == false)
-                        .filter(|node| node.is_leaving == false)
-                        .filter(|node| node.is_draining == true)
+                        .filter(|node| !node.is_compromised)
+                        .filter(|node| !node.is_leaving)
+                        .filter(|node| node.is_draining)
                         .clone()
                         .collect();
 
                         _involved_devices = match metrics.communication.draining.constrained.involved_devices {
                             InvolvedDevicesCount::All => self.len() as u32,
-                            InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.communication.draining.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::SameAsDefined => metrics.communication.draining.constrained.number_of_involved_devices,
+                            InvolvedDevicesCount::GatewayMembers => {
+                                // Counted for each node, on its own clusters
+                                cluster_members = true;
+                                0
+                            }
                             InvolvedDevicesCount::Neighbors => {
                                 // Count devices and their neighbors
                                 let mut devices = 0;
                                 for node in self.iter() {
-                                    if node.kind == NodeType::Constrained && node.is_draining == true {
+                                    if node.kind == NodeType::Constrained && node.is_draining {
                                         devices += 1;
-                                        devices += node.neighbors.len();
+                                        devices += self.degree(node.id);
                                     }
                                 }
                                 devices as u32
@@ -737,14 +550,25 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
 
                 _involved_devices = match metrics.communication.compromised.gateway.involved_devices {
                     InvolvedDevicesCount::All => self.len() as u32,
-                    InvolvedDevicesCount::SameAsDefined | InvolvedDevicesCount::GatewayMembers => metrics.communication.compromised.gateway.number_of_involved_devices,
+                    InvolvedDevicesCount::SameAsDefined => {
+                        metrics
+                            .communication
+                            .compromised
+                            .gateway
+                            .number_of_involved_devices
+                    }
+                    InvolvedDevicesCount::GatewayMembers => {
+                        // Counted for each node, on its own clusters
+                        cluster_members = true;
+                        0
+                    }
                     InvolvedDevicesCount::Neighbors => {
                         // Count devices and their neighbors
                         let mut devices = 0;
                         for node in self.iter() {
                             if node.kind == NodeType::Gateway {
                                 devices += 1;
-                                devices += node.neighbors.len();
+                                devices += self.degree(node.id);
                             }
                         }
                         devices as u32
@@ -762,12 +586,18 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
             }
         };
         for node in filtered_nodes.iter() {
-            if metrics_for == MetricsFor::Constrained && node.kind == NodeType::Constrained {
-                total_communication_overhead += node.communication_overhead(&*self, status, metrics) * _involved_devices as f32;
-            } else if metrics_for == MetricsFor::Gateway && node.kind == NodeType::Gateway {
-                total_communication_overhead += node.communication_overhead(&*self, status, metrics) * _involved_devices as f32;
-            } else if metrics_for == MetricsFor::All {
-                total_communication_overhead += node.communication_overhead(&*self, status, metrics) * _involved_devices as f32;
+            if (metrics_for == MetricsFor::Constrained && node.kind == NodeType::Constrained)
+                || (metrics_for == MetricsFor::Gateway && node.kind == NodeType::Gateway)
+                || metrics_for == MetricsFor::All
+            {
+                let involved_devices = if cluster_members {
+                    self.involved_devices(node, InvolvedDevicesCount::GatewayMembers, 0)
+                        .len() as u32
+                } else {
+                    _involved_devices
+                };
+                total_communication_overhead +=
+                    node.communication_overhead(self, status, metrics) * involved_devices as f32;
             }
         }
         total_communication_overhead
@@ -776,19 +606,28 @@ impl TotalCommunicationOverhead<MetricsType> for NodesVec {
 
 impl EnergyConsumption<Node, MetricsType> for Node {
     fn energy_consumption(&self, nodes: &NodesVec, status: NodeStatus, metrics: MetricsType) -> f32 {
+        if let Some(radio) = nodes.routes().radio() {
+            return self.radio_energy(nodes, radio, status, metrics);
+        }
         match status {
             NodeStatus::Compromised => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.compromised.gateway.exchange.messages.0, metrics.energy.compromised.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.compromised.gateway.exchange.messages.1, metrics.energy.compromised.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.compromised.gateway.exchange.messages.0, metrics.energy.compromised.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.compromised.gateway.exchange.messages.1, metrics.energy.compromised.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.compromised.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.energy.compromised.gateway.exchange_cost.received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.compromised.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.compromised.constrained.exchange.messages.0, metrics.energy.compromised.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.compromised.constrained.exchange.messages.1, metrics.energy.compromised.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.compromised.constrained.exchange.messages.0, metrics.energy.compromised.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.compromised.constrained.exchange.messages.1, metrics.energy.compromised.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.compromised.constrained.exchange_cost.sent)
                         + (involved_received_messages as f32
@@ -798,42 +637,72 @@ impl EnergyConsumption<Node, MetricsType> for Node {
                                 .constrained
                                 .exchange_cost
                                 .received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.compromised.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
             NodeStatus::Leaving => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.leaving.gateway.exchange.messages.0, metrics.energy.leaving.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.leaving.gateway.exchange.messages.1, metrics.energy.leaving.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.leaving.gateway.exchange.messages.0, metrics.energy.leaving.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.leaving.gateway.exchange.messages.1, metrics.energy.leaving.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.leaving.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.energy.leaving.gateway.exchange_cost.received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.leaving.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.leaving.constrained.exchange.messages.0, metrics.energy.leaving.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.leaving.constrained.exchange.messages.1, metrics.energy.leaving.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.leaving.constrained.exchange.messages.0, metrics.energy.leaving.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.leaving.constrained.exchange.messages.1, metrics.energy.leaving.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.leaving.constrained.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.energy.leaving.constrained.exchange_cost.received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.leaving.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
             NodeStatus::Draining => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.draining.gateway.exchange.messages.0, metrics.energy.draining.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.draining.gateway.exchange.messages.1, metrics.energy.draining.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.draining.gateway.exchange.messages.0, metrics.energy.draining.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.draining.gateway.exchange.messages.1, metrics.energy.draining.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.draining.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.energy.draining.gateway.exchange_cost.received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.draining.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.draining.constrained.exchange.messages.0, metrics.energy.draining.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.energy.draining.constrained.exchange.messages.1, metrics.energy.draining.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.draining.constrained.exchange.messages.0, metrics.energy.draining.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.energy.draining.constrained.exchange.messages.1, metrics.energy.draining.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.energy.draining.constrained.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.energy.draining.constrained.exchange_cost.received)
+                        + self.relay_cost(
+                            nodes,
+                            &metrics.energy.draining.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
         }
@@ -845,8 +714,8 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
         match status {
             NodeStatus::Compromised => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.compromised.gateway.exchange.messages.0, metrics.communication.compromised.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.compromised.gateway.exchange.messages.1, metrics.communication.compromised.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.compromised.gateway.exchange.messages.0, metrics.communication.compromised.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.compromised.gateway.exchange.messages.1, metrics.communication.compromised.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.communication.compromised.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
@@ -856,10 +725,16 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
                                 .gateway
                                 .exchange_cost
                                 .received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.compromised.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.compromised.constrained.exchange.messages.0, metrics.communication.compromised.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.compromised.constrained.exchange.messages.1, metrics.communication.compromised.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.compromised.constrained.exchange.messages.0, metrics.communication.compromised.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.compromised.constrained.exchange.messages.1, metrics.communication.compromised.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics
                             .communication
@@ -874,20 +749,32 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
                                 .constrained
                                 .exchange_cost
                                 .received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.compromised.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
             NodeStatus::Leaving => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.leaving.gateway.exchange.messages.0, metrics.communication.leaving.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.leaving.gateway.exchange.messages.1, metrics.communication.leaving.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.leaving.gateway.exchange.messages.0, metrics.communication.leaving.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.leaving.gateway.exchange.messages.1, metrics.communication.leaving.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.communication.leaving.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
                             * metrics.communication.leaving.gateway.exchange_cost.received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.leaving.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.leaving.constrained.exchange.messages.0, metrics.communication.leaving.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.leaving.constrained.exchange.messages.1, metrics.communication.leaving.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.leaving.constrained.exchange.messages.0, metrics.communication.leaving.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.leaving.constrained.exchange.messages.1, metrics.communication.leaving.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.communication.leaving.constrained.exchange_cost.sent)
                         + (involved_received_messages as f32
@@ -897,12 +784,18 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
                                 .constrained
                                 .exchange_cost
                                 .received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.leaving.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
             NodeStatus::Draining => match self.kind {
                 NodeType::Gateway => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.draining.gateway.exchange.messages.0, metrics.communication.draining.gateway.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.draining.gateway.exchange.messages.1, metrics.communication.draining.gateway.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.draining.gateway.exchange.messages.0, metrics.communication.draining.gateway.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.draining.gateway.exchange.messages.1, metrics.communication.draining.gateway.exchange.received);
                     (involved_sent_messages as f32
                         * metrics.communication.draining.gateway.exchange_cost.sent)
                         + (involved_received_messages as f32
@@ -912,10 +805,16 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
                                 .gateway
                                 .exchange_cost
                                 .received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.draining.gateway,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
                 NodeType::Constrained => {
-                    let involved_sent_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.draining.constrained.exchange.messages.0, metrics.communication.draining.constrained.exchange.sent);
-                    let involved_received_messages: u32 = self.calculate_involved_messages(&*nodes, metrics.communication.draining.constrained.exchange.messages.1, metrics.communication.draining.constrained.exchange.received);
+                    let involved_sent_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.draining.constrained.exchange.messages.0, metrics.communication.draining.constrained.exchange.sent);
+                    let involved_received_messages: u32 = self.calculate_involved_messages(nodes, metrics.communication.draining.constrained.exchange.messages.1, metrics.communication.draining.constrained.exchange.received);
                     (involved_sent_messages as f32
                         * metrics
                             .communication
@@ -930,276 +829,425 @@ impl CommunicationOverhead<Node, MetricsType> for Node {
                                 .constrained
                                 .exchange_cost
                                 .received)
+                        + self.relay_overhead(
+                            nodes,
+                            &metrics.communication.draining.constrained,
+                            involved_sent_messages,
+                            involved_received_messages,
+                        )
                 }
             },
         }
     }
 }
 
-#[derive(Debug)]
-pub struct NodesVec(Vec<Node>);
+// Network with its adjacency in compressed sparse row form: the neighbors of node i are
+// targets[offsets[i]..offsets[i + 1]], in the order the links were made, and node ids are their
+// positions. The number of active nodes of each kind, in the whole network and around every node,
+// is kept up to date as statuses change, so the cost of an event never has to scan the network
+// once per affected node.
+#[derive(Debug, Clone, Default)]
+pub struct NodesVec {
+    nodes: Vec<Node>,
+    offsets: Vec<usize>,
+    targets: Vec<usize>,
+    active: [usize; 2],
+    active_neighbors: Vec<[usize; 2]>,
+    clusters: ClusterMap,
+    routes: Routes,
+}
 
 impl NodesVec {
-    fn new() -> Self {
-        Self(Vec::new())
-    }
+    // Build a network from its nodes and undirected links, nodes are renumbered by position.
+    // Constrained nodes are members of the gateways they are linked to.
+    pub fn from_edges(mut nodes: Vec<Node>, edges: &[(usize, usize)]) -> Self {
+        for (index, node) in nodes.iter_mut().enumerate() {
+            node.id = index;
+        }
+        let mut offsets = vec![0; nodes.len() + 1];
+        for (a, b) in edges {
+            offsets[*a + 1] += 1;
+            offsets[*b + 1] += 1;
+        }
+        for index in 1..offsets.len() {
+            offsets[index] += offsets[index - 1];
+        }
+        let mut next = offsets.clone();
+        let mut targets = vec![0; edges.len() * 2];
+        for (a, b) in edges {
+            targets[next[*a]] = *b;
+            next[*a] += 1;
+            targets[next[*b]] = *a;
+            next[*b] += 1;
+        }
 
-    pub fn compromise_nodes(&mut self, number_of_nodes_to_compromise: usize) -> &mut Self {
-        let mut rng: ThreadRng = thread_rng();
-        // Select number_of_nodes_to_compromise random nodes and compromise them
-        let mut compromised_nodes: Vec<usize> = (0..self.len()).collect();
-        compromised_nodes.shuffle(&mut rng);
-        compromised_nodes.truncate(number_of_nodes_to_compromise);
-        for node_id in compromised_nodes {
-            self[node_id].is_compromised = true;
+        let mut network = Self {
+            active_neighbors: vec![[0; 2]; nodes.len()],
+            nodes,
+            offsets,
+            targets,
+            active: [0; 2],
+            clusters: ClusterMap::default(),
+            routes: Routes::default(),
+        };
+        for id in 0..network.len() {
+            if network[id].is_active() {
+                network.update_counts(id, true);
+            }
         }
+        network.clusters = ClusterMap::adjacent(&network);
+        network
+    }
+
+    pub fn neighbors(&self, id: usize) -> &[usize] {
+        &self.targets[self.offsets[id]..self.offsets[id + 1]]
+    }
+
+    pub fn degree(&self, id: usize) -> usize {
+        self.offsets[id + 1] - self.offsets[id]
+    }
+
+    pub fn clusters(&self) -> &ClusterMap {
+        &self.clusters
+    }
+
+    pub fn set_clusters(&mut self, clusters: ClusterMap) -> &mut Self {
+        self.clusters = clusters;
         self
     }
 
-    pub fn compromised_nodes(&self) -> NodesVec {
-        let compromised_vec = self
-            .iter()
-            .filter(|node| node.is_compromised)
-            .collect::<Vec<&Node>>();
-        let mut compromised_nodes: NodesVec = NodesVec::new();
-        for node in compromised_vec {
-            compromised_nodes.push(Node::new(
-                node.id,
-                node.kind,
-                node.neighbors.clone(),
-                node.max_possible_neighbors,
-            ));
-        }
-        compromised_nodes
+    pub fn routes(&self) -> &Routes {
+        &self.routes
     }
 
-    pub fn leave_nodes(&mut self, number_of_nodes_to_leave: usize) -> &mut Self {
-        let mut rng: ThreadRng = thread_rng();
-        // Select number_of_nodes_to_leave random nodes and leave them
-        let mut leaving_nodes: Vec<usize> = (0..self.len()).collect();
-        leaving_nodes.shuffle(&mut rng);
-        leaving_nodes.truncate(number_of_nodes_to_leave);
-        for node_id in leaving_nodes {
-            self[node_id].is_leaving = true;
-        }
+    // Routes must be built on the final clusters, they are not updated when clusters change
+    pub fn set_routes(&mut self, routes: Routes) -> &mut Self {
+        self.routes = routes;
         self
     }
 
-    pub fn drain_nodes(&mut self, number_of_nodes_to_drain: usize) -> &mut Self {
-        let mut rng: ThreadRng = thread_rng();
-        // Select number_of_nodes_to_drain random nodes and drain them
-        let mut drained_nodes: Vec<usize> = (0..self.len()).collect();
-        drained_nodes.shuffle(&mut rng);
-        drained_nodes.truncate(number_of_nodes_to_drain);
-        for node_id in drained_nodes {
-            self[node_id].is_draining = true;
+    // Nodes of a kind that are neither compromised, leaving nor draining
+    pub fn active_nodes(&self, kind: NodeType) -> usize {
+        self.active[kind.index()]
+    }
+
+    pub fn active_neighbors(&self, id: usize, kind: NodeType) -> usize {
+        self.active_neighbors[id][kind.index()]
+    }
+
+    // Active constrained devices taking part in the rekeying around an affected node, or around a
+    // gateway for its own cluster. The number of the cell is only used when the devices are not
+    // identified.
+    pub fn involved_devices(
+        &self,
+        node: &Node,
+        devices: InvolvedDevicesCount,
+        number: u32,
+    ) -> Vec<usize> {
+        let active = |id: &usize| self[*id].kind == NodeType::Constrained && self[*id].is_active();
+        match devices {
+            InvolvedDevicesCount::All => (0..self.len()).filter(active).collect(),
+            InvolvedDevicesCount::Neighbors => self
+                .neighbors(node.id)
+                .iter()
+                .copied()
+                .filter(active)
+                .collect(),
+            InvolvedDevicesCount::GatewayMembers => {
+                let clusters = self.clusters();
+                let gateways = match node.kind {
+                    NodeType::Gateway => std::slice::from_ref(&node.id),
+                    NodeType::Constrained => clusters.gateways(node.id),
+                };
+                let mut members: Vec<usize> = gateways
+                    .iter()
+                    .flat_map(|gateway| clusters.members(*gateway).iter().copied())
+                    .filter(active)
+                    .collect();
+                members.sort_unstable();
+                members.dedup();
+                members
+            }
+            // The affected node repeats its own exchange
+            InvolvedDevicesCount::SameAsDefined => vec![node.id; number as usize],
         }
-        self
     }
 
-    pub fn drained_nodes(&self) -> NodesVec {
-        let drained_vec = self
-            .iter()
-            .filter(|node| node.is_draining)
-            .collect::<Vec<&Node>>();
-        let mut drained_nodes: NodesVec = NodesVec::new();
-        for node in drained_vec {
-            drained_nodes.push(Node::new(
-                node.id,
-                node.kind,
-                node.neighbors.clone(),
-                node.max_possible_neighbors,
-            ));
+    // Account for a node becoming active or inactive
+    fn update_counts(&mut self, id: usize, active: bool) {
+        let kind = self.nodes[id].kind.index();
+        let range = self.offsets[id]..self.offsets[id + 1];
+        if active {
+            self.active[kind] += 1;
+            for neighbor in &self.targets[range] {
+                self.active_neighbors[*neighbor][kind] += 1;
+            }
+        } else {
+            self.active[kind] -= 1;
+            for neighbor in &self.targets[range] {
+                self.active_neighbors[*neighbor][kind] -= 1;
+            }
         }
-        drained_nodes
     }
 
-    pub fn left_nodes(&self) -> NodesVec {
-        let leaving_vec = self
-            .iter()
-            .filter(|node| node.is_leaving)
-            .collect::<Vec<&Node>>();
-        let mut leaving_nodes: NodesVec = NodesVec::new();
-        for node in leaving_vec {
-            leaving_nodes.push(Node::new(
-                node.id,
-                node.kind,
-                node.neighbors.clone(),
-                node.max_possible_neighbors,
-            ));
+    pub fn affect_node_ids(&mut self, status: NodeStatus, node_ids: &[usize]) -> &mut Self {
+        for node_id in node_ids {
+            let node = &mut self.nodes[*node_id];
+            let was_active = node.is_active();
+            match status {
+                NodeStatus::Compromised => node.is_compromised = true,
+                NodeStatus::Leaving => node.is_leaving = true,
+                NodeStatus::Draining => node.is_draining = true,
+            }
+            if was_active {
+                self.update_counts(*node_id, false);
+            }
         }
-        leaving_nodes
+        self
     }
 
     pub fn reset(&mut self) -> &mut Self {
-        for node in self.iter_mut() {
-            node.is_compromised = false;
-            node.is_leaving = false;
-            node.is_draining = false;
+        for id in 0..self.len() {
+            let node = &mut self.nodes[id];
+            if !node.is_active() {
+                node.is_compromised = false;
+                node.is_leaving = false;
+                node.is_draining = false;
+                if node.is_alive() {
+                    self.update_counts(id, true);
+                }
+            }
+        }
+        self
+    }
+
+    // Fill the batteries of the nodes of a kind
+    pub fn set_capacity(&mut self, kind: NodeType, capacity: f64) -> &mut Self {
+        for id in 0..self.len() {
+            let node = &mut self.nodes[id];
+            if node.kind != kind {
+                continue;
+            }
+            let was_active = node.is_active();
+            node.capacity = capacity;
+            node.remaining = capacity;
+            if node.is_active() != was_active {
+                self.update_counts(id, !was_active);
+            }
         }
         self
     }
+
+    // Take energy from a node's battery, return whether the node died
+    pub fn debit(&mut self, id: usize, energy: f64) -> bool {
+        let node = &mut self.nodes[id];
+        if !node.is_alive() {
+            return false;
+        }
+        let was_active = node.is_active();
+        node.remaining -= energy;
+        if node.is_alive() {
+            return false;
+        }
+        node.remaining = 0.0;
+        if was_active {
+            self.update_counts(id, false);
+        }
+        true
+    }
 }
 
 impl Deref for NodesVec {
     type Target = Vec<Node>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
-    }
-}
-
-impl DerefMut for NodesVec {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &self.nodes
     }
 }
 
 impl Node {
-    fn new(
-        id: usize,
-        kind: NodeType,
-        neighbors: Vec<usize>,
-        max_possible_neighbors: usize,
-    ) -> Self {
+    pub fn new(id: usize, kind: NodeType, max_possible_neighbors: usize) -> Self {
         Self {
             id,
             kind,
-            neighbors,
             max_possible_neighbors,
+            position: None,
             is_compromised: false,
             is_leaving: false,
             is_draining: false,
+            capacity: f64::INFINITY,
+            remaining: f64::INFINITY,
         }
     }
 
-    fn calculate_involved_messages(&self, nodes: &NodesVec, exchanged_messages_count_type: InvolvedExchangesCount, exchanged_messages_count: u32) -> u32 {
-        match exchanged_messages_count_type {
-            InvolvedExchangesCount::SameAsDefined => exchanged_messages_count as u32,
-            InvolvedExchangesCount::Neighbors => {
-                nodes.iter()
-                    .filter(|node| self.neighbors.contains(&node.id))
-                    .filter(|node| node.kind == NodeType::Constrained)
-                    .filter(|node| node.is_compromised == false)
-                    .filter(|node| node.is_leaving == false)
-                    .filter(|node| node.is_draining == false)
-                    .count() as u32
-            },
-            InvolvedExchangesCount::All => (nodes.iter()
-                .filter(|node| node.kind == NodeType::Constrained)
-                .filter(|node| node.is_compromised == false)
-                .filter(|node| node.is_leaving == false)
-                .filter(|node| node.is_draining == false)
-                .count() - 1) as u32,
-            InvolvedExchangesCount::GatewayMembers => {
-                let mut devices_vec: Vec<usize> = vec![];
-                // Get neighbor nodes of the current node
-                let neighbors_ids = self.neighbors.clone();
-                // Get neighbors by their ids from the nodes vector that are gateways
-                let gateway_neighbors = nodes
-                    .iter()
-                    .filter(|node| neighbors_ids.contains(&node.id))
-                    .filter(|node| node.kind == NodeType::Gateway)
-                    .collect::<Vec<&Node>>();
-                // Get the neighbors length of these gateways
-                for gateway in gateway_neighbors.iter() {
-                    // Get neighbor constrained nodes of the current gateway
-                    let constrained_neighbors_ids = gateway.neighbors.clone();
-                    // Get neighbors by their ids from the nodes vector that are constrained 
-                    let constrained_neighbors = nodes
-                        .iter()
-                        .filter(|node| constrained_neighbors_ids.contains(&node.id))
-                        .filter(|node| node.kind == NodeType::Constrained)
-                        .filter(|node| node.id != self.id)
-                        .collect::<Vec<&Node>>();
-                    // Add devices ids to the devices vector without duplicates
-                    for constrained_neighbor in constrained_neighbors.iter() {
-                        if !devices_vec.contains(&constrained_neighbor.id) {
-                            devices_vec.push(constrained_neighbor.id);
-                        }
-                    }
-                }
-                devices_vec.len() as u32
-            }
-        }
+    pub fn is_active(&self) -> bool {
+        !self.is_compromised && !self.is_leaving && !self.is_draining && self.is_alive()
     }
-}
 
-pub fn initialize_network(
-    number_of_nodes: i32,
-    number_of_gateways: i32,
-    number_of_min_possible_neighbors: i32,
-    number_of_max_possible_neighbors: i32,
-) -> NodesVec {
-    let mut nodes: NodesVec = NodesVec::new();
-
-    let mut rng: ThreadRng = thread_rng();
-
-    // Push gateway nodes
-    for _ in 0..number_of_gateways {
-        let number_of_neighbors: i32 =
-            rng.gen_range(number_of_min_possible_neighbors..=number_of_max_possible_neighbors);
-        let node = Node::new(
-            nodes.len(),
-            NodeType::Gateway,
-            vec![],
-            number_of_neighbors as usize,
-        );
-        nodes.push(node);
+    pub fn is_alive(&self) -> bool {
+        self.remaining > 0.0
     }
 
-    // Push constrained nodes
-    for _ in 0..(number_of_nodes - number_of_gateways) {
-        let number_of_neighbors: i32 =
-            rng.gen_range(number_of_min_possible_neighbors..=number_of_max_possible_neighbors);
-        let node = Node::new(
-            nodes.len(),
-            NodeType::Constrained,
-            vec![],
-            number_of_neighbors as usize,
-        );
-        nodes.push(node);
+    // Flagged with this status and no other, as the nodes whose rekeying is costed
+    pub fn has_only_status(&self, status: NodeStatus) -> bool {
+        let flags = [self.is_compromised, self.is_leaving, self.is_draining];
+        (0..flags.len()).all(|index| flags[index] == (index == status.index()))
     }
 
-    // Sort nodes randomly
-    nodes.shuffle(&mut rng);
+    // Live battery powered node left with at most this share of its battery
+    pub fn is_low_battery(&self, threshold: f64) -> bool {
+        self.is_alive() && self.capacity.is_finite() && self.remaining <= threshold * self.capacity
+    }
 
-    // Update id of each node to match its index in the nodes array
-    for i in 0..nodes.len() {
-        nodes[i].id = i;
+    // Euclidean distance to another node, when both have a position
+    pub fn distance(&self, other: &Node) -> Option<f64> {
+        let (a, b) = (self.position?, other.position?);
+        Some(
+            a.iter()
+                .zip(b.iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f64>()
+                .sqrt(),
+        )
     }
 
-    // Start adding neighbors to each node
-    for i in 0..nodes.len() {
-        let current_node_id: usize = nodes[i].id;
-        let number_of_current_neighbors: usize = nodes[i].neighbors.len();
-        let number_of_current_max_possible_neighbors: usize = nodes[i].max_possible_neighbors;
-        let number_of_current_remaining_possible_neighbors: usize =
-            number_of_current_max_possible_neighbors - number_of_current_neighbors;
-        // For each remaining possible neighbor, add it to the current node if it is not already a neighbor
-        for _j in 0..number_of_current_remaining_possible_neighbors {
-            // Get the list of nodes that are not already neighbors of the current node and that are not the current node itself and that do not have the maximum number of neighbors
-            let possible_neighbors: Vec<usize> = (0..nodes.len())
-                .filter(|&k| k != i)
-                .filter(|&k| !nodes[i].neighbors.contains(&k))
-                .filter(|&k| nodes[k].neighbors.len() < nodes[k].max_possible_neighbors)
-                .collect();
+    // Energy of the messages of a cell under a radio model: they are sized by the communication
+    // cell of the same status and role, the node sends on the first hop of the routes of the
+    // involved devices and the relays forward them. A direction whose energy cost is zero, such
+    // as the one of a mains powered gateway, stays free.
+    fn radio_energy(
+        &self,
+        nodes: &NodesVec,
+        radio: &dyn RadioModel,
+        status: NodeStatus,
+        metrics: MetricsType,
+    ) -> f32 {
+        let (energy, communication) = match status {
+            NodeStatus::Compromised => (
+                metrics.energy.compromised,
+                metrics.communication.compromised,
+            ),
+            NodeStatus::Leaving => (metrics.energy.leaving, metrics.communication.leaving),
+            NodeStatus::Draining => (metrics.energy.draining, metrics.communication.draining),
+        };
+        let (state, sizes) = match self.kind {
+            NodeType::Gateway => (energy.gateway, communication.gateway),
+            NodeType::Constrained => (energy.constrained, communication.constrained),
+        };
+        let sent_messages =
+            self.calculate_involved_messages(nodes, state.exchange.messages.0, state.exchange.sent);
+        let received_messages = self.calculate_involved_messages(
+            nodes,
+            state.exchange.messages.1,
+            state.exchange.received,
+        );
+        let route = nodes.routes().mean(nodes, self.id, state.involved_devices);
+        let mut total = 0.0;
+        if state.exchange_cost.sent != 0.0 {
+            let mut per_bit = route.transmit;
+            if state.exchange.messages.0 != InvolvedExchangesCount::Neighbors {
+                per_bit += route.upward;
+            }
+            total += sent_messages as f64 * sizes.exchange_cost.sent as f64 * 8.0 * per_bit;
+        }
+        if state.exchange_cost.received != 0.0 {
+            let mut per_bit = radio.receive();
+            if state.exchange.messages.1 != InvolvedExchangesCount::Neighbors {
+                per_bit += route.downward;
+            }
+            total += received_messages as f64 * sizes.exchange_cost.received as f64 * 8.0 * per_bit;
+        }
+        total as f32
+    }
 
-            // If there are no possible neighbors, break the loop
-            if possible_neighbors.len() == 0 {
-                break;
+    // Messages exchanged with a gateway rather than with neighbors are forwarded by every relay on
+    // the routes of the involved devices: the mean number of relays, and the routed messages sent
+    // and received
+    fn relayed_messages(
+        &self,
+        nodes: &NodesVec,
+        state: &StateCostType,
+        sent_messages: u32,
+        received_messages: u32,
+    ) -> (f32, u32, u32) {
+        let relays = nodes
+            .routes()
+            .mean(nodes, self.id, state.involved_devices)
+            .relays as f32;
+        let routed = |kind: InvolvedExchangesCount, messages: u32| {
+            if kind == InvolvedExchangesCount::Neighbors {
+                0
+            } else {
+                messages
             }
+        };
+        (
+            relays,
+            routed(state.exchange.messages.0, sent_messages),
+            routed(state.exchange.messages.1, received_messages),
+        )
+    }
 
-            // Pick a random node from the list of possible neighbors
-            let neighbor_index: usize = rng.gen_range(0..possible_neighbors.len());
-            let neighbor: usize = possible_neighbors[neighbor_index];
-            let neighbor_id: usize = nodes[neighbor].id;
-            // Add the neighbor id to the current node's list of neighbors
-            nodes[i].neighbors.push(neighbor_id);
-            // Add the current node id to the neighbor's list of neighbors
-            nodes[neighbor].neighbors.push(current_node_id);
-        }
+    // Each relay receives the forwarded messages and sends them again
+    fn relay_cost(
+        &self,
+        nodes: &NodesVec,
+        state: &StateCostType,
+        sent_messages: u32,
+        received_messages: u32,
+    ) -> f32 {
+        let (relays, sent, received) =
+            self.relayed_messages(nodes, state, sent_messages, received_messages);
+        (sent + received) as f32
+            * relays
+            * (state.exchange_cost.sent + state.exchange_cost.received)
+    }
+
+    // Each relay sends the forwarded messages again, at the size of their direction
+    fn relay_overhead(
+        &self,
+        nodes: &NodesVec,
+        state: &StateCostType,
+        sent_messages: u32,
+        received_messages: u32,
+    ) -> f32 {
+        let (relays, sent, received) =
+            self.relayed_messages(nodes, state, sent_messages, received_messages);
+        relays
+            * (sent as f32 * state.exchange_cost.sent
+                + received as f32 * state.exchange_cost.received)
     }
 
-    nodes
+    fn calculate_involved_messages(&self, nodes: &NodesVec, exchanged_messages_count_type: InvolvedExchangesCount, exchanged_messages_count: u32) -> u32 {
+        match exchanged_messages_count_type {
+            InvolvedExchangesCount::SameAsDefined => exchanged_messages_count,
+            InvolvedExchangesCount::Neighbors => {
+                nodes.active_neighbors(self.id, NodeType::Constrained) as u32
+            },
+            InvolvedExchangesCount::All => {
+                nodes.active_nodes(NodeType::Constrained).saturating_sub(1) as u32
+            }
+            InvolvedExchangesCount::GatewayMembers => {
+                let clusters = nodes.clusters();
+                let mut devices_vec: Vec<usize> = vec![];
+                // Get the members of the clusters of the current node, or of its own one for a gateway
+                let gateways = match self.kind {
+                    NodeType::Gateway => std::slice::from_ref(&self.id),
+                    NodeType::Constrained => clusters.gateways(self.id),
+                };
+                for gateway in gateways {
+                    devices_vec.extend(
+                        clusters
+                            .members(*gateway)
+                            .iter()
+                            .filter(|id| **id != self.id),
+                    );
+                }
+                // Count devices without duplicates
+                devices_vec.sort_unstable();
+                devices_vec.dedup();
+                devices_vec.len() as u32
+            }
+        }
+    }
 }
@@ -0,0 +1,649 @@
+use std::collections::VecDeque;
+
+use rand::{seq::index, Rng};
+use serde::Deserialize;
+
+use crate::{
+    methods::RekeyingScheme,
+    rng::{derive_rng, LIFETIME_STREAM},
+    InvolvedExchangesCount, MetricsType, NodeStatus, NodeType, NodesVec,
+};
+
+// How long the batteries of the constrained nodes last under a stream of rekeying events.
+// Gateways are mains powered.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct LifetimeConfig {
+    // Initial energy of every constrained node, in joules
+    pub capacity: f64,
+    // Time between two events, in hours
+    pub event_interval: f64,
+    // Live constrained nodes hit by every event, with a status drawn uniformly. Gateways are
+    // never hit, their rekeying would drain no battery.
+    pub affected_nodes: usize,
+    // Share of its capacity under which a node announces it is draining, e.g. 0.2. Draining then
+    // only comes from the batteries: the event stream draws compromised and leaving nodes, and
+    // every node crossing the threshold triggers one draining rekey, whose cost may push other
+    // nodes across it in turn.
+    pub low_battery: Option<f64>,
+    // Shares of dead constrained nodes, in percent, whose first event is reported
+    pub dead_percentages: Vec<f64>,
+    // The run stops there even if some milestones are not reached
+    pub max_events: usize,
+}
+
+// Events after which each milestone was reached, none when it was not within the run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lifetime {
+    pub scheme: String,
+    pub events: usize,
+    pub first_death: Option<usize>,
+    pub dead: Vec<(f64, Option<usize>)>,
+    // A constrained node that could reach a gateway at the start is cut from all of them
+    pub partition: Option<usize>,
+    // Energy taken from the batteries, in joules, and the part of it spent on cryptographic
+    // operations
+    pub energy: f64,
+    pub computation: f64,
+    pub first_drain: Option<usize>,
+    // Draining rekeys triggered by low batteries, and the most set off by a single event
+    pub drains: usize,
+    pub largest_cascade: usize,
+}
+
+impl Default for LifetimeConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 0.5,
+            event_interval: 1.0,
+            affected_nodes: 1,
+            low_battery: None,
+            dead_percentages: vec![10.0, 50.0],
+            max_events: 1_000_000,
+        }
+    }
+}
+
+impl LifetimeConfig {
+    pub fn validate(&self) -> Result<(), (&'static str, String)> {
+        for (field, value) in [
+            ("lifetime.capacity", self.capacity),
+            ("lifetime.event_interval", self.event_interval),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err((field, format!("{} is not a positive number", value)));
+            }
+        }
+        if self.affected_nodes == 0 {
+            return Err((
+                "lifetime.affected_nodes",
+                "must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(threshold) = self.low_battery {
+            if !(0.0..1.0).contains(&threshold) {
+                return Err((
+                    "lifetime.low_battery",
+                    format!("{} is not in [0, 1[", threshold),
+                ));
+            }
+        }
+        if let Some(percentage) = self
+            .dead_percentages
+            .iter()
+            .find(|percentage| !(**percentage > 0.0 && **percentage <= 100.0))
+        {
+            return Err((
+                "lifetime.dead_percentages",
+                format!("{} is not in ]0, 100]", percentage),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn hours(&self, events: usize) -> f64 {
+        events as f64 * self.event_interval
+    }
+}
+
+// Run one scheme until every milestone is reached. The events only depend on the seed and on
+// the nodes still alive, so every scheme starts from the same draws.
+pub fn simulate_lifetime(
+    nodes: &NodesVec,
+    scheme: &dyn RekeyingScheme,
+    config: &LifetimeConfig,
+    seed: u64,
+) -> Lifetime {
+    let mut nodes = nodes.clone();
+    nodes.reset();
+    nodes.set_capacity(NodeType::Constrained, config.capacity);
+    let metrics = scheme.metrics();
+    let mut rng = derive_rng(seed, &[LIFETIME_STREAM]);
+    let constrained = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained)
+        .count();
+    let reachable = reaching_gateways(&nodes);
+    let mut alive: Vec<usize> = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained)
+        .map(|node| node.id)
+        .collect();
+    let mut lifetime = Lifetime {
+        scheme: scheme.name().to_string(),
+        events: 0,
+        first_death: None,
+        dead: config
+            .dead_percentages
+            .iter()
+            .map(|percentage| (*percentage, None))
+            .collect(),
+        partition: None,
+        energy: 0.0,
+        computation: 0.0,
+        first_drain: None,
+        drains: 0,
+        largest_cascade: 0,
+    };
+    let mut dead = 0;
+    let statuses: &[NodeStatus] = match config.low_battery {
+        Some(_) => &[NodeStatus::Compromised, NodeStatus::Leaving],
+        None => &NodeStatus::ALL,
+    };
+    let mut drained = vec![false; nodes.len()];
+
+    // Milestones left once no battery can drain any more cannot be reached: every constrained
+    // node is dead, or every live one is hit by each event and none is left to take part
+    while lifetime.events < config.max_events
+        && !lifetime.is_complete()
+        && alive.len() > config.affected_nodes
+    {
+        lifetime.events += 1;
+        let status = statuses[rng.gen_range(0..statuses.len())];
+        let affected: Vec<usize> = index::sample(
+            &mut rng,
+            alive.len(),
+            config.affected_nodes.min(alive.len()),
+        )
+        .into_iter()
+        .map(|index| alive[index])
+        .collect();
+        let (deaths, cascade) = rekey(
+            &mut nodes,
+            (status, affected),
+            &metrics,
+            config.low_battery,
+            &mut drained,
+            &mut lifetime,
+        );
+        dead += deaths;
+        if cascade > 0 {
+            lifetime.first_drain.get_or_insert(lifetime.events);
+            lifetime.drains += cascade;
+            lifetime.largest_cascade = lifetime.largest_cascade.max(cascade);
+        }
+        if deaths == 0 {
+            continue;
+        }
+        alive.retain(|id| nodes[*id].is_alive());
+        if !nodes.routes().is_empty() {
+            let routes = nodes.routes().rebuild(&nodes);
+            nodes.set_routes(routes);
+        }
+        lifetime.first_death.get_or_insert(lifetime.events);
+        for (percentage, event) in lifetime.dead.iter_mut() {
+            if event.is_none() && dead as f64 >= *percentage / 100.0 * constrained as f64 {
+                *event = Some(lifetime.events);
+            }
+        }
+        if lifetime.partition.is_none() {
+            let now = reaching_gateways(&nodes);
+            if (0..nodes.len()).any(|id| reachable[id] && nodes[id].is_alive() && !now[id]) {
+                lifetime.partition = Some(lifetime.events);
+            }
+        }
+    }
+    lifetime
+}
+
+// Rekey the network after an event, then once more for every node the debits push under the
+// low battery threshold, which announces it is draining. Returns the nodes that died and the
+// draining rekeys that were run.
+fn rekey(
+    nodes: &mut NodesVec,
+    event: (NodeStatus, Vec<usize>),
+    metrics: &MetricsType,
+    low_battery: Option<f64>,
+    drained: &mut [bool],
+    lifetime: &mut Lifetime,
+) -> (usize, usize) {
+    let mut events = VecDeque::from([event]);
+    let mut deaths = 0;
+    let mut rekeys: usize = 0;
+    while let Some((status, affected)) = events.pop_front() {
+        // A node that went low and then died within the cascade has nothing left to announce
+        let affected: Vec<usize> = affected
+            .into_iter()
+            .filter(|id| nodes[*id].is_alive())
+            .collect();
+        if affected.is_empty() {
+            continue;
+        }
+        rekeys += 1;
+        nodes.affect_node_ids(status, &affected);
+        let radio = energy_debits(nodes, status, metrics);
+        let computation = computation_debits(nodes, status, metrics);
+        nodes.reset();
+
+        let debits = radio
+            .into_iter()
+            .map(|(id, energy)| (id, energy, false))
+            .chain(
+                computation
+                    .into_iter()
+                    .map(|(id, energy)| (id, energy, true)),
+            );
+        for (id, energy, computed) in debits {
+            if !nodes[id].is_alive() {
+                continue;
+            }
+            let spent = energy.min(nodes[id].remaining);
+            lifetime.energy += spent;
+            if computed {
+                lifetime.computation += spent;
+            }
+            if nodes.debit(id, energy) {
+                deaths += 1;
+            }
+            // A node emptied at once has no time left to announce it
+            if low_battery.is_some_and(|threshold| nodes[id].is_low_battery(threshold))
+                && !drained[id]
+            {
+                drained[id] = true;
+                events.push_back((NodeStatus::Draining, vec![id]));
+            }
+        }
+    }
+    // Every rekey after the one of the event itself was a draining one
+    (deaths, rekeys.saturating_sub(1))
+}
+
+impl Lifetime {
+    fn is_complete(&self) -> bool {
+        self.first_death.is_some()
+            && self.partition.is_some()
+            && self.dead.iter().all(|(_, event)| event.is_some())
+    }
+}
+
+// Energy every node spends on the rekeying once the nodes flagged with `status` are removed,
+// following the constrained cells of a scheme: each active device involved with an affected node
+// pays for its own messages, and the relays on its route for forwarding the ones exchanged with
+// the gateway.
+pub fn energy_debits(
+    nodes: &NodesVec,
+    status: NodeStatus,
+    metrics: &MetricsType,
+) -> Vec<(usize, f64)> {
+    let (state, sizes) = match status {
+        NodeStatus::Compromised => (
+            metrics.energy.compromised.constrained,
+            metrics.communication.compromised.constrained,
+        ),
+        NodeStatus::Leaving => (
+            metrics.energy.leaving.constrained,
+            metrics.communication.leaving.constrained,
+        ),
+        NodeStatus::Draining => (
+            metrics.energy.draining.constrained,
+            metrics.communication.draining.constrained,
+        ),
+    };
+    let routes = nodes.routes();
+    let routed = (
+        state.exchange.messages.0 != InvolvedExchangesCount::Neighbors,
+        state.exchange.messages.1 != InvolvedExchangesCount::Neighbors,
+    );
+    let mut debits = vec![];
+    for node in nodes.iter().filter(|node| node.has_only_status(status)) {
+        if node.kind != NodeType::Constrained {
+            continue;
+        }
+        let sent =
+            node.calculate_involved_messages(nodes, state.exchange.messages.0, state.exchange.sent)
+                as f64;
+        let received = node.calculate_involved_messages(
+            nodes,
+            state.exchange.messages.1,
+            state.exchange.received,
+        ) as f64;
+        for device in nodes.involved_devices(
+            node,
+            state.involved_devices,
+            state.number_of_involved_devices,
+        ) {
+            match routes.radio() {
+                None => {
+                    let cost = state.exchange_cost;
+                    debits.push((
+                        device,
+                        sent * cost.sent as f64 + received * cost.received as f64,
+                    ));
+                    let forwarded =
+                        if routed.0 { sent } else { 0.0 } + if routed.1 { received } else { 0.0 };
+                    for relay in routes.relays(device) {
+                        debits.push((*relay, forwarded * (cost.sent + cost.received) as f64));
+                    }
+                }
+                Some(radio) => {
+                    // A direction whose energy cost is zero stays free, as in the event cost
+                    if state.exchange_cost.sent != 0.0 {
+                        let bits = sent * sizes.exchange_cost.sent as f64 * 8.0;
+                        debits.push((device, bits * routes.first_hop(device)));
+                        if routed.0 {
+                            for (relay, energy) in routes.forwarding(nodes, device, true) {
+                                debits.push((relay, bits * energy));
+                            }
+                        }
+                    }
+                    if state.exchange_cost.received != 0.0 {
+                        let bits = received * sizes.exchange_cost.received as f64 * 8.0;
+                        debits.push((device, bits * radio.receive()));
+                        if routed.1 {
+                            for (relay, energy) in routes.forwarding(nodes, device, false) {
+                                debits.push((relay, bits * energy));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    debits
+}
+
+// Energy every node spends on the cryptographic operations of the rekeying: each active device
+// involved with an affected node performs those of the constrained cell, the node itself those
+// of the left cell. The gateways are mains powered.
+pub fn computation_debits(
+    nodes: &NodesVec,
+    status: NodeStatus,
+    metrics: &MetricsType,
+) -> Vec<(usize, f64)> {
+    let cells = match status {
+        NodeStatus::Compromised => metrics.energy.compromised,
+        NodeStatus::Leaving => metrics.energy.leaving,
+        NodeStatus::Draining => metrics.energy.draining,
+    };
+    let (state, left) = (cells.constrained, cells.left);
+    let mut debits = vec![];
+    if state.computation_cost == 0.0 && left.computation_cost == 0.0 {
+        return debits;
+    }
+    for node in nodes.iter().filter(|node| node.has_only_status(status)) {
+        if node.kind != NodeType::Constrained {
+            continue;
+        }
+        if state.computation_cost != 0.0 {
+            for device in nodes.involved_devices(
+                node,
+                state.involved_devices,
+                state.number_of_involved_devices,
+            ) {
+                debits.push((device, state.computation_cost));
+            }
+        }
+        if left.computation_cost != 0.0 {
+            let devices = nodes.involved_devices(
+                node,
+                left.involved_devices,
+                left.number_of_involved_devices,
+            );
+            debits.push((node.id, devices.len() as f64 * left.computation_cost));
+        }
+    }
+    debits
+}
+
+// Live nodes with a path of live nodes to a gateway
+fn reaching_gateways(nodes: &NodesVec) -> Vec<bool> {
+    let mut reached = vec![false; nodes.len()];
+    let mut queue: VecDeque<usize> = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Gateway && node.is_alive())
+        .map(|node| node.id)
+        .collect();
+    for gateway in queue.iter() {
+        reached[*gateway] = true;
+    }
+    while let Some(node) = queue.pop_front() {
+        for neighbor in nodes.neighbors(node) {
+            if !reached[*neighbor] && nodes[*neighbor].is_alive() {
+                reached[*neighbor] = true;
+                queue.push_back(*neighbor);
+            }
+        }
+    }
+    reached
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        methods::SchemeRegistry,
+        routing::{busiest_relay, Routes, RoutingStrategy},
+        scenario::Scenario,
+        topology, MetricsFor, TotalEnergyConsumption,
+    };
+
+    fn scenario(low_battery: Option<f64>) -> Scenario {
+        let mut scenario: Scenario = toml::from_str(
+            r#"
+            schemes = ["bkrsc", "others"]
+
+            [topology]
+            number_of_nodes = 12
+            number_of_gateways = 2
+            number_of_min_possible_neighbors = 2
+            number_of_max_possible_neighbors = 4
+            number_of_gateway_members = 5
+
+            [lifetime]
+            capacity = 0.0005
+            dead_percentages = [10.0, 100.0]
+            "#,
+        )
+        .unwrap();
+        scenario.lifetime.low_battery = low_battery;
+        scenario.validate().unwrap();
+        scenario
+    }
+
+    fn network(scenario: &Scenario, seed: u64) -> NodesVec {
+        let mut nodes = topology::generate(&scenario.topology, seed).unwrap();
+        let routes = Routes::build(&nodes, &scenario.routing, &scenario.costs.radio);
+        nodes.set_routes(routes);
+        nodes
+    }
+
+    // The last live nodes are hit by every event and count no one else among the active ones.
+    // Nodes that no scheme involves any more keep their battery, hence the bounded run.
+    #[test]
+    fn every_scheme_runs_down_to_the_last_nodes() {
+        for low_battery in [None, Some(0.2)] {
+            let mut scenario = scenario(low_battery);
+            scenario.lifetime.max_events = 20_000;
+            let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+            let nodes = network(&scenario, 7);
+            for scheme in registry.iter() {
+                let lifetime = simulate_lifetime(&nodes, scheme, &scenario.lifetime, 7);
+                assert!(lifetime.dead[0].1.is_some(), "{}", scheme.name());
+                assert!(lifetime.first_death <= lifetime.dead[1].1.or(Some(usize::MAX)));
+            }
+        }
+    }
+
+    // The last live nodes all hit at once leave no active node to exchange with
+    #[test]
+    fn every_constrained_node_affected_at_once() {
+        let scenario = scenario(None);
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let mut nodes = network(&scenario, 7);
+        let constrained: Vec<usize> = nodes
+            .iter()
+            .filter(|node| node.kind == NodeType::Constrained)
+            .map(|node| node.id)
+            .collect();
+        for scheme in registry.iter() {
+            for status in NodeStatus::ALL {
+                nodes.affect_node_ids(status, &constrained);
+                let metrics = scheme.metrics();
+                let cost = scheme.event_cost(&nodes, status);
+                assert!(cost.energy.is_finite() && cost.communication.is_finite());
+                assert!(energy_debits(&nodes, status, &metrics)
+                    .iter()
+                    .all(|(_, energy)| energy.is_finite()));
+                nodes.reset();
+            }
+        }
+    }
+
+    // Without routes the batteries of the cluster members pay what the run command counts for
+    // the constrained nodes
+    #[test]
+    fn gateway_member_debits_add_up_to_the_constrained_totals() {
+        let mut scenario = scenario(None);
+        scenario.topology.clusters.memberships = Some(2);
+        scenario.topology.clusters.strategy = topology::clusters::ClusterStrategy::Nearest;
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let mut nodes = topology::generate(&scenario.topology, 7).unwrap();
+        let scheme = registry.get("bkrsc").unwrap();
+        let metrics = scheme.metrics();
+        for status in NodeStatus::ALL {
+            for id in [2, 5, 11] {
+                nodes.affect_node_ids(status, &[id]);
+                let total =
+                    nodes.total_energy_consumption(status, MetricsFor::Constrained, metrics) as f64;
+                let debits: f64 = energy_debits(&nodes, status, &metrics)
+                    .iter()
+                    .map(|(_, energy)| energy)
+                    .sum();
+                assert!(total > 0.0 && (total - debits).abs() <= 1e-6 * total);
+                nodes.reset();
+            }
+        }
+    }
+
+    // What the relays forward is what the routes add to the debits of direct messages
+    #[test]
+    fn busiest_relay_pays_the_largest_forwarding_debit() {
+        let mut scenario = scenario(None);
+        scenario.routing.strategy = RoutingStrategy::ShortestPath;
+        scenario.topology.clusters.strategy = topology::clusters::ClusterStrategy::Nearest;
+        scenario.topology.number_of_gateways = 1;
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let mut direct = topology::generate(&scenario.topology, 7).unwrap();
+        let mut routed = network(&scenario, 7);
+        let scheme = registry.get("bkrsc").unwrap();
+        let metrics = scheme.metrics();
+        let mut relayed = false;
+        for status in NodeStatus::ALL {
+            for id in [2, 5, 11] {
+                let mut forwarded = vec![0.0; routed.len()];
+                for (nodes, sign) in [(&mut routed, 1.0), (&mut direct, -1.0)] {
+                    nodes.affect_node_ids(status, &[id]);
+                    for (device, energy) in energy_debits(nodes, status, &metrics) {
+                        forwarded[device] += sign * energy;
+                    }
+                }
+                let busiest = forwarded.into_iter().fold(0.0, f64::max);
+                routed.affect_node_ids(status, &[id]);
+                let relay = busiest_relay(&routed, status, &metrics) as f64;
+                assert!((relay - busiest).abs() <= 1e-6 * busiest);
+                relayed |= relay > 0.0;
+                routed.reset();
+                direct.reset();
+            }
+        }
+        assert!(relayed);
+    }
+
+    // A device paying for two affected nodes crosses the threshold on the first debit and dies
+    // on the second: its draining is never announced, so no one pays for it
+    #[test]
+    fn node_dying_within_the_cascade_does_not_drain() {
+        let scenario = scenario(Some(0.5));
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let metrics = registry.get("others").unwrap().metrics();
+        let mut nodes = network(&scenario, 7);
+        nodes.set_capacity(NodeType::Constrained, 1.0);
+        let status = NodeStatus::Compromised;
+        let affected = vec![2, 5];
+        nodes.affect_node_ids(status, &affected);
+        let debits = energy_debits(&nodes, status, &metrics);
+        nodes.reset();
+        let device = debits
+            .iter()
+            .map(|(id, _)| *id)
+            .find(|id| debits.iter().filter(|(other, _)| other == id).count() >= 2)
+            .unwrap();
+        let mut energies = debits
+            .iter()
+            .filter(|(id, _)| *id == device)
+            .map(|(_, energy)| *energy);
+        let (first, second) = (energies.next().unwrap(), energies.next().unwrap());
+        // Low but alive after the first debit, dead after the second one
+        let remaining = first + second / 2.0;
+        nodes.debit(device, 1.0 - remaining);
+
+        let mut lifetime = Lifetime {
+            scheme: "others".to_string(),
+            events: 1,
+            first_death: None,
+            dead: vec![],
+            partition: None,
+            energy: 0.0,
+            computation: 0.0,
+            first_drain: None,
+            drains: 0,
+            largest_cascade: 0,
+        };
+        let mut drained = vec![false; nodes.len()];
+        let (deaths, drains) = rekey(
+            &mut nodes,
+            (status, affected),
+            &metrics,
+            Some(0.5),
+            &mut drained,
+            &mut lifetime,
+        );
+        assert!(drained[device] && !nodes[device].is_alive());
+        assert_eq!((deaths, drains), (1, 0));
+        let others: f64 = debits
+            .iter()
+            .filter(|(id, _)| *id != device)
+            .map(|(_, energy)| energy)
+            .sum();
+        assert!((lifetime.energy - others - remaining).abs() <= 1e-9);
+    }
+
+    #[test]
+    fn events_only_hit_constrained_nodes() {
+        let mut scenario = scenario(None);
+        scenario.lifetime.capacity = 1e9;
+        scenario.lifetime.max_events = 200;
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let nodes = network(&scenario, 7);
+        let lifetime = simulate_lifetime(
+            &nodes,
+            registry.iter().next().unwrap(),
+            &scenario.lifetime,
+            7,
+        );
+        // Gateway events cost no battery, every event here drains some
+        assert_eq!(lifetime.events, 200);
+        assert!(lifetime.first_death.is_none());
+        assert!(lifetime.energy > 0.0);
+    }
+}
@@ -1,412 +1,584 @@
-#![allow(unused_imports)]
-use std::env;
+mod cli;
 
-use dotenv::dotenv;
-// use NodesVec from lib.rs
+use std::{
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+use cli::{
+    Command, CompareOptions, ExportOptions, LifetimeOptions, ReplayOptions, RunOptions,
+    ScenarioOptions, SweepOptions, TopologyOptions, USAGE,
+};
 use iot_metrics_simulation::{
-    initialize_network,
-    methods::{bkrsc::get_metrics as bkrsc_get_metrics, others::get_metrics as others_get_metrics},
-    CommunicationOverheadType, CommunicationType, EnergyConsumptionType, EnergyType,
-    ExchangeCostType, ExchangeType, MetricsFor, MetricsType, NodeStatus, NodesVec, StateCostType,
-    TotalCommunicationOverhead, TotalEnergyConsumption,
+    comparison::{compare_schemes, SchemeComparison},
+    events::EventSet,
+    export::{aggregated_records, raw_records, write_records, ExportFormat},
+    lifetime::simulate_lifetime,
+    methods::{EventCost, SchemeRegistry},
+    rng::{derive_seed, random_seed, REPETITION_STREAM},
+    routing::Routes,
+    scenario::Scenario,
+    simulation::{replay, simulate, SimulationResults},
+    sweep::{Sweep, SweepMode, SweepParameter},
+    topology::{
+        self,
+        analysis::{self, TopologyReport},
+        io::{write_network, NetworkFormat},
+        DegreeDistribution,
+    },
+    NodeStatus, NodeType, NodesVec,
 };
 
 fn main() {
-    dotenv().ok();
-    let number_of_nodes = env::var("NUMBER_OF_NODES")
-        .expect("NUMBER_OF_NODES must be set")
-        .parse::<i32>()
-        .expect("NUMBER_OF_NODES must be a number");
-    let number_of_gateways = env::var("NUMBER_OF_GATEWAYS")
-        .expect("NUMBER_OF_GATEWAYS must be set")
-        .parse::<i32>()
-        .expect("NUMBER_OF_GATEWAYS must be a number");
-    let number_of_min_possible_neighbors = env::var("NUMBER_OF_MIN_POSSIBLE_NEIGHBORS")
-        .expect("NUMBER_OF_MIN_POSSIBLE_NEIGHBORS must be set")
-        .parse::<i32>()
-        .expect("NUMBER_OF_MIN_POSSIBLE_NEIGHBORS must be a number");
-    let number_of_max_possible_neighbors = env::var("NUMBER_OF_MAX_POSSIBLE_NEIGHBORS")
-        .expect("NUMBER_OF_MAX_POSSIBLE_NEIGHBORS must be set")
-        .parse::<i32>()
-        .expect("NUMBER_OF_MAX_POSSIBLE_NEIGHBORS must be a number");
-    let number_of_gateway_members = env::var("NUMBER_OF_GATEWAY_MEMBERS")
-        .expect("NUMBER_OF_GATEWAY_MEMBERS must be set")
-        .parse::<i32>()
-        .expect("NUMBER_OF_GATEWAY_MEMBERS must be a number");
-    let vec: NodesVec = initialize_network(
-        number_of_nodes,
-        number_of_gateways,
-        number_of_min_possible_neighbors,
-        number_of_max_possible_neighbors,
-    );
-    simulate(
-        vec,
-        number_of_nodes,
-        number_of_gateway_members,
-        (number_of_min_possible_neighbors + number_of_max_possible_neighbors) / 2 as i32,
-    );
+    let command = match cli::parse(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    match command {
+        Command::Run(options) => run(&options),
+        Command::Sweep(options) => sweep(&options),
+        Command::Topology(options) => topology(&options),
+        Command::Analyze(options) => analyze(&options),
+        Command::Compare(options) => compare(&options),
+        Command::Replay(options) => replay_events(&options),
+        Command::Lifetime(options) => lifetime(&options),
+        Command::Help => println!("{}", USAGE),
+    }
 }
 
-fn simulate(
-    mut vec: NodesVec,
-    number_of_nodes: i32,
-    number_of_gateway_members: i32,
-    number_of_neighbors: i32,
-) {
-    let iterations = 1000;
-    let min_affected_nodes = 1;
-    let max_affected_nodes = 10;
-
-    let mut bkrsc_compromised_results: Vec<Vec<(f32, f32)>> = vec![];
-    let mut others_compromised_results: Vec<Vec<(f32, f32)>> = vec![];
-    
-    let mut bkrsc_leaving_results: Vec<Vec<(f32, f32)>> = vec![];
-    let mut others_leaving_results: Vec<Vec<(f32, f32)>> = vec![];
-
-    let mut bkrsc_draining_results: Vec<Vec<(f32, f32)>> = vec![];
-    let mut others_draining_results: Vec<Vec<(f32, f32)>> = vec![];
-
-    let mut bkrsc_compromised_average: Vec<(f32, f32)> = vec![];
-    let mut others_compromised_average: Vec<(f32, f32)> = vec![];
-    let mut bkrsc_leaving_average: Vec<(f32, f32)> = vec![];
-    let mut others_leaving_average: Vec<(f32, f32)> = vec![];
-    let mut bkrsc_draining_average: Vec<(f32, f32)> = vec![];
-    let mut others_draining_average: Vec<(f32, f32)> = vec![];
-
-    let bkrsc_metrics = bkrsc_get_metrics(
-        number_of_nodes as u32,
-        number_of_gateway_members as u32,
-        number_of_neighbors as u32,
-    );
-    let others_metrics = others_get_metrics(
-        number_of_nodes as u32,
-        number_of_gateway_members as u32,
-        number_of_neighbors as u32,
-    );
-
-    for iteration in 0..iterations {
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
 
-        bkrsc_compromised_results.push(vec![]);
-        others_compromised_results.push(vec![]);
-        bkrsc_leaving_results.push(vec![]);
-        others_leaving_results.push(vec![]);
-        bkrsc_draining_results.push(vec![]);
-        others_draining_results.push(vec![]);
+// Load the scenario with the command line overrides and settle the master seed. The command line
+// seed wins over the scenario one; without either a fresh seed is drawn and reported so the run
+// can still be reproduced
+fn load_scenario(options: &ScenarioOptions) -> (Scenario, u64) {
+    let mut scenario = match options.load() {
+        Ok(scenario) => scenario,
+        Err(err) => exit_with_error(&err.to_string()),
+    };
+    let seed = scenario.simulation.seed.unwrap_or_else(random_seed);
+    scenario.simulation.seed = Some(seed);
+    (scenario, seed)
+}
 
-        for i in min_affected_nodes..=max_affected_nodes {
-            vec.compromise_nodes(i);
-            
-            println!(
-                "COMPROMISED-BKRSC: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
-            println!(
-                "COMPROMISED-BKRSC: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
+fn validate(scenario: &Scenario) {
+    if let Err(err) = scenario.validate() {
+        exit_with_error(&err.to_string());
+    }
+}
 
-            println!(
-                "COMPROMISED-OTHERS: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
-            println!(
-                "COMPROMISED-OTHERS: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
+fn generate_network(scenario: &Scenario, seed: u64) -> NodesVec {
+    match topology::generate(&scenario.topology, seed) {
+        Ok(nodes) => nodes,
+        Err(err) => exit_with_error(&format!("cannot generate the network: {}", err)),
+    }
+}
 
-            println!(
-                "bkrsc compromised: {:?}",
-                bkrsc_compromised_average
-            );
+// The network to simulate with its routes, refused when it does not connect as the scenario
+// requires
+fn build_network(scenario: &Scenario, seed: u64) -> NodesVec {
+    let mut nodes = generate_network(scenario, seed);
+    let connectivity = scenario.topology.connectivity;
+    if let Err(err) = analysis::check(&nodes, connectivity) {
+        exit_with_error(&format!(
+            "the network does not meet connectivity = \"{}\": {}",
+            connectivity.label(),
+            err
+        ));
+    }
+    let routes = Routes::build(&nodes, &scenario.routing, &scenario.costs.radio);
+    nodes.set_routes(routes);
+    nodes
+}
 
-            bkrsc_compromised_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-            ));
+fn build_registry(scenario: &Scenario) -> SchemeRegistry {
+    match SchemeRegistry::from_scenario(scenario) {
+        Ok(registry) => registry,
+        Err(err) => exit_with_error(&err.to_string()),
+    }
+}
 
-            others_compromised_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Compromised,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-            ));
+fn simulate_scenario(scenario: &Scenario, seed: u64) -> SimulationResults {
+    let registry = build_registry(scenario);
+    let vec = build_network(scenario, seed);
+    simulate(&vec, &registry, &scenario.simulation, seed)
+}
 
-            vec.reset();
+fn run(options: &RunOptions) {
+    let (scenario, seed) = load_scenario(&options.scenario);
+    validate(&scenario);
+    let results = simulate_scenario(&scenario, seed);
+    if !options.export.writes_to_stdout() {
+        print_results(&results, Columns::of(&scenario));
+    }
+    export(&options.export, &[(results, scenario)]);
+}
 
-            vec.leave_nodes(i);
+// Write the result files requested on the command line, with the records of every run in the
+// same file
+fn export(options: &ExportOptions, runs: &[(SimulationResults, Scenario)]) {
+    let format_of = |path: &Path| {
+        options
+            .format
+            .unwrap_or_else(|| ExportFormat::from_path(path))
+    };
+    let mut written = Ok(());
+    if let Some(path) = &options.raw {
+        let records: Vec<_> = runs
+            .iter()
+            .flat_map(|(results, scenario)| raw_records(results, scenario))
+            .collect();
+        written = written.and_then(|_| write_records(path, format_of(path), &records));
+    }
+    if let Some(path) = &options.aggregated {
+        let records: Vec<_> = runs
+            .iter()
+            .flat_map(|(results, scenario)| aggregated_records(results, scenario))
+            .collect();
+        written = written.and_then(|_| write_records(path, format_of(path), &records));
+    }
+    if let Err(err) = written {
+        exit_with_error(&err.to_string());
+    }
+}
 
-            println!(
-                "LEAVING-BKRSC: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
-            println!(
-                "LEAVING-BKRSC: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
+// Run every point of the sweep with the same master seed, so the points only differ by their
+// parameters, and report them all in one table
+fn sweep(options: &SweepOptions) {
+    let (mut scenario, seed) = load_scenario(&options.scenario);
+    // Command line values replace the scenario ones given for the same parameter
+    for (parameter, values) in options.parameters.iter() {
+        scenario
+            .sweep
+            .parameters
+            .retain(|name, _| SweepParameter::from_name(name) != Some(*parameter));
+        scenario
+            .sweep
+            .parameters
+            .insert(parameter.name().to_string(), values.clone());
+    }
+    if let Some(samples) = options.latin_hypercube {
+        scenario.sweep.mode = SweepMode::LatinHypercube;
+        scenario.sweep.samples = Some(samples);
+    }
+    let sweep = match Sweep::new(&scenario.sweep) {
+        Ok(sweep) => sweep,
+        Err(err) => exit_with_error(&err.to_string()),
+    };
+    let points = sweep.points(&scenario, seed);
+    // Check the whole grid before running anything, an invalid point should not be discovered
+    // after hours of simulation
+    for point in points.iter() {
+        if let Err(err) = point.scenario.validate() {
+            exit_with_error(&format!("{} ({})", err, point.describe()));
+        }
+    }
 
-            println!(
-                "LEAVING-OTHERS: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
-            println!(
-                "LEAVING-OTHERS: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
+    let mut runs = vec![];
+    for (index, point) in points.iter().enumerate() {
+        eprintln!("point {}/{}: {}", index + 1, points.len(), point.describe());
+        let results = simulate_scenario(&point.scenario, seed);
+        runs.push((results, point.scenario.clone()));
+    }
+    let mut export_options = options.export.clone();
+    if export_options.aggregated.is_none() {
+        export_options.aggregated = Some(PathBuf::from("-"));
+    }
+    export(&export_options, &runs);
+}
 
-            bkrsc_leaving_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-            ));
+fn describe_topology(scenario: &Scenario) -> String {
+    let topology = &scenario.topology;
+    format!(
+        "model={} nodes={} gateways={} min_neighbors={} max_neighbors={} gateway_members={}",
+        topology.model.label(),
+        topology.number_of_nodes,
+        topology.number_of_gateways,
+        topology.number_of_min_possible_neighbors,
+        topology.number_of_max_possible_neighbors,
+        topology.number_of_gateway_members
+    )
+}
 
-            others_leaving_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Leaving,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-            ));
+// Cluster sizes and constrained nodes left without a gateway
+fn describe_clusters(scenario: &Scenario, nodes: &NodesVec) -> String {
+    let clusters = nodes.clusters();
+    let sizes: Vec<usize> = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Gateway)
+        .map(|gateway| clusters.members(gateway.id).len())
+        .collect();
+    let unassigned = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained)
+        .filter(|node| clusters.gateways(node.id).is_empty())
+        .count();
+    format!(
+        "strategy={} members min={} max={} mean={} unassigned={}",
+        scenario.topology.clusters.strategy.label(),
+        sizes.iter().min().unwrap_or(&0),
+        sizes.iter().max().unwrap_or(&0),
+        sizes.iter().sum::<usize>() as f64 / sizes.len().max(1) as f64,
+        unassigned
+    )
+}
 
-            vec.reset();
+// Export the generated network, as an adjacency list by default: one node per line with its id,
+// kind and the comma separated ids of its neighbors
+fn topology(options: &TopologyOptions) {
+    let (scenario, seed) = load_scenario(&options.scenario);
+    validate(&scenario);
+    let vec = build_network(&scenario, seed);
+
+    let format = options.format.unwrap_or_else(|| {
+        options
+            .output
+            .as_deref()
+            .map(NetworkFormat::from_path)
+            .unwrap_or_default()
+    });
+    let description = format!(
+        "seed {}\n{}\ndegrees {}\nclusters {}",
+        seed,
+        describe_topology(&scenario),
+        DegreeDistribution::of(&vec),
+        describe_clusters(&scenario, &vec)
+    );
+    let contents = write_network(&vec, format, &description);
+
+    let written = match &options.output {
+        Some(path) => fs::write(path, contents)
+            .map_err(|err| format!("cannot write {}: {}", path.display(), err)),
+        None => io::stdout()
+            .write_all(contents.as_bytes())
+            .map_err(|err| err.to_string()),
+    };
+    if let Err(err) = written {
+        exit_with_error(&err);
+    }
+}
 
-            vec.drain_nodes(i);
+// Report the components, gateway reachability and graph metrics of the network, even when it
+// does not meet the required connectivity
+fn analyze(options: &ScenarioOptions) {
+    let (scenario, seed) = load_scenario(options);
+    validate(&scenario);
+    let vec = generate_network(&scenario, seed);
+    let connectivity = scenario.topology.connectivity;
+    println!("# seed {}", seed);
+    println!("# {}", describe_topology(&scenario));
+    println!("# clusters {}", describe_clusters(&scenario, &vec));
+    println!("{}", TopologyReport::of(&vec));
+    println!(
+        "routing strategy={} {}",
+        scenario.routing.strategy.label(),
+        Routes::build(&vec, &scenario.routing, &scenario.costs.radio)
+    );
+    println!(
+        "connectivity required={} met={}",
+        connectivity.label(),
+        analysis::check(&vec, connectivity).is_ok()
+    );
+}
 
-            println!(
-                "DRAINED-BKRSC: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
-            println!(
-                "DRAINED-BKRSC: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics
-                )
-            );
+// Run all schemes on several seeds and report their averages side by side. Each seed builds its
+// own network and events, shared by every scheme.
+fn compare(options: &CompareOptions) {
+    let (scenario, master_seed) = load_scenario(&options.scenario);
+    validate(&scenario);
+    let seeds: Vec<u64> = if options.seeds.is_empty() {
+        (0..options.repetitions as u64)
+            .map(|repetition| derive_seed(master_seed, &[REPETITION_STREAM, repetition]))
+            .collect()
+    } else {
+        options.seeds.clone()
+    };
+
+    let runs: Vec<SimulationResults> = seeds
+        .iter()
+        .map(|seed| simulate_scenario(&scenario, *seed))
+        .collect();
+
+    // Every scheme is tested against the baseline on the events they shared
+    let schemes = &runs[0].schemes;
+    let baseline = match &options.baseline {
+        Some(name) => match schemes.iter().position(|scheme| scheme == name) {
+            Some(index) => index,
+            None => exit_with_error(&format!(
+                "unknown baseline scheme \"{}\" (expected one of: {})",
+                name,
+                schemes.join(", ")
+            )),
+        },
+        None => schemes.len() - 1,
+    };
+    let comparisons: Vec<SchemeComparison> = (0..schemes.len())
+        .filter(|candidate| *candidate != baseline)
+        .flat_map(|candidate| compare_schemes(&runs, baseline, candidate))
+        .collect();
+
+    let comparison_to_stdout = options.comparison.as_deref() == Some(Path::new("-"));
+    if !options.export.writes_to_stdout() && !comparison_to_stdout {
+        print_comparison(&seeds, &runs, Columns::of(&scenario));
+        print_significance(&comparisons, Columns::of(&scenario));
+    }
+    if let Some(path) = &options.comparison {
+        let records: Vec<_> = comparisons
+            .iter()
+            .flat_map(|comparison| comparison.records())
+            .collect();
+        let format = options
+            .export
+            .format
+            .unwrap_or_else(|| ExportFormat::from_path(path));
+        if let Err(err) = write_records(path, format, &records) {
+            exit_with_error(&err.to_string());
+        }
+    }
+    let runs: Vec<_> = runs
+        .into_iter()
+        .map(|results| (results, scenario.clone()))
+        .collect();
+    export(&options.export, &runs);
+}
 
-            println!(
-                "DRAINED-OTHERS: Total energy consumption: {}",
-                vec.total_energy_consumption(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
-            println!(
-                "DRAINED-OTHERS: Total communication overhead: {}",
-                vec.total_communication_overhead(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    others_metrics
-                )
-            );
+// Value of a metric in the cost of an event
+type Metric = fn(&EventCost) -> f32;
 
-            bkrsc_draining_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    bkrsc_metrics,
-                ),
-            ));
+// Metrics shown besides energy, communication and latency: the computation when the scenario
+// costs the cryptographic operations, the busiest relay when messages are routed
+#[derive(Debug, Clone, Copy)]
+struct Columns {
+    computation: bool,
+    relay: bool,
+}
 
-            others_draining_results[iteration as usize].push((
-                vec.total_energy_consumption(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-                vec.total_communication_overhead(
-                    NodeStatus::Draining,
-                    MetricsFor::Constrained,
-                    others_metrics,
-                ),
-            ));
+impl Columns {
+    fn of(scenario: &Scenario) -> Self {
+        Self {
+            computation: scenario.costs.crypto.is_some(),
+            relay: scenario.routing.is_routed(&scenario.costs.radio),
+        }
+    }
 
-            vec.reset();
+    fn metrics(&self) -> Vec<(&'static str, Metric)> {
+        let mut metrics = vec![("energy", (|cost| cost.energy) as Metric)];
+        if self.computation {
+            metrics.push(("computation", |cost| cost.computation));
+        }
+        metrics.push(("communication", |cost| cost.communication));
+        metrics.push(("latency", |cost| cost.latency));
+        if self.relay {
+            metrics.push(("relay", |cost| cost.relay));
         }
+        metrics
     }
+}
 
-    // Calculate the average of the results of communication overhead and energy consumption
-    // Sum and average the same index of each iteration and divide by the number of iterations
-    // Push the result to the corresponding vector as tuple (energy consumption, communication overhead)
-    // The result variables are bkrsc_compromised_average, bkrsc_leaving_average, bkrsc_draining_average,
-    // others_compromised_average, others_leaving_average, others_draining_average
-    for i in min_affected_nodes - 1..max_affected_nodes {
-        let mut bkrsc_compromised_energy = 0.0;
-        let mut bkrsc_compromised_communication = 0.0;
-        let mut others_compromised_energy = 0.0;
-        let mut others_compromised_communication = 0.0;
-        let mut bkrsc_leaving_energy = 0.0;
-        let mut bkrsc_leaving_communication = 0.0;
-        let mut others_leaving_energy = 0.0;
-        let mut others_leaving_communication = 0.0;
-        let mut bkrsc_draining_energy = 0.0;
-        let mut bkrsc_draining_communication = 0.0;
-        let mut others_draining_energy = 0.0;
-        let mut others_draining_communication = 0.0;
-
-        for j in 0..iterations {
-            bkrsc_compromised_energy += bkrsc_compromised_results[j as usize][i as usize].0;
-            bkrsc_compromised_communication +=
-                bkrsc_compromised_results[j as usize][i as usize].1;
-            others_compromised_energy += others_compromised_results[j as usize][i as usize].0;
-            others_compromised_communication +=
-                others_compromised_results[j as usize][i as usize].1;
-            bkrsc_leaving_energy += bkrsc_leaving_results[j as usize][i as usize].0;
-            bkrsc_leaving_communication += bkrsc_leaving_results[j as usize][i as usize].1;
-            others_leaving_energy += others_leaving_results[j as usize][i as usize].0;
-            others_leaving_communication += others_leaving_results[j as usize][i as usize].1;
-            bkrsc_draining_energy += bkrsc_draining_results[j as usize][i as usize].0;
-            bkrsc_draining_communication += bkrsc_draining_results[j as usize][i as usize].1;
-            others_draining_energy += others_draining_results[j as usize][i as usize].0;
-            others_draining_communication += others_draining_results[j as usize][i as usize].1;
+fn print_comparison(seeds: &[u64], runs: &[SimulationResults], columns: Columns) {
+    let metrics = columns.metrics();
+    let first = &runs[0];
+    println!(
+        "SEEDS: {}",
+        seeds
+            .iter()
+            .map(|seed| seed.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    );
+    for status in NodeStatus::ALL {
+        println!("{}", status.label().to_uppercase());
+        let mut header = vec!["affected".to_string()];
+        for scheme in first.schemes.iter() {
+            for (metric, _) in metrics.iter() {
+                header.push(format!("{}-{}", scheme, metric));
+            }
         }
+        println!("{}", header.join(" "));
+        for affected in first.affected_counts() {
+            let mut row = vec![affected.to_string()];
+            for scheme_index in 0..first.schemes.len() {
+                let averages: Vec<EventCost> = runs
+                    .iter()
+                    .map(|results| results.average(scheme_index, status, affected))
+                    .collect();
+                for (_, value) in metrics.iter() {
+                    let total: f32 = averages.iter().map(value).sum();
+                    row.push((total / runs.len() as f32).to_string());
+                }
+            }
+            println!("{}", row.join(" "));
+        }
+    }
+}
 
-        bkrsc_compromised_average.push((
-            bkrsc_compromised_energy / iterations as f32,
-            bkrsc_compromised_communication / iterations as f32,
-        ));
-        others_compromised_average.push((
-            others_compromised_energy / iterations as f32,
-            others_compromised_communication / iterations as f32,
-        ));
-        bkrsc_leaving_average.push((
-            bkrsc_leaving_energy / iterations as f32,
-            bkrsc_leaving_communication / iterations as f32,
-        ));
-        others_leaving_average.push((
-            others_leaving_energy / iterations as f32,
-            others_leaving_communication / iterations as f32,
-        ));
-        bkrsc_draining_average.push((
-            bkrsc_draining_energy / iterations as f32,
-            bkrsc_draining_communication / iterations as f32,
-        ));
-        others_draining_average.push((
-            others_draining_energy / iterations as f32,
-            others_draining_communication / iterations as f32,
+// Regenerate the event set of one iteration and affected count and evaluate it again, e.g. to
+// inspect an outlier found in the raw results
+fn replay_events(options: &ReplayOptions) {
+    let (scenario, seed) = load_scenario(&options.scenario);
+    validate(&scenario);
+    let registry = build_registry(&scenario);
+    let mut vec = build_network(&scenario, seed);
+    if options.affected == 0 || options.affected > vec.len() {
+        exit_with_error(&format!(
+            "cannot affect {} nodes in a network of {} nodes",
+            options.affected,
+            vec.len()
         ));
     }
 
-    println!("BKRSC-COMPROMISED-ENERGY");
-    for i in 0..bkrsc_compromised_average.len() {
-        println!("({}, {})", i + 1, bkrsc_compromised_average[i].0);
-    }
-    println!("OTHERS-COMPROMISED-ENERGY");
-    for i in 0..others_compromised_average.len() {
-        println!("({}, {})", i + 1, others_compromised_average[i].0);
-    }
-    println!("BKRSC-COMPROMISED-COMMUNICATION");
-    for i in 0..bkrsc_compromised_average.len() {
-        println!("({}, {})", i + 1, bkrsc_compromised_average[i].1);
-    }
-    println!("OTHERS-COMPROMISED-COMMUNICATION");
-    for i in 0..others_compromised_average.len() {
-        println!("({}, {})", i + 1, others_compromised_average[i].1);
+    let metrics = Columns::of(&scenario).metrics();
+    let events = EventSet::generate(vec.len(), seed, options.iteration, options.affected);
+    println!("SEED: {}", seed);
+    println!("ITERATION: {}", events.iteration);
+    println!("AFFECTED: {}", events.affected);
+    for status in NodeStatus::ALL {
+        let nodes: Vec<String> = events
+            .nodes(status)
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        println!("{}: {}", status.label().to_uppercase(), nodes.join(","));
+        let costs = replay(&mut vec, &registry, &events, status);
+        for (scheme, cost) in registry.names().iter().zip(costs) {
+            let mut values = vec![scheme.to_string()];
+            for (_, value) in metrics.iter() {
+                values.push(value(&cost).to_string());
+            }
+            println!("{}", values.join(" "));
+        }
     }
+}
 
-    println!("BKRSC-LEAVING-ENERGY");
-    for i in 0..bkrsc_leaving_average.len() {
-        println!("({}, {})", i + 1, bkrsc_leaving_average[i].0);
-    }
-    println!("OTHERS-LEAVING-ENERGY");
-    for i in 0..others_leaving_average.len() {
-        println!("({}, {})", i + 1, others_leaving_average[i].0);
-    }
-    println!("BKRSC-LEAVING-COMMUNICATION");
-    for i in 0..bkrsc_leaving_average.len() {
-        println!("({}, {})", i + 1, bkrsc_leaving_average[i].1);
-    }
-    println!("OTHERS-LEAVING-COMMUNICATION");
-    for i in 0..others_leaving_average.len() {
-        println!("({}, {})", i + 1, others_leaving_average[i].1);
+// Drain the batteries of the same network once per scheme and report when each milestone was
+// reached, in events and hours ("-" when it was not within the run)
+fn lifetime(options: &LifetimeOptions) {
+    let (mut scenario, seed) = load_scenario(&options.scenario);
+    let config = &mut scenario.lifetime;
+    config.capacity = options.capacity.unwrap_or(config.capacity);
+    config.event_interval = options.event_interval.unwrap_or(config.event_interval);
+    config.low_battery = options.low_battery.or(config.low_battery);
+    config.max_events = options.max_events.unwrap_or(config.max_events);
+    validate(&scenario);
+    let registry = build_registry(&scenario);
+    let vec = build_network(&scenario, seed);
+    let config = &scenario.lifetime;
+
+    println!("SEED: {}", seed);
+    let low_battery = config
+        .low_battery
+        .map_or("none".to_string(), |threshold| threshold.to_string());
+    println!(
+        "# capacity={} event_interval={} affected_nodes={} low_battery={} max_events={}",
+        config.capacity,
+        config.event_interval,
+        config.affected_nodes,
+        low_battery,
+        config.max_events
+    );
+    println!("scheme milestone events hours");
+    for scheme in registry.iter() {
+        let lifetime = simulate_lifetime(&vec, scheme, config, seed);
+        let mut milestones = vec![
+            ("first_drain".to_string(), lifetime.first_drain),
+            ("first_death".to_string(), lifetime.first_death),
+        ];
+        for (percentage, event) in lifetime.dead.iter() {
+            milestones.push((format!("dead_{}%", percentage), *event));
+        }
+        milestones.push(("partition".to_string(), lifetime.partition));
+        for (milestone, event) in milestones {
+            match event {
+                Some(event) => println!(
+                    "{} {} {} {}",
+                    lifetime.scheme,
+                    milestone,
+                    event,
+                    config.hours(event)
+                ),
+                None => println!("{} {} - -", lifetime.scheme, milestone),
+            }
+        }
+        println!(
+            "# {} simulated_events={} energy_spent={} computation_spent={} draining_rekeys={} \
+             largest_cascade={}",
+            lifetime.scheme,
+            lifetime.events,
+            lifetime.energy,
+            lifetime.computation,
+            lifetime.drains,
+            lifetime.largest_cascade
+        );
     }
+}
 
-    println!("BKRSC-DRAINING-ENERGY");
-    for i in 0..bkrsc_draining_average.len() {
-        println!("({}, {})", i + 1, bkrsc_draining_average[i].0);
-    }
-    println!("OTHERS-DRAINING-ENERGY");
-    for i in 0..others_draining_average.len() {
-        println!("({}, {})", i + 1, others_draining_average[i].0);
-    }
-    println!("BKRSC-DRAINING-COMMUNICATION");
-    for i in 0..bkrsc_draining_average.len() {
-        println!("({}, {})", i + 1, bkrsc_draining_average[i].1);
+// One line per candidate, status, affected count and metric. A positive saving means the
+// candidate is cheaper than the baseline.
+fn print_significance(comparisons: &[SchemeComparison], columns: Columns) {
+    if comparisons.is_empty() {
+        return;
     }
-    println!("OTHERS-DRAINING-COMMUNICATION");
-    for i in 0..others_draining_average.len() {
-        println!("({}, {})", i + 1, others_draining_average[i].1);
+    println!("SIGNIFICANCE");
+    println!(
+        "candidate baseline status affected metric saving-percent t-test-p wilcoxon-p effect-size"
+    );
+    for comparison in comparisons {
+        let mut tests = vec![("energy", comparison.energy)];
+        if columns.computation {
+            tests.push(("computation", comparison.computation));
+        }
+        tests.push(("communication", comparison.communication));
+        tests.push(("latency", comparison.latency));
+        if columns.relay {
+            tests.push(("relay", comparison.relay));
+        }
+        for (metric, test) in tests {
+            println!(
+                "{} {} {} {} {} {} {:.3e} {:.3e} {:.3}",
+                comparison.candidate,
+                comparison.baseline,
+                comparison.status.label(),
+                comparison.affected,
+                metric,
+                if test.saving_percent.is_finite() {
+                    format!("{:.2}", test.saving_percent)
+                } else {
+                    "-".to_string()
+                },
+                test.t_test_p,
+                test.wilcoxon_p,
+                test.effect_size
+            );
+        }
     }
+}
 
-
-    
-
+fn print_results(results: &SimulationResults, columns: Columns) {
+    println!("SEED: {}", results.seed);
+    let metrics = columns.metrics();
+    for status in NodeStatus::ALL {
+        let status_label = status.label().to_uppercase();
+        for (metric_label, value) in metrics.iter() {
+            for (scheme_index, scheme) in results.schemes.iter().enumerate() {
+                println!(
+                    "{}-{}-{}",
+                    scheme.to_uppercase(),
+                    status_label,
+                    metric_label.to_uppercase()
+                );
+                for affected in results.affected_counts() {
+                    let average = results.average(scheme_index, status, affected);
+                    println!("({}, {})", affected, value(&average));
+                }
+            }
+        }
+    }
 }
@@ -1,287 +1,88 @@
-use std::env;
-
-use lazy_static::lazy_static;
-
 use crate::{
-    CommunicationOverheadType, CommunicationType, EnergyConsumptionType, EnergyType,
-    ExchangeCostType, ExchangeType, MetricsType, StateCostType, InvolvedDevicesCount, InvolvedExchangesCount,
+    builder::{CellBuilder, MetricsBuildError},
+    crypto::CryptoOperation,
+    methods::{RekeyingScheme, SchemeParameters},
+    scenario::CostConstants,
+    InvolvedDevicesCount, InvolvedExchangesCount, MetricsType, NodeStatus, Role,
 };
 
-lazy_static! {
-    static ref EPSB: f32 = env::var("EPSB")
-        .unwrap_or(0.0001.to_string())
-        .parse::<f32>()
-        .unwrap();
-    static ref EPRB: f32 = env::var("EPRB")
-        .unwrap_or(0.0001.to_string())
-        .parse::<f32>()
-        .unwrap();
-    static ref SENT_MESSAGE_SIZE: u32 = env::var("SENT_MESSAGE_SIZE")
-        .unwrap_or(16.to_string())
-        .parse::<u32>()
-        .unwrap();
-    static ref RECEIVED_MESSAGE_SIZE: u32 = env::var("RECEIVED_MESSAGE_SIZE")
-        .unwrap_or(16.to_string())
-        .parse::<u32>()
-        .unwrap();
+pub const NAME: &str = "bkrsc";
+
+pub struct Bkrsc {
+    metrics: MetricsType,
+}
+
+impl Bkrsc {
+    pub fn new(parameters: &SchemeParameters) -> Result<Self, MetricsBuildError> {
+        Ok(Self {
+            metrics: get_metrics(
+                parameters.number_of_nodes,
+                parameters.number_of_gateway_members,
+                parameters.number_of_neighbors,
+                &parameters.costs,
+            )?,
+        })
+    }
+}
+
+impl RekeyingScheme for Bkrsc {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn metrics(&self) -> MetricsType {
+        self.metrics
+    }
 }
 
 pub fn get_metrics(
     number_of_nodes: u32,
     number_of_gateway_members: u32,
     number_of_neighbors: u32,
-) -> MetricsType {
-    let metrics = MetricsType {
-        energy: EnergyType {
-            compromised: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            draining: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_gateway_members,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::GatewayMembers),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            leaving: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_neighbors,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::Neighbors),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-        },
-        communication: CommunicationType {
-            compromised: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            draining: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_gateway_members,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::GatewayMembers),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            leaving: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_gateway_members,
-                    involved_devices: InvolvedDevicesCount::GatewayMembers,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_neighbors,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::Neighbors),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-        },
+    costs: &CostConstants,
+) -> Result<MetricsType, MetricsBuildError> {
+    // The gateway wraps the new key for each member and appends a signed entry to its ledger
+    let ledger_entry = |cell: CellBuilder| {
+        cell.operation(CryptoOperation::Aes128Block, number_of_gateway_members)
+            .operation(CryptoOperation::Sha256Block, 4)
+            .operation(CryptoOperation::EcdsaSign, 1)
     };
-    metrics
+    MetricsType::builder(costs)
+        // Members check the ledger entry, unwrap the new key and authenticate their answer
+        .cells(&NodeStatus::ALL, Role::Constrained, |cell| {
+            cell.sent(1)
+                .received(1)
+                .involved_devices(
+                    InvolvedDevicesCount::GatewayMembers,
+                    number_of_gateway_members,
+                )
+                .operation(CryptoOperation::EcdsaVerify, 1)
+                .operation(CryptoOperation::Aes128Block, 2)
+                .operation(CryptoOperation::Sha256Block, 2)
+        })
+        .cell(NodeStatus::Compromised, Role::Gateway, |cell| {
+            ledger_entry(cell)
+                .sent(1)
+                .received_from(InvolvedExchangesCount::All, number_of_nodes)
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .energy_cost(0.0, 0.0)
+        })
+        .cell(NodeStatus::Leaving, Role::Gateway, |cell| {
+            ledger_entry(cell)
+                .sent(1)
+                .received_from(InvolvedExchangesCount::Neighbors, number_of_neighbors)
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .energy_cost(0.0, 0.0)
+        })
+        .cell(NodeStatus::Draining, Role::Gateway, |cell| {
+            ledger_entry(cell)
+                .sent(1)
+                .received_from(
+                    InvolvedExchangesCount::GatewayMembers,
+                    number_of_gateway_members,
+                )
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .energy_cost(0.0, 0.0)
+        })
+        .build()
 }
@@ -0,0 +1,511 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    builder::CellBuilder,
+    crypto::{CryptoOperation, Operations},
+    methods::{RekeyingScheme, SchemeParameters},
+    InvolvedDevicesCount, InvolvedExchangesCount, MetricsType, NodeStatus, Role,
+};
+
+// A scheme described in a TOML file instead of Rust code. Each status table holds one table per
+// role, e.g. [leaving.gateway], and every field of a role has a default so only the exchanges
+// that actually happen need to be written down.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SchemeDefinition {
+    pub name: String,
+    pub compromised: StatusDefinition,
+    pub leaving: StatusDefinition,
+    pub draining: StatusDefinition,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct StatusDefinition {
+    pub constrained: RoleDefinition,
+    pub gateway: RoleDefinition,
+    pub left: RoleDefinition,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RoleDefinition {
+    pub sent: Count,
+    pub received: Count,
+    pub sent_messages: InvolvedExchangesCount,
+    pub received_messages: InvolvedExchangesCount,
+    pub involved_devices: InvolvedDevicesCount,
+    pub number_of_involved_devices: Count,
+    pub energy_cost: CostDefinition,
+    pub communication_cost: CostDefinition,
+    pub operations: OperationsDefinition,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CostDefinition {
+    pub sent: Cost,
+    pub received: Cost,
+}
+
+// Cryptographic operations of every involved device, e.g.
+// operations = { ecdsa_verify = 1, aes128_block = "number_of_gateway_members" }
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct OperationsDefinition {
+    pub aes128_block: Count,
+    pub sha256_block: Count,
+    pub ecdh_p256: Count,
+    pub ecdsa_sign: Count,
+    pub ecdsa_verify: Count,
+    pub key_generation: Count,
+}
+
+// A message or device count: either a literal or a scheme parameter with an optional offset,
+// written as "number_of_nodes - 1"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Count {
+    Fixed(u32),
+    Expression(String),
+}
+
+// A per message cost: either a literal or one of the scenario cost constants
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Cost {
+    Fixed(f32),
+    Constant(String),
+}
+
+#[derive(Debug)]
+pub enum SchemeFileError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        scheme: String,
+        field: String,
+        reason: String,
+    },
+}
+
+pub struct DeclarativeScheme {
+    name: String,
+    metrics: MetricsType,
+}
+
+impl Default for RoleDefinition {
+    fn default() -> Self {
+        Self {
+            sent: Count::Fixed(0),
+            received: Count::Fixed(0),
+            sent_messages: InvolvedExchangesCount::SameAsDefined,
+            received_messages: InvolvedExchangesCount::SameAsDefined,
+            involved_devices: InvolvedDevicesCount::SameAsDefined,
+            number_of_involved_devices: Count::Fixed(0),
+            energy_cost: CostDefinition {
+                sent: Cost::Constant("epsb".to_string()),
+                received: Cost::Constant("eprb".to_string()),
+            },
+            communication_cost: CostDefinition {
+                sent: Cost::Constant("sent_message_size".to_string()),
+                received: Cost::Constant("received_message_size".to_string()),
+            },
+            operations: OperationsDefinition::default(),
+        }
+    }
+}
+
+impl Default for OperationsDefinition {
+    fn default() -> Self {
+        Self {
+            aes128_block: Count::Fixed(0),
+            sha256_block: Count::Fixed(0),
+            ecdh_p256: Count::Fixed(0),
+            ecdsa_sign: Count::Fixed(0),
+            ecdsa_verify: Count::Fixed(0),
+            key_generation: Count::Fixed(0),
+        }
+    }
+}
+
+impl OperationsDefinition {
+    fn resolve(&self, parameters: &SchemeParameters) -> Result<Operations, String> {
+        let mut operations = Operations::default();
+        for (operation, count) in [
+            (CryptoOperation::Aes128Block, &self.aes128_block),
+            (CryptoOperation::Sha256Block, &self.sha256_block),
+            (CryptoOperation::EcdhP256, &self.ecdh_p256),
+            (CryptoOperation::EcdsaSign, &self.ecdsa_sign),
+            (CryptoOperation::EcdsaVerify, &self.ecdsa_verify),
+            (CryptoOperation::KeyGeneration, &self.key_generation),
+        ] {
+            operations.set(operation, count.resolve(parameters)?);
+        }
+        Ok(operations)
+    }
+}
+
+impl Count {
+    fn resolve(&self, parameters: &SchemeParameters) -> Result<u32, String> {
+        let expression = match self {
+            Count::Fixed(value) => return Ok(*value),
+            Count::Expression(expression) => expression,
+        };
+        let (name, offset) = match expression.find(['+', '-']) {
+            Some(index) => {
+                let offset = expression[index + 1..]
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| format!("invalid offset in \"{}\"", expression))?;
+                let sign = if expression[index..].starts_with('-') {
+                    -1
+                } else {
+                    1
+                };
+                (expression[..index].trim(), sign * offset)
+            }
+            None => (expression.trim(), 0),
+        };
+        let value = match name {
+            "number_of_nodes" => parameters.number_of_nodes,
+            "number_of_gateway_members" => parameters.number_of_gateway_members,
+            "number_of_neighbors" => parameters.number_of_neighbors,
+            _ => {
+                return Err(format!(
+                    "unknown parameter \"{}\" (expected number_of_nodes, \
+                     number_of_gateway_members or number_of_neighbors)",
+                    name
+                ))
+            }
+        };
+        u32::try_from(value as i64 + offset)
+            .map_err(|_| format!("\"{}\" is negative for {} = {}", expression, name, value))
+    }
+}
+
+impl Cost {
+    fn resolve(&self, parameters: &SchemeParameters) -> Result<f32, String> {
+        let costs = &parameters.costs;
+        let value = match self {
+            Cost::Fixed(value) => *value,
+            Cost::Constant(name) => match name.as_str() {
+                "epsb" => costs.epsb,
+                "eprb" => costs.eprb,
+                "sent_message_size" => costs.sent_message_size as f32,
+                "received_message_size" => costs.received_message_size as f32,
+                _ => {
+                    return Err(format!(
+                        "unknown cost constant \"{}\" (expected epsb, eprb, sent_message_size \
+                         or received_message_size)",
+                        name
+                    ))
+                }
+            },
+        };
+        if !value.is_finite() || value < 0.0 {
+            return Err(format!("{} is not a non-negative cost", value));
+        }
+        Ok(value)
+    }
+}
+
+impl SchemeDefinition {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, SchemeFileError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| SchemeFileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        toml::from_str(&contents).map_err(|source| SchemeFileError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn metrics(&self, parameters: &SchemeParameters) -> Result<MetricsType, SchemeFileError> {
+        let mut builder = MetricsType::builder(&parameters.costs);
+        let statuses = [
+            (NodeStatus::Compromised, &self.compromised),
+            (NodeStatus::Leaving, &self.leaving),
+            (NodeStatus::Draining, &self.draining),
+        ];
+        for (status, definition) in statuses {
+            let roles = [
+                (Role::Constrained, &definition.constrained),
+                (Role::Gateway, &definition.gateway),
+                (Role::Left, &definition.left),
+            ];
+            for (role, role_definition) in roles {
+                let cell = role_definition.resolve(parameters).map_err(|reason| {
+                    self.invalid(&format!("{}.{}", status.label(), role.label()), reason)
+                })?;
+                builder = builder.cell(status, role, |builder_cell| cell.apply(builder_cell));
+            }
+        }
+        builder.build().map_err(|err| {
+            self.invalid(
+                &format!("{}.{}", err.status.label(), err.role.label()),
+                err.reason,
+            )
+        })
+    }
+
+    fn invalid(&self, field: &str, reason: String) -> SchemeFileError {
+        SchemeFileError::Invalid {
+            scheme: self.name.clone(),
+            field: field.to_string(),
+            reason,
+        }
+    }
+}
+
+// A role definition with its parameters and constants replaced by values
+struct ResolvedRole {
+    sent: u32,
+    received: u32,
+    messages: (InvolvedExchangesCount, InvolvedExchangesCount),
+    number_of_involved_devices: u32,
+    involved_devices: InvolvedDevicesCount,
+    energy_cost: (f32, f32),
+    communication_cost: (f32, f32),
+    operations: Operations,
+}
+
+impl RoleDefinition {
+    fn resolve(&self, parameters: &SchemeParameters) -> Result<ResolvedRole, String> {
+        Ok(ResolvedRole {
+            sent: self.sent.resolve(parameters)?,
+            received: self.received.resolve(parameters)?,
+            messages: (self.sent_messages, self.received_messages),
+            number_of_involved_devices: self.number_of_involved_devices.resolve(parameters)?,
+            involved_devices: self.involved_devices,
+            energy_cost: (
+                self.energy_cost.sent.resolve(parameters)?,
+                self.energy_cost.received.resolve(parameters)?,
+            ),
+            communication_cost: (
+                self.communication_cost.sent.resolve(parameters)?,
+                self.communication_cost.received.resolve(parameters)?,
+            ),
+            operations: self.operations.resolve(parameters)?,
+        })
+    }
+}
+
+impl ResolvedRole {
+    fn apply(&self, cell: CellBuilder) -> CellBuilder {
+        let cell = cell
+            .sent_to(self.messages.0, self.sent)
+            .received_from(self.messages.1, self.received)
+            .involved_devices(self.involved_devices, self.number_of_involved_devices)
+            .energy_cost(self.energy_cost.0, self.energy_cost.1)
+            .communication_cost(self.communication_cost.0, self.communication_cost.1);
+        CryptoOperation::ALL.iter().fold(cell, |cell, operation| {
+            cell.operation(*operation, self.operations.count(*operation))
+        })
+    }
+}
+
+impl DeclarativeScheme {
+    pub fn new(
+        definition: &SchemeDefinition,
+        parameters: &SchemeParameters,
+    ) -> Result<Self, SchemeFileError> {
+        Ok(Self {
+            name: definition.name.clone(),
+            metrics: definition.metrics(parameters)?,
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        parameters: &SchemeParameters,
+    ) -> Result<Self, SchemeFileError> {
+        Self::new(&SchemeDefinition::from_file(path)?, parameters)
+    }
+}
+
+impl RekeyingScheme for DeclarativeScheme {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn metrics(&self) -> MetricsType {
+        self.metrics
+    }
+}
+
+impl fmt::Display for SchemeFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemeFileError::Io { path, source } => {
+                write!(f, "cannot read scheme {}: {}", path.display(), source)
+            }
+            SchemeFileError::Parse { path, source } => {
+                write!(f, "cannot parse scheme {}: {}", path.display(), source)
+            }
+            SchemeFileError::Invalid {
+                scheme,
+                field,
+                reason,
+            } => write!(
+                f,
+                "invalid value in scheme {} for {}: {}",
+                scheme, field, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SchemeFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemeFileError::Io { source, .. } => Some(source),
+            SchemeFileError::Parse { source, .. } => Some(source),
+            SchemeFileError::Invalid { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::MetricsBuildError,
+        crypto::CryptoCosts,
+        methods::{bkrsc, others},
+        scenario::CostConstants,
+    };
+
+    fn parameters() -> SchemeParameters {
+        SchemeParameters {
+            number_of_nodes: 40,
+            number_of_gateway_members: 3,
+            number_of_neighbors: 5,
+            costs: CostConstants {
+                epsb: 0.0002,
+                eprb: 0.0003,
+                sent_message_size: 24,
+                received_message_size: 32,
+                crypto: Some(CryptoCosts::default()),
+                ..CostConstants::default()
+            },
+        }
+    }
+
+    fn bundled(file: &str) -> MetricsType {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("schemes")
+            .join(file);
+        let scheme = DeclarativeScheme::from_file(path, &parameters()).unwrap();
+        scheme.metrics()
+    }
+
+    fn invalid(contents: &str) -> (String, String) {
+        let definition: SchemeDefinition = toml::from_str(contents).unwrap();
+        match definition.metrics(&parameters()) {
+            Err(SchemeFileError::Invalid { field, reason, .. }) => (field, reason),
+            other => panic!("expected an invalid scheme, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn bundled_schemes_match_the_builtin_ones() {
+        let parameters = parameters();
+        let builtin = |get_metrics: fn(
+            u32,
+            u32,
+            u32,
+            &CostConstants,
+        ) -> Result<MetricsType, MetricsBuildError>| {
+            get_metrics(
+                parameters.number_of_nodes,
+                parameters.number_of_gateway_members,
+                parameters.number_of_neighbors,
+                &parameters.costs,
+            )
+            .unwrap()
+        };
+        assert_eq!(bundled("bkrsc.toml"), builtin(bkrsc::get_metrics));
+        assert_eq!(bundled("others.toml"), builtin(others::get_metrics));
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let definitions = [
+            // Top level
+            "name = \"s\"\ncompromised = {}\nleaving = {}\ndraining = {}\nversion = 2",
+            // Role
+            "name = \"s\"\ncompromised = {}\ndraining = {}\n[leaving.router]\nsent = 1",
+            // Role field
+            "name = \"s\"\ncompromised = {}\ndraining = {}\n[leaving.gateway]\nsend = 1",
+            // Operation
+            "name = \"s\"\ncompromised = {}\ndraining = {}\n[leaving.gateway]\n\
+             operations = { rsa_sign = 1 }",
+            // Cost
+            "name = \"s\"\ncompromised = {}\ndraining = {}\n[leaving.gateway]\n\
+             energy_cost = { sent = 0, received = 0, idle = 0 }",
+        ];
+        for contents in definitions {
+            let err = toml::from_str::<SchemeDefinition>(contents).unwrap_err();
+            assert!(err.to_string().contains("unknown field"), "{}", err);
+        }
+        let empty = "name = \"s\"\ncompromised = {}\nleaving = {}\ndraining = {}";
+        assert!(toml::from_str::<SchemeDefinition>(empty).is_ok());
+    }
+
+    #[test]
+    fn unknown_parameter_names_are_rejected() {
+        let (field, reason) = invalid(
+            "name = \"s\"\ncompromised = {}\ndraining = {}\n[leaving.gateway]\n\
+             sent = 1\nreceived = \"number_of_routers - 1\"\nnumber_of_involved_devices = 1",
+        );
+        assert_eq!(field, "leaving.gateway");
+        assert!(
+            reason.contains("unknown parameter \"number_of_routers\""),
+            "{}",
+            reason
+        );
+
+        let (field, reason) = invalid(
+            "name = \"s\"\nleaving = {}\ndraining = {}\n[compromised.constrained]\n\
+             sent = 1\nnumber_of_involved_devices = 1\nenergy_cost = { sent = \"epsb\", \
+             received = \"eprb_gateway\" }",
+        );
+        assert_eq!(field, "compromised.constrained");
+        assert!(
+            reason.contains("unknown cost constant \"eprb_gateway\""),
+            "{}",
+            reason
+        );
+
+        let (_, reason) = invalid(
+            "name = \"s\"\ncompromised = {}\nleaving = {}\n[draining.left]\n\
+             sent = 1\nnumber_of_involved_devices = \"number_of_nodes - x\"",
+        );
+        assert!(reason.contains("invalid offset"), "{}", reason);
+    }
+
+    #[test]
+    fn parameters_with_an_offset_resolve() {
+        let parameters = parameters();
+        let count =
+            |expression: &str| Count::Expression(expression.to_string()).resolve(&parameters);
+        assert_eq!(count("number_of_nodes - 1"), Ok(39));
+        assert_eq!(count("number_of_neighbors+2"), Ok(7));
+        assert_eq!(count(" number_of_gateway_members "), Ok(3));
+        assert!(count("number_of_gateway_members - 4").is_err());
+    }
+}
@@ -1,2 +1,195 @@
+use std::fmt;
+
+use declarative::{DeclarativeScheme, SchemeFileError};
+
+use crate::{
+    builder::MetricsBuildError,
+    crypto::computation_cost,
+    latency::time_to_rekey,
+    routing::busiest_relay,
+    scenario::{CostConstants, Scenario},
+    MetricsFor, MetricsType, NodeStatus, NodesVec, TotalCommunicationOverhead,
+    TotalEnergyConsumption,
+};
+
 pub mod bkrsc;
-pub mod others;
\ No newline at end of file
+pub mod declarative;
+pub mod others;
+
+pub const BUILTIN_SCHEMES: [&str; 2] = [bkrsc::NAME, others::NAME];
+
+// Network wide values a scheme needs to describe its costs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchemeParameters {
+    pub number_of_nodes: u32,
+    pub number_of_gateway_members: u32,
+    pub number_of_neighbors: u32,
+    pub costs: CostConstants,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EventCost {
+    pub energy: f32,
+    // Part of the energy spent on cryptographic operations rather than on the radio
+    pub computation: f32,
+    pub communication: f32,
+    // Seconds until the rekeying is over
+    pub latency: f32,
+    // Forwarding energy of the busiest relay, zero when messages are not routed
+    pub relay: f32,
+}
+
+pub trait RekeyingScheme: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn metrics(&self) -> MetricsType;
+
+    // Cost of rekeying the network once the nodes flagged with `status` are removed. Schemes
+    // whose cost does not fit the MetricsType description can override it.
+    fn event_cost(&self, nodes: &NodesVec, status: NodeStatus) -> EventCost {
+        let metrics = self.metrics();
+        let computation = computation_cost(nodes, status, &metrics);
+        EventCost {
+            energy: nodes.total_energy_consumption(status, MetricsFor::Constrained, metrics)
+                + computation,
+            computation,
+            communication: nodes.total_communication_overhead(
+                status,
+                MetricsFor::Constrained,
+                metrics,
+            ),
+            latency: time_to_rekey(nodes, status, &metrics),
+            relay: busiest_relay(nodes, status, &metrics),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SchemeRegistry {
+    schemes: Vec<Box<dyn RekeyingScheme>>,
+}
+
+#[derive(Debug)]
+pub enum SchemeError {
+    Unknown(String),
+    Duplicate(String),
+    Invalid {
+        scheme: String,
+        source: MetricsBuildError,
+    },
+    File(SchemeFileError),
+}
+
+impl SchemeParameters {
+    pub fn from_scenario(scenario: &Scenario) -> Self {
+        Self {
+            number_of_nodes: scenario.topology.number_of_nodes,
+            number_of_gateway_members: scenario.topology.number_of_gateway_members,
+            number_of_neighbors: scenario.topology.average_neighbors(),
+            costs: scenario.costs,
+        }
+    }
+}
+
+impl SchemeRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn builtin(
+        name: &str,
+        parameters: &SchemeParameters,
+    ) -> Result<Box<dyn RekeyingScheme>, SchemeError> {
+        match name {
+            bkrsc::NAME => bkrsc::Bkrsc::new(parameters)
+                .map(|scheme| Box::new(scheme) as Box<dyn RekeyingScheme>),
+            others::NAME => others::Others::new(parameters)
+                .map(|scheme| Box::new(scheme) as Box<dyn RekeyingScheme>),
+            _ => return Err(SchemeError::Unknown(name.to_string())),
+        }
+        .map_err(|source| SchemeError::Invalid {
+            scheme: name.to_string(),
+            source,
+        })
+    }
+
+    // Built-in schemes listed by name in the scenario, followed by the ones loaded from files
+    pub fn from_scenario(scenario: &Scenario) -> Result<Self, SchemeError> {
+        let parameters = SchemeParameters::from_scenario(scenario);
+        let mut registry = Self::new();
+        for name in scenario.schemes.iter() {
+            registry.register(Self::builtin(name, &parameters)?)?;
+        }
+        for path in scenario.scheme_files.iter() {
+            let scheme =
+                DeclarativeScheme::from_file(path, &parameters).map_err(SchemeError::File)?;
+            registry.register(Box::new(scheme))?;
+        }
+        Ok(registry)
+    }
+
+    // Scheme names identify results, so two schemes cannot share one
+    pub fn register(&mut self, scheme: Box<dyn RekeyingScheme>) -> Result<&mut Self, SchemeError> {
+        if self.get(scheme.name()).is_some() {
+            return Err(SchemeError::Duplicate(scheme.name().to_string()));
+        }
+        self.schemes.push(scheme);
+        Ok(self)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn RekeyingScheme> {
+        self.schemes
+            .iter()
+            .find(|scheme| scheme.name() == name)
+            .map(|scheme| scheme.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.schemes
+            .iter()
+            .map(|scheme| scheme.name().to_string())
+            .collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn RekeyingScheme> {
+        self.schemes.iter().map(|scheme| scheme.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.schemes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schemes.is_empty()
+    }
+}
+
+impl fmt::Display for SchemeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemeError::Unknown(name) => write!(
+                f,
+                "unknown scheme \"{}\" (expected one of: {})",
+                name,
+                BUILTIN_SCHEMES.join(", ")
+            ),
+            SchemeError::Duplicate(name) => {
+                write!(f, "scheme \"{}\" is defined more than once", name)
+            }
+            SchemeError::Invalid { scheme, source } => {
+                write!(f, "invalid scheme {}: {}", scheme, source)
+            }
+            SchemeError::File(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SchemeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SchemeError::Invalid { source, .. } => Some(source),
+            SchemeError::File(err) => Some(err),
+            _ => None,
+        }
+    }
+}
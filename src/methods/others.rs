@@ -1,287 +1,103 @@
-use std::env;
-
-use lazy_static::lazy_static;
-
 use crate::{
-    CommunicationOverheadType, CommunicationType, EnergyConsumptionType, EnergyType,
-    ExchangeCostType, ExchangeType, MetricsType, StateCostType, InvolvedDevicesCount, InvolvedExchangesCount,
+    builder::MetricsBuildError,
+    crypto::CryptoOperation,
+    methods::{RekeyingScheme, SchemeParameters},
+    scenario::CostConstants,
+    InvolvedDevicesCount, InvolvedExchangesCount, MetricsType, NodeStatus, Role,
 };
 
-lazy_static! {
-    static ref EPSB: f32 = env::var("EPSB")
-        .unwrap_or(0.0001.to_string())
-        .parse::<f32>()
-        .unwrap();
-    static ref EPRB: f32 = env::var("EPRB")
-        .unwrap_or(0.0001.to_string())
-        .parse::<f32>()
-        .unwrap();
-    static ref SENT_MESSAGE_SIZE: u32 = env::var("SENT_MESSAGE_SIZE")
-        .unwrap_or(16.to_string())
-        .parse::<u32>()
-        .unwrap();
-    static ref RECEIVED_MESSAGE_SIZE: u32 = env::var("RECEIVED_MESSAGE_SIZE")
-        .unwrap_or(16.to_string())
-        .parse::<u32>()
-        .unwrap();
+pub const NAME: &str = "others";
+
+pub struct Others {
+    metrics: MetricsType,
+}
+
+impl Others {
+    pub fn new(parameters: &SchemeParameters) -> Result<Self, MetricsBuildError> {
+        Ok(Self {
+            metrics: get_metrics(
+                parameters.number_of_nodes,
+                parameters.number_of_gateway_members,
+                parameters.number_of_neighbors,
+                &parameters.costs,
+            )?,
+        })
+    }
+}
+
+impl RekeyingScheme for Others {
+    fn name(&self) -> &str {
+        NAME
+    }
+
+    fn metrics(&self) -> MetricsType {
+        self.metrics
+    }
 }
 
 pub fn get_metrics(
     number_of_nodes: u32,
     _number_of_gateway_members: u32,
     number_of_neighbors: u32,
-) -> MetricsType {
-    let metrics = MetricsType {
-        energy: EnergyType {
-            compromised: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_nodes,
-                    involved_devices: InvolvedDevicesCount::All,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            draining: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes - 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_nodes,
-                    involved_devices: InvolvedDevicesCount::All,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            leaving: EnergyConsumptionType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: number_of_neighbors,
-                    involved_devices: InvolvedDevicesCount::Neighbors,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: 0f32,
-                        received: 0f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_neighbors,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::Neighbors),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *EPSB,
-                        received: *EPRB,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-        },
-        communication: CommunicationType {
-            compromised: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_nodes,
-                    involved_devices: InvolvedDevicesCount::All,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            draining: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes - 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_nodes,
-                    involved_devices: InvolvedDevicesCount::All,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_nodes - 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::All),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 0,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-            leaving: CommunicationOverheadType {
-                constrained: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: 1,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: number_of_neighbors,
-                    involved_devices: InvolvedDevicesCount::Neighbors,
-                },
-                gateway: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 0,
-                        received: 0,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::SameAsDefined),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-                left: StateCostType {
-                    exchange: ExchangeType {
-                        sent: 1,
-                        received: number_of_neighbors,
-                        messages: (InvolvedExchangesCount::SameAsDefined, InvolvedExchangesCount::Neighbors),
-                    },
-                    exchange_cost: ExchangeCostType {
-                        sent: *SENT_MESSAGE_SIZE as f32,
-                        received: *RECEIVED_MESSAGE_SIZE as f32,
-                    },
-                    number_of_involved_devices: 1,
-                    involved_devices: InvolvedDevicesCount::SameAsDefined,
-                },
-            },
-        },
-    };
-    metrics
+    costs: &CostConstants,
+) -> Result<MetricsType, MetricsBuildError> {
+    MetricsType::builder(costs)
+        // Every device agrees on a fresh key with the gateway
+        .cell(NodeStatus::Compromised, Role::Constrained, |cell| {
+            cell.sent(1)
+                .received(1)
+                .involved_devices(InvolvedDevicesCount::All, number_of_nodes)
+                .operation(CryptoOperation::KeyGeneration, 1)
+                .operation(CryptoOperation::EcdhP256, 1)
+                .operation(CryptoOperation::Aes128Block, 2)
+        })
+        .cell(NodeStatus::Compromised, Role::Gateway, |cell| {
+            cell.sent(1)
+                .received_from(InvolvedExchangesCount::All, number_of_nodes)
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .energy_cost(0.0, 0.0)
+                .operation(CryptoOperation::EcdhP256, number_of_nodes)
+                .operation(CryptoOperation::Aes128Block, number_of_nodes)
+        })
+        // The neighbors of the leaving node set up new pairwise keys
+        .cell(NodeStatus::Leaving, Role::Constrained, |cell| {
+            cell.sent(1)
+                .received(1)
+                .involved_devices(InvolvedDevicesCount::Neighbors, number_of_neighbors)
+                .operation(CryptoOperation::KeyGeneration, 1)
+                .operation(CryptoOperation::EcdhP256, 1)
+                .operation(CryptoOperation::Aes128Block, 2)
+        })
+        .cell(NodeStatus::Leaving, Role::Left, |cell| {
+            cell.sent(1)
+                .received_from(InvolvedExchangesCount::Neighbors, number_of_neighbors)
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .operation(CryptoOperation::Aes128Block, number_of_neighbors)
+        })
+        // The gateway hands a new group key to every device
+        .cell(NodeStatus::Draining, Role::Constrained, |cell| {
+            cell.sent(1)
+                .received_from(
+                    InvolvedExchangesCount::All,
+                    number_of_nodes.saturating_sub(1),
+                )
+                .involved_devices(InvolvedDevicesCount::All, number_of_nodes)
+                .operation(CryptoOperation::Aes128Block, 2)
+                .operation(CryptoOperation::Sha256Block, 1)
+        })
+        .cell(NodeStatus::Draining, Role::Gateway, |cell| {
+            cell.sent(1)
+                .received_from(
+                    InvolvedExchangesCount::All,
+                    number_of_nodes.saturating_sub(1),
+                )
+                .involved_devices(InvolvedDevicesCount::SameAsDefined, 1)
+                .energy_cost(0.0, 0.0)
+                .operation(
+                    CryptoOperation::Aes128Block,
+                    number_of_nodes.saturating_sub(1),
+                )
+                .operation(CryptoOperation::Sha256Block, 1)
+        })
+        .build()
 }
@@ -0,0 +1,113 @@
+use serde::Deserialize;
+
+// Energy a radio spends per bit of a message, given the length of the hop
+pub trait RadioModel {
+    fn transmit(&self, distance: f64) -> f64;
+
+    fn receive(&self) -> f64;
+}
+
+// Radio of the constrained nodes, set in [costs.radio]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum RadioConfig {
+    // Every message costs the energy per message of its cell, whatever its size and hop length
+    #[default]
+    Flat,
+    FirstOrder(FirstOrderRadio),
+}
+
+// First-order radio model (Heinzelman et al.): the electronics spend the same energy per bit to
+// send and to receive, and the amplifier compensates a free space loss in d^2 up to the crossover
+// distance, a multipath loss in d^4 beyond it. Message sizes of the cells are in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct FirstOrderRadio {
+    // J/bit
+    pub electronics: f64,
+    // J/bit/m^2
+    pub free_space: f64,
+    // J/bit/m^4
+    pub multipath: f64,
+    // Length of a hop in meters when one of its ends has no position
+    pub default_distance: f64,
+}
+
+impl RadioConfig {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RadioConfig::Flat => "flat",
+            RadioConfig::FirstOrder(_) => "first_order",
+        }
+    }
+
+    // Model charging messages by their size and hop length, none when they cost a flat amount
+    pub fn model(&self) -> Option<&dyn RadioModel> {
+        match self {
+            RadioConfig::Flat => None,
+            RadioConfig::FirstOrder(radio) => Some(radio),
+        }
+    }
+
+    pub fn default_distance(&self) -> f64 {
+        match self {
+            RadioConfig::Flat => 0.0,
+            RadioConfig::FirstOrder(radio) => radio.default_distance,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let RadioConfig::FirstOrder(radio) = self else {
+            return Ok(());
+        };
+        for (field, value) in [
+            ("electronics", radio.electronics),
+            ("free_space", radio.free_space),
+            ("multipath", radio.multipath),
+            ("default_distance", radio.default_distance),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!(
+                    "{} = {} is not a non-negative number",
+                    field, value
+                ));
+            }
+        }
+        if radio.free_space > 0.0 && radio.multipath == 0.0 {
+            return Err("multipath must be positive when free_space is".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Default for FirstOrderRadio {
+    fn default() -> Self {
+        Self {
+            electronics: 50e-9,
+            free_space: 10e-12,
+            multipath: 0.0013e-12,
+            default_distance: 10.0,
+        }
+    }
+}
+
+impl FirstOrderRadio {
+    // Hop length from which the multipath loss takes over, about 88 m with the defaults
+    pub fn crossover(&self) -> f64 {
+        (self.free_space / self.multipath).sqrt()
+    }
+}
+
+impl RadioModel for FirstOrderRadio {
+    fn transmit(&self, distance: f64) -> f64 {
+        if distance < self.crossover() {
+            self.electronics + self.free_space * distance.powi(2)
+        } else {
+            self.electronics + self.multipath * distance.powi(4)
+        }
+    }
+
+    fn receive(&self) -> f64 {
+        self.electronics
+    }
+}
@@ -0,0 +1,39 @@
+use rand::{thread_rng, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// ChaCha is used instead of StdRng because its output is guaranteed to stay the same across
+// rand releases, so a seed keeps reproducing the same figures
+pub type SimulationRng = ChaCha8Rng;
+
+// Identifiers of the independent streams drawn from the master seed
+pub const TOPOLOGY_STREAM: u64 = 1;
+pub const EVENTS_STREAM: u64 = 2;
+pub const REPETITION_STREAM: u64 = 3;
+pub const SWEEP_STREAM: u64 = 4;
+pub const LIFETIME_STREAM: u64 = 5;
+
+pub fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
+// Derive a deterministic RNG from the master seed and a path of labels, e.g.
+// [EVENTS_STREAM, iteration]. Different paths give statistically independent streams.
+pub fn derive_rng(seed: u64, labels: &[u64]) -> SimulationRng {
+    SimulationRng::seed_from_u64(derive_seed(seed, labels))
+}
+
+// Derive a new master seed, e.g. for the repetitions of an experiment
+pub fn derive_seed(seed: u64, labels: &[u64]) -> u64 {
+    let mut state = splitmix64(seed);
+    for label in labels {
+        state = splitmix64(state ^ splitmix64(*label));
+    }
+    state
+}
+
+fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,695 @@
+use std::{cmp::Ordering, collections::BinaryHeap, fmt};
+
+use serde::Deserialize;
+
+use crate::{
+    radio::{RadioConfig, RadioModel},
+    InvolvedDevicesCount, InvolvedExchangesCount, MetricsType, Node, NodeStatus, NodeType,
+    NodesVec,
+};
+
+const UNROUTED: usize = usize::MAX;
+
+// How messages between a constrained node and its gateway travel through the network
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutingStrategy {
+    // Every message reaches its destination in one hop
+    #[default]
+    Direct,
+    // Fewest hops to the first gateway of the node's cluster
+    ShortestPath,
+    // Least radio energy to the first gateway of the node's cluster: a hop costs what the radio
+    // model spends to send and receive a bit over it, or its length to the power of the path loss
+    // exponent with a flat radio (fewest hops when nodes have no position)
+    MinimumEnergy,
+    // RPL-like upward routes: every gateway roots a DODAG with hop count ranks (objective
+    // function zero), a node joins the DODAG where its rank is lowest and keeps the first parent
+    // it heard among the ones giving that rank. The node's gateway is then the root of its DODAG,
+    // whatever its cluster.
+    Rpl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct RoutingConfig {
+    pub strategy: RoutingStrategy,
+    pub path_loss_exponent: f64,
+}
+
+// Route of every live constrained node to its gateway, through live nodes. Relays of node i are
+// relays[offsets[i]..offsets[i + 1]], from the node towards the gateway. Without routes (direct
+// strategy and flat radio) every message takes a single hop at its flat cost.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Routes {
+    config: RoutingConfig,
+    gateways: Vec<usize>,
+    offsets: Vec<usize>,
+    relays: Vec<usize>,
+    // Number of routes every node forwards, and the largest one
+    load: Vec<usize>,
+    busiest: usize,
+    radio: RadioConfig,
+    costs: Vec<RouteCost>,
+    // Sums of the costs of the members of every gateway's cluster and of all the constrained
+    // nodes, with their number, so the mean route of the devices involved in an event is found
+    // in constant time
+    cluster_costs: Vec<(RouteCost, usize)>,
+    network_costs: (RouteCost, usize),
+    // Constrained nodes without a path to a gateway, charged as if they reached it directly
+    unrouted: usize,
+}
+
+// What a route adds to the messages of a node. Energies are per bit and zero with a flat radio.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RouteCost {
+    pub relays: f64,
+    // The node sending on the first hop
+    pub transmit: f64,
+    // The relays forwarding a message to the gateway, and one from the gateway
+    pub upward: f64,
+    pub downward: f64,
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            strategy: RoutingStrategy::Direct,
+            path_loss_exponent: 2.0,
+        }
+    }
+}
+
+impl RoutingStrategy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            RoutingStrategy::Direct => "direct",
+            RoutingStrategy::ShortestPath => "shortest_path",
+            RoutingStrategy::MinimumEnergy => "minimum_energy",
+            RoutingStrategy::Rpl => "rpl",
+        }
+    }
+}
+
+impl RoutingConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.path_loss_exponent.is_finite() || self.path_loss_exponent <= 0.0 {
+            return Err(format!(
+                "{} is not a positive number",
+                self.path_loss_exponent
+            ));
+        }
+        Ok(())
+    }
+
+    // Whether messages take routes at all, rather than a single hop at their flat cost
+    pub fn is_routed(&self, radio: &RadioConfig) -> bool {
+        self.strategy != RoutingStrategy::Direct || radio.model().is_some()
+    }
+
+    // Cost of the hop between two linked nodes
+    fn weight(&self, nodes: &NodesVec, radio: &RadioConfig, a: usize, b: usize) -> f64 {
+        if self.strategy != RoutingStrategy::MinimumEnergy {
+            return 1.0;
+        }
+        let distance = nodes[a].distance(&nodes[b]);
+        match (radio.model(), distance) {
+            (Some(model), distance) => {
+                model.transmit(distance.unwrap_or(radio.default_distance())) + model.receive()
+            }
+            (None, Some(distance)) => distance.powf(self.path_loss_exponent),
+            (None, None) => 1.0,
+        }
+    }
+}
+
+impl Routes {
+    pub fn build(nodes: &NodesVec, config: &RoutingConfig, radio: &RadioConfig) -> Self {
+        if !config.is_routed(radio) {
+            return Self::default();
+        }
+        let gateways: Vec<usize> = nodes
+            .iter()
+            .filter(|node| node.kind == NodeType::Gateway && node.is_alive())
+            .map(|node| node.id)
+            .collect();
+        let constrained =
+            |id: usize| nodes[id].kind == NodeType::Constrained && nodes[id].is_alive();
+        let weight = |a: usize, b: usize| config.weight(nodes, radio, a, b);
+        let clusters = nodes.clusters();
+        let first_gateway = |id: usize| clusters.gateways(id).first().copied();
+        let mut paths: Vec<Option<Vec<usize>>> = vec![None; nodes.len()];
+        let mut search = Search::new(nodes.len());
+
+        match config.strategy {
+            // A single hop to the first gateway of the node's cluster, linked or not
+            RoutingStrategy::Direct => {
+                for id in (0..nodes.len()).filter(|id| constrained(*id)) {
+                    paths[id] = first_gateway(id).map(|gateway| vec![id, gateway]);
+                }
+            }
+            RoutingStrategy::Rpl => {
+                search.run(nodes, &gateways, weight, constrained);
+                for id in (0..nodes.len()).filter(|id| constrained(*id)) {
+                    paths[id] = search.path(id);
+                }
+            }
+            RoutingStrategy::ShortestPath | RoutingStrategy::MinimumEnergy => {
+                // One search from every gateway, stopped once the members routed to it are reached
+                for gateway in gateways.iter() {
+                    let members = search.run(nodes, &[*gateway], weight, |id| {
+                        constrained(id) && first_gateway(id) == Some(*gateway)
+                    });
+                    for id in members {
+                        paths[id] = search.path(id);
+                    }
+                }
+                // Nodes outside every cluster go to the closest gateway
+                let members = search.run(nodes, &gateways, weight, |id| {
+                    constrained(id) && first_gateway(id).is_none()
+                });
+                for id in members {
+                    paths[id] = search.path(id);
+                }
+            }
+        }
+        Self::from_paths(nodes, paths, config, radio)
+    }
+
+    // Paths hold the node, its relays and the gateway
+    fn from_paths(
+        nodes: &NodesVec,
+        paths: Vec<Option<Vec<usize>>>,
+        config: &RoutingConfig,
+        radio: &RadioConfig,
+    ) -> Self {
+        let mut routes = Self {
+            config: *config,
+            gateways: vec![UNROUTED; nodes.len()],
+            offsets: vec![0; nodes.len() + 1],
+            load: vec![0; nodes.len()],
+            radio: *radio,
+            costs: vec![RouteCost::default(); nodes.len()],
+            cluster_costs: vec![(RouteCost::default(), 0); nodes.len()],
+            ..Self::default()
+        };
+        let transmit = |a: usize, b: Option<usize>| transmit(nodes, radio, a, b);
+        let forward = |a: usize, b: usize| forward(nodes, radio, a, b);
+        for (id, path) in paths.into_iter().enumerate() {
+            if nodes[id].kind == NodeType::Constrained && nodes[id].is_alive() {
+                let cost = &mut routes.costs[id];
+                match path {
+                    Some(path) => {
+                        let relays = &path[1..path.len() - 1];
+                        routes.gateways[id] = path[path.len() - 1];
+                        routes.relays.extend_from_slice(relays);
+                        cost.relays = relays.len() as f64;
+                        cost.transmit = transmit(path[0], Some(path[1]));
+                        for index in 1..path.len() - 1 {
+                            cost.upward += forward(path[index], path[index + 1]);
+                            cost.downward += forward(path[index], path[index - 1]);
+                        }
+                    }
+                    None => {
+                        routes.unrouted += 1;
+                        cost.transmit = transmit(id, None);
+                    }
+                }
+            }
+            routes.offsets[id + 1] = routes.relays.len();
+        }
+        for relay in routes.relays.iter() {
+            routes.load[*relay] += 1;
+        }
+        routes.busiest = routes.load.iter().copied().max().unwrap_or(0);
+        for id in 0..nodes.len() {
+            if nodes[id].kind != NodeType::Constrained || !nodes[id].is_alive() {
+                continue;
+            }
+            let cost = routes.costs[id];
+            routes.network_costs.0.add(&cost);
+            routes.network_costs.1 += 1;
+            for gateway in nodes.clusters().gateways(id) {
+                routes.cluster_costs[*gateway].0.add(&cost);
+                routes.cluster_costs[*gateway].1 += 1;
+            }
+        }
+        routes
+    }
+
+    // Whether messages go straight to their destination at their flat cost
+    pub fn is_empty(&self) -> bool {
+        self.gateways.is_empty()
+    }
+
+    pub fn is_routed(&self, id: usize) -> bool {
+        self.gateways
+            .get(id)
+            .is_some_and(|gateway| *gateway != UNROUTED)
+    }
+
+    // Gateway at the end of the node's route
+    pub fn gateway(&self, id: usize) -> Option<usize> {
+        Some(*self.gateways.get(id)?).filter(|gateway| *gateway != UNROUTED)
+    }
+
+    // Nodes forwarding the messages of a node, from the node towards its gateway
+    pub fn relays(&self, id: usize) -> &[usize] {
+        match self.offsets.get(id..id + 2) {
+            Some(range) => &self.relays[range[0]..range[1]],
+            None => &[],
+        }
+    }
+
+    pub fn hops(&self, id: usize) -> usize {
+        self.relays(id).len() + 1
+    }
+
+    // Routes going through a node
+    pub fn load(&self, id: usize) -> usize {
+        self.load.get(id).copied().unwrap_or(0)
+    }
+
+    // Most routes of the devices taking part in an exchange of a node that a single relay
+    // forwards, each device counted as many times as it takes part
+    pub fn busiest_load(
+        &self,
+        nodes: &NodesVec,
+        node: &Node,
+        devices: InvolvedDevicesCount,
+        number: u32,
+    ) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        if devices == InvolvedDevicesCount::All {
+            return self.busiest;
+        }
+        let mut relays: Vec<usize> = nodes
+            .involved_devices(node, devices, number)
+            .into_iter()
+            .flat_map(|device| self.relays(device).iter().copied())
+            .collect();
+        relays.sort_unstable();
+        relays
+            .chunk_by(|a, b| a == b)
+            .map(|routes| routes.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn radio(&self) -> Option<&dyn RadioModel> {
+        self.radio.model()
+    }
+
+    // Routes of the same strategy around the nodes that died since these were built
+    pub fn rebuild(&self, nodes: &NodesVec) -> Self {
+        Self::build(nodes, &self.config, &self.radio)
+    }
+
+    // Energy per bit a node spends sending on the first hop of its route
+    pub fn first_hop(&self, id: usize) -> f64 {
+        self.costs.get(id).map_or(0.0, |cost| cost.transmit)
+    }
+
+    // Energy per bit every relay of a node spends forwarding one of its messages to the gateway,
+    // or one from the gateway
+    pub fn forwarding(&self, nodes: &NodesVec, id: usize, upward: bool) -> Vec<(usize, f64)> {
+        let Some(gateway) = self.gateway(id) else {
+            return vec![];
+        };
+        let mut path = vec![id];
+        path.extend_from_slice(self.relays(id));
+        path.push(gateway);
+        (1..path.len() - 1)
+            .map(|index| {
+                let next = if upward { index + 1 } else { index - 1 };
+                (
+                    path[index],
+                    forward(nodes, &self.radio, path[index], path[next]),
+                )
+            })
+            .collect()
+    }
+
+    // Mean cost of the routes of the devices taking part in an exchange of a node
+    pub fn mean(&self, nodes: &NodesVec, id: usize, devices: InvolvedDevicesCount) -> RouteCost {
+        if self.is_empty() {
+            return RouteCost::default();
+        }
+        let (mut total, count) = match devices {
+            InvolvedDevicesCount::SameAsDefined => match nodes[id].kind {
+                NodeType::Constrained => (self.costs[id], 1),
+                NodeType::Gateway => (RouteCost::default(), 0),
+            },
+            InvolvedDevicesCount::GatewayMembers => {
+                let gateways = match nodes[id].kind {
+                    NodeType::Gateway => std::slice::from_ref(&id),
+                    NodeType::Constrained => nodes.clusters().gateways(id),
+                };
+                let mut total = (RouteCost::default(), 0);
+                for gateway in gateways {
+                    let (cost, count) = &self.cluster_costs[*gateway];
+                    total.0.add(cost);
+                    total.1 += count;
+                }
+                total
+            }
+            InvolvedDevicesCount::All => self.network_costs,
+            InvolvedDevicesCount::Neighbors => {
+                let mut total = (RouteCost::default(), 0);
+                // The active ones, as the devices being costed
+                for neighbor in nodes.neighbors(id) {
+                    if nodes[*neighbor].kind == NodeType::Constrained
+                        && nodes[*neighbor].is_active()
+                    {
+                        total.0.add(&self.costs[*neighbor]);
+                        total.1 += 1;
+                    }
+                }
+                total
+            }
+        };
+        if count > 0 {
+            total.scale(1.0 / count as f64);
+        }
+        total
+    }
+}
+
+// Energy the busiest relay spends forwarding the rekeying once the nodes flagged with `status` are
+// removed, following the constrained cells as the lifetime debits: for each affected node, the
+// relay forwarding the most routes of the involved devices by what one route carries. The relays
+// of several affected nodes add up, the busiest of the event when they are the same one. With a
+// radio model, a relay spends the mean energy per hop of the routes.
+pub fn busiest_relay(nodes: &NodesVec, status: NodeStatus, metrics: &MetricsType) -> f32 {
+    let routes = nodes.routes();
+    if routes.is_empty() {
+        return 0.0;
+    }
+    let (state, sizes) = match status {
+        NodeStatus::Compromised => (
+            metrics.energy.compromised.constrained,
+            metrics.communication.compromised.constrained,
+        ),
+        NodeStatus::Leaving => (
+            metrics.energy.leaving.constrained,
+            metrics.communication.leaving.constrained,
+        ),
+        NodeStatus::Draining => (
+            metrics.energy.draining.constrained,
+            metrics.communication.draining.constrained,
+        ),
+    };
+    let mut total = 0.0;
+    for node in nodes.iter().filter(|node| node.has_only_status(status)) {
+        if node.kind != NodeType::Constrained {
+            continue;
+        }
+        let load = routes.busiest_load(
+            nodes,
+            node,
+            state.involved_devices,
+            state.number_of_involved_devices,
+        );
+        if load == 0 {
+            continue;
+        }
+        // Messages exchanged with neighbors are not forwarded
+        let routed = |kind: InvolvedExchangesCount, count: u32| {
+            if kind == InvolvedExchangesCount::Neighbors {
+                0.0
+            } else {
+                node.calculate_involved_messages(nodes, kind, count) as f64
+            }
+        };
+        let sent = routed(state.exchange.messages.0, state.exchange.sent);
+        let received = routed(state.exchange.messages.1, state.exchange.received);
+        let per_route = match routes.radio() {
+            None => {
+                (sent + received) * (state.exchange_cost.sent + state.exchange_cost.received) as f64
+            }
+            Some(_) => {
+                let route = routes.mean(nodes, node.id, state.involved_devices);
+                let mut energy = 0.0;
+                if state.exchange_cost.sent != 0.0 {
+                    energy += sent * sizes.exchange_cost.sent as f64 * 8.0 * route.upward;
+                }
+                if state.exchange_cost.received != 0.0 {
+                    energy += received * sizes.exchange_cost.received as f64 * 8.0 * route.downward;
+                }
+                if route.relays > 0.0 {
+                    energy / route.relays
+                } else {
+                    0.0
+                }
+            }
+        };
+        total += load as f64 * per_route;
+    }
+    total as f32
+}
+
+// Energy per bit to send from a node to another, over the default distance when one of them has
+// no position or there is no other node
+fn transmit(nodes: &NodesVec, radio: &RadioConfig, from: usize, to: Option<usize>) -> f64 {
+    let distance = to
+        .and_then(|to| nodes[from].distance(&nodes[to]))
+        .unwrap_or(radio.default_distance());
+    radio.model().map_or(0.0, |model| model.transmit(distance))
+}
+
+// A relay receives the message and sends it on
+fn forward(nodes: &NodesVec, radio: &RadioConfig, from: usize, to: usize) -> f64 {
+    transmit(nodes, radio, from, Some(to)) + radio.model().map_or(0.0, |model| model.receive())
+}
+
+impl RouteCost {
+    fn add(&mut self, other: &RouteCost) {
+        self.relays += other.relays;
+        self.transmit += other.transmit;
+        self.upward += other.upward;
+        self.downward += other.downward;
+    }
+
+    fn scale(&mut self, factor: f64) {
+        self.relays *= factor;
+        self.transmit *= factor;
+        self.upward *= factor;
+        self.downward *= factor;
+    }
+}
+
+// e.g. "hops max=4 mean=2.1 counts=1:40,2:30 unrouted=0 relay_load max=12 mean=1.1 busiest=17"
+impl fmt::Display for Routes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "hops max=1 mean=1");
+        }
+        let mut counts: Vec<usize> = vec![];
+        for id in 0..self.gateways.len() {
+            if !self.is_routed(id) {
+                continue;
+            }
+            let hops = self.hops(id);
+            if hops >= counts.len() {
+                counts.resize(hops + 1, 0);
+            }
+            counts[hops] += 1;
+        }
+        let routed: usize = counts.iter().sum();
+        let histogram: Vec<String> = counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(hops, count)| format!("{}:{}", hops, count))
+            .collect();
+        let busiest = (0..self.load.len())
+            .max_by_key(|id| (self.load[*id], std::cmp::Reverse(*id)))
+            .unwrap_or(0);
+        let forwarders = self.load.iter().filter(|load| **load > 0).count();
+        write!(
+            f,
+            "hops max={} mean={} counts={} unrouted={} relay_load max={} mean={} busiest={}",
+            counts.len().saturating_sub(1),
+            (self.relays.len() + routed) as f64 / routed.max(1) as f64,
+            histogram.join(","),
+            self.unrouted,
+            self.load(busiest),
+            self.relays.len() as f64 / forwarders.max(1) as f64,
+            busiest
+        )
+    }
+}
+
+// Node waiting to be settled, ordered so that the heap pops the cheapest then the lowest id
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Entry {
+    cost: f64,
+    node: usize,
+}
+
+impl Eq for Entry {}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .total_cmp(&self.cost)
+            .then(other.node.cmp(&self.node))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Dijkstra searches from a set of sources, reusing their buffers. A node keeps the first
+// neighbor that reached it at its lowest cost as its parent.
+struct Search {
+    cost: Vec<f64>,
+    parent: Vec<usize>,
+    settled: Vec<bool>,
+    touched: Vec<usize>,
+    heap: BinaryHeap<Entry>,
+}
+
+impl Search {
+    fn new(number_of_nodes: usize) -> Self {
+        Self {
+            cost: vec![f64::INFINITY; number_of_nodes],
+            parent: vec![UNROUTED; number_of_nodes],
+            settled: vec![false; number_of_nodes],
+            touched: vec![],
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    // Settle nodes by increasing cost and return the targets reached. The search stops early
+    // once every target has been settled.
+    fn run<W, T>(
+        &mut self,
+        nodes: &NodesVec,
+        sources: &[usize],
+        weight: W,
+        is_target: T,
+    ) -> Vec<usize>
+    where
+        W: Fn(usize, usize) -> f64,
+        T: Fn(usize) -> bool,
+    {
+        for node in self.touched.drain(..) {
+            self.cost[node] = f64::INFINITY;
+            self.parent[node] = UNROUTED;
+            self.settled[node] = false;
+        }
+        self.heap.clear();
+        let mut remaining = (0..nodes.len()).filter(|id| is_target(*id)).count();
+        for source in sources {
+            self.cost[*source] = 0.0;
+            self.touched.push(*source);
+            self.heap.push(Entry {
+                cost: 0.0,
+                node: *source,
+            });
+        }
+
+        let mut reached = vec![];
+        while let Some(Entry { cost, node }) = self.heap.pop() {
+            if remaining == 0 {
+                break;
+            }
+            if self.settled[node] {
+                continue;
+            }
+            self.settled[node] = true;
+            if is_target(node) {
+                reached.push(node);
+                remaining -= 1;
+            }
+            for neighbor in nodes.neighbors(node) {
+                if !nodes[*neighbor].is_alive() {
+                    continue;
+                }
+                let candidate = cost + weight(node, *neighbor);
+                if !self.settled[*neighbor] && candidate < self.cost[*neighbor] {
+                    if self.cost[*neighbor].is_infinite() {
+                        self.touched.push(*neighbor);
+                    }
+                    self.cost[*neighbor] = candidate;
+                    self.parent[*neighbor] = node;
+                    self.heap.push(Entry {
+                        cost: candidate,
+                        node: *neighbor,
+                    });
+                }
+            }
+        }
+        reached
+    }
+
+    // The node, its relays and the source it was reached from
+    fn path(&self, node: usize) -> Option<Vec<usize>> {
+        if !self.settled[node] {
+            return None;
+        }
+        let mut path = vec![node];
+        let mut current = node;
+        while self.parent[current] != UNROUTED {
+            current = self.parent[current];
+            path.push(current);
+        }
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenario::Scenario, topology};
+
+    #[test]
+    fn neighbor_routes_are_averaged_over_the_active_devices() {
+        let mut scenario: Scenario = toml::from_str(
+            r#"
+            [topology]
+            number_of_nodes = 30
+            number_of_gateways = 2
+            number_of_min_possible_neighbors = 3
+            number_of_max_possible_neighbors = 5
+            number_of_gateway_members = 14
+
+            [costs.radio]
+            kind = "first_order"
+            "#,
+        )
+        .unwrap();
+        scenario.routing.strategy = RoutingStrategy::ShortestPath;
+        let mut nodes = topology::generate(&scenario.topology, 4).unwrap();
+        let routes = Routes::build(&nodes, &scenario.routing, &scenario.costs.radio);
+        nodes.set_routes(routes);
+        let id = (0..nodes.len())
+            .find(|id| nodes[*id].kind == NodeType::Constrained)
+            .unwrap();
+        // Take one constrained neighbor out of the exchange with the node
+        let neighbor = *nodes
+            .neighbors(id)
+            .iter()
+            .find(|neighbor| nodes[**neighbor].kind == NodeType::Constrained)
+            .unwrap();
+        nodes.affect_node_ids(NodeStatus::Compromised, &[id, neighbor]);
+        let devices = nodes.involved_devices(&nodes[id], InvolvedDevicesCount::Neighbors, 0);
+        assert!(!devices.contains(&neighbor));
+        let routes = nodes.routes();
+        let mut expected = RouteCost::default();
+        for device in devices.iter() {
+            expected.add(&routes.costs[*device]);
+        }
+        expected.scale(1.0 / devices.len() as f64);
+        let mean = routes.mean(&nodes, id, InvolvedDevicesCount::Neighbors);
+        assert!(mean.transmit > 0.0);
+        assert_eq!(mean, expected);
+    }
+}
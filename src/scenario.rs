@@ -0,0 +1,380 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use serde::Deserialize;
+
+use crate::{
+    crypto::CryptoCosts,
+    latency::TimingConfig,
+    lifetime::LifetimeConfig,
+    methods::BUILTIN_SCHEMES,
+    radio::RadioConfig,
+    routing::RoutingConfig,
+    sweep::{Sweep, SweepConfig},
+    topology::{
+        self,
+        analysis::Connectivity,
+        clusters::ClusterConfig,
+        geometric::{GeometricConfig, Layout},
+        io::FileConfig,
+        random::{BarabasiAlbertConfig, ErdosRenyiConfig, WattsStrogatzConfig},
+        structured::{LatticeConfig, TreeConfig},
+        TopologyModel,
+    },
+};
+
+// A scenario describes one experiment: the topology to generate, the cost constants used by the
+// schemes, the schemes to compare and how many iterations to run for each affected node count.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub topology: TopologyConfig,
+    #[serde(default)]
+    pub costs: CostConstants,
+    // How rekeying messages travel between constrained nodes and their gateway
+    #[serde(default)]
+    pub routing: RoutingConfig,
+    #[serde(default)]
+    pub simulation: SimulationConfig,
+    // Battery and event stream of the lifetime command
+    #[serde(default)]
+    pub lifetime: LifetimeConfig,
+    #[serde(default = "default_schemes")]
+    pub schemes: Vec<String>,
+    // Schemes described in data files, relative to the scenario file
+    #[serde(default)]
+    pub scheme_files: Vec<PathBuf>,
+    // Parameters varied by the sweep command
+    #[serde(default)]
+    pub sweep: SweepConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TopologyConfig {
+    pub number_of_nodes: u32,
+    pub number_of_gateways: u32,
+    pub number_of_min_possible_neighbors: u32,
+    pub number_of_max_possible_neighbors: u32,
+    pub number_of_gateway_members: u32,
+    #[serde(default)]
+    pub model: TopologyModel,
+    #[serde(default)]
+    pub geometric: Option<GeometricConfig>,
+    #[serde(default)]
+    pub erdos_renyi: Option<ErdosRenyiConfig>,
+    #[serde(default)]
+    pub barabasi_albert: Option<BarabasiAlbertConfig>,
+    #[serde(default)]
+    pub watts_strogatz: Option<WattsStrogatzConfig>,
+    #[serde(default)]
+    pub lattice: Option<LatticeConfig>,
+    #[serde(default)]
+    pub tree: Option<TreeConfig>,
+    #[serde(default)]
+    pub file: Option<FileConfig>,
+    // Assignment of the constrained nodes to gateways
+    #[serde(default)]
+    pub clusters: ClusterConfig,
+    // Networks that do not connect as required are refused instead of simulated
+    #[serde(default)]
+    pub connectivity: Connectivity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct CostConstants {
+    pub epsb: f32,
+    pub eprb: f32,
+    // Bytes
+    pub sent_message_size: u32,
+    pub received_message_size: u32,
+    pub radio: RadioConfig,
+    // Energy of the cryptographic operations listed by the schemes, none when they are free
+    pub crypto: Option<CryptoCosts>,
+    // Link and processing times of the time to rekey
+    pub timing: TimingConfig,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct SimulationConfig {
+    pub seed: Option<u64>,
+    pub iterations: usize,
+    pub min_affected_nodes: usize,
+    pub max_affected_nodes: usize,
+    // Worker threads running the iterations, all available cores by default
+    pub threads: Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    Invalid {
+        field: &'static str,
+        reason: String,
+    },
+}
+
+impl Default for CostConstants {
+    fn default() -> Self {
+        Self {
+            epsb: 0.0001,
+            eprb: 0.0001,
+            sent_message_size: 16,
+            received_message_size: 16,
+            radio: RadioConfig::Flat,
+            crypto: None,
+            timing: TimingConfig::default(),
+        }
+    }
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            iterations: 1000,
+            min_affected_nodes: 1,
+            max_affected_nodes: 10,
+            threads: None,
+        }
+    }
+}
+
+fn default_schemes() -> Vec<String> {
+    BUILTIN_SCHEMES
+        .iter()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+impl TopologyConfig {
+    // Average number of neighbors a node is expected to have, used by the schemes as the
+    // static neighbor count
+    pub fn average_neighbors(&self) -> u32 {
+        (self.number_of_min_possible_neighbors + self.number_of_max_possible_neighbors) / 2
+    }
+}
+
+impl SimulationConfig {
+    pub fn threads(&self) -> usize {
+        self.threads.unwrap_or_else(|| {
+            thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1)
+        })
+    }
+}
+
+impl Scenario {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let scenario = Self::read(path)?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    // Parse a scenario without validating it, so callers can override values first
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(|source| ScenarioError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut scenario: Scenario =
+            toml::from_str(&contents).map_err(|source| ScenarioError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        if let Some(directory) = path.parent() {
+            for scheme_file in scenario.scheme_files.iter_mut() {
+                if scheme_file.is_relative() {
+                    *scheme_file = directory.join(&*scheme_file);
+                }
+            }
+            if let Some(GeometricConfig {
+                layout: Layout::File { positions },
+                ..
+            }) = &mut scenario.topology.geometric
+            {
+                if positions.is_relative() {
+                    *positions = directory.join(&*positions);
+                }
+            }
+            if let Some(file) = &mut scenario.topology.file {
+                if file.path.is_relative() {
+                    file.path = directory.join(&file.path);
+                }
+            }
+        }
+        Ok(scenario)
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        let topology = &self.topology;
+        if topology.number_of_nodes == 0 {
+            return Err(invalid(
+                "topology.number_of_nodes",
+                "must be greater than 0",
+            ));
+        }
+        if topology.number_of_gateways > topology.number_of_nodes {
+            return Err(invalid(
+                "topology.number_of_gateways",
+                format!(
+                    "{} gateways exceed the {} nodes of the network",
+                    topology.number_of_gateways, topology.number_of_nodes
+                ),
+            ));
+        }
+        if topology.number_of_min_possible_neighbors > topology.number_of_max_possible_neighbors {
+            return Err(invalid(
+                "topology.number_of_min_possible_neighbors",
+                format!(
+                    "minimum ({}) is greater than maximum ({})",
+                    topology.number_of_min_possible_neighbors,
+                    topology.number_of_max_possible_neighbors
+                ),
+            ));
+        }
+        if topology.number_of_max_possible_neighbors >= topology.number_of_nodes {
+            return Err(invalid(
+                "topology.number_of_max_possible_neighbors",
+                format!(
+                    "a node cannot have {} neighbors in a network of {} nodes",
+                    topology.number_of_max_possible_neighbors, topology.number_of_nodes
+                ),
+            ));
+        }
+
+        if let Err(err) = topology::validate(topology) {
+            return Err(invalid("topology", err.to_string()));
+        }
+
+        if let Err(reason) = self.routing.validate() {
+            return Err(invalid("routing.path_loss_exponent", reason));
+        }
+
+        let costs = &self.costs;
+        for (field, value) in [("costs.epsb", costs.epsb), ("costs.eprb", costs.eprb)] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(invalid(
+                    field,
+                    format!("{} is not a non-negative number", value),
+                ));
+            }
+        }
+
+        if let Err(reason) = costs.radio.validate() {
+            return Err(invalid("costs.radio", reason));
+        }
+        if let Some(Err(reason)) = costs.crypto.map(|crypto| crypto.validate()) {
+            return Err(invalid("costs.crypto", reason));
+        }
+        if let Err(reason) = costs.timing.validate() {
+            return Err(invalid("costs.timing", reason));
+        }
+
+        let simulation = &self.simulation;
+        if simulation.iterations == 0 {
+            return Err(invalid("simulation.iterations", "must be greater than 0"));
+        }
+        if simulation.threads == Some(0) {
+            return Err(invalid("simulation.threads", "must be greater than 0"));
+        }
+        if simulation.min_affected_nodes == 0 {
+            return Err(invalid(
+                "simulation.min_affected_nodes",
+                "must be greater than 0",
+            ));
+        }
+        if simulation.min_affected_nodes > simulation.max_affected_nodes {
+            return Err(invalid(
+                "simulation.min_affected_nodes",
+                format!(
+                    "minimum ({}) is greater than maximum ({})",
+                    simulation.min_affected_nodes, simulation.max_affected_nodes
+                ),
+            ));
+        }
+        if simulation.max_affected_nodes > topology.number_of_nodes as usize {
+            return Err(invalid(
+                "simulation.max_affected_nodes",
+                format!(
+                    "cannot affect {} nodes in a network of {} nodes",
+                    simulation.max_affected_nodes, topology.number_of_nodes
+                ),
+            ));
+        }
+
+        if let Err((field, reason)) = self.lifetime.validate() {
+            return Err(invalid(field, reason));
+        }
+
+        if self.schemes.is_empty() && self.scheme_files.is_empty() {
+            return Err(invalid(
+                "schemes",
+                "at least one scheme or scheme file must be listed",
+            ));
+        }
+        for scheme in self.schemes.iter() {
+            if !BUILTIN_SCHEMES.contains(&scheme.as_str()) {
+                return Err(invalid(
+                    "schemes",
+                    format!(
+                        "unknown scheme \"{}\" (expected one of: {})",
+                        scheme,
+                        BUILTIN_SCHEMES.join(", ")
+                    ),
+                ));
+            }
+        }
+        if let Err(err) = Sweep::new(&self.sweep) {
+            return Err(invalid("sweep", err.to_string()));
+        }
+        Ok(())
+    }
+}
+
+fn invalid(field: &'static str, reason: impl Into<String>) -> ScenarioError {
+    ScenarioError::Invalid {
+        field,
+        reason: reason.into(),
+    }
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io { path, source } => {
+                write!(f, "cannot read scenario {}: {}", path.display(), source)
+            }
+            ScenarioError::Parse { path, source } => {
+                write!(f, "cannot parse scenario {}: {}", path.display(), source)
+            }
+            ScenarioError::Invalid { field, reason } => {
+                write!(f, "invalid scenario value for {}: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ScenarioError::Io { source, .. } => Some(source),
+            ScenarioError::Parse { source, .. } => Some(source),
+            ScenarioError::Invalid { .. } => None,
+        }
+    }
+}
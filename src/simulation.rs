@@ -0,0 +1,260 @@
+use std::{ops::Range, thread};
+
+use crate::{
+    events::EventSet,
+    methods::{EventCost, SchemeRegistry},
+    scenario::SimulationConfig,
+    stats::{StreamingStats, Summary},
+    NodeStatus, NodesVec,
+};
+
+// Per event costs of every scheme, for every status, affected node count and iteration
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationResults {
+    pub seed: u64,
+    pub schemes: Vec<String>,
+    pub iterations: usize,
+    pub min_affected_nodes: usize,
+    pub max_affected_nodes: usize,
+    samples: Vec<EventCost>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostSummary {
+    pub energy: Summary,
+    pub computation: Summary,
+    pub communication: Summary,
+    pub latency: Summary,
+    pub relay: Summary,
+}
+
+// Cost of one scheme for one iteration, affected count and status
+struct SampleCost {
+    iteration: usize,
+    affected: usize,
+    status: NodeStatus,
+    scheme: usize,
+    cost: EventCost,
+}
+
+impl SimulationResults {
+    fn new(seed: u64, schemes: Vec<String>, config: &SimulationConfig) -> Self {
+        let mut results = Self {
+            seed,
+            schemes,
+            iterations: config.iterations,
+            min_affected_nodes: config.min_affected_nodes,
+            max_affected_nodes: config.max_affected_nodes,
+            samples: vec![],
+        };
+        let len = results.schemes.len()
+            * NodeStatus::ALL.len()
+            * (results.max_affected_nodes - results.min_affected_nodes + 1)
+            * results.iterations;
+        results.samples = vec![EventCost::default(); len];
+        results
+    }
+
+    pub fn affected_counts(&self) -> std::ops::RangeInclusive<usize> {
+        self.min_affected_nodes..=self.max_affected_nodes
+    }
+
+    fn offset(&self, scheme: usize, status: NodeStatus, affected: usize) -> usize {
+        let affected_index = affected - self.min_affected_nodes;
+        ((scheme * NodeStatus::ALL.len() + status.index())
+            * (self.max_affected_nodes - self.min_affected_nodes + 1)
+            + affected_index)
+            * self.iterations
+    }
+
+    // Costs of all iterations for one scheme (by registry position), status and affected count
+    pub fn samples(&self, scheme: usize, status: NodeStatus, affected: usize) -> &[EventCost] {
+        let offset = self.offset(scheme, status, affected);
+        &self.samples[offset..offset + self.iterations]
+    }
+
+    pub fn average(&self, scheme: usize, status: NodeStatus, affected: usize) -> EventCost {
+        let summary = self.summary(scheme, status, affected);
+        EventCost {
+            energy: summary.energy.mean as f32,
+            computation: summary.computation.mean as f32,
+            communication: summary.communication.mean as f32,
+            latency: summary.latency.mean as f32,
+            relay: summary.relay.mean as f32,
+        }
+    }
+
+    pub fn summary(&self, scheme: usize, status: NodeStatus, affected: usize) -> CostSummary {
+        let mut energy = StreamingStats::new();
+        let mut computation = StreamingStats::new();
+        let mut communication = StreamingStats::new();
+        let mut latency = StreamingStats::new();
+        let mut relay = StreamingStats::new();
+        for sample in self.samples(scheme, status, affected) {
+            energy.push(sample.energy as f64);
+            computation.push(sample.computation as f64);
+            communication.push(sample.communication as f64);
+            latency.push(sample.latency as f64);
+            relay.push(sample.relay as f64);
+        }
+        CostSummary {
+            energy: energy.summary(),
+            computation: computation.summary(),
+            communication: communication.summary(),
+            latency: latency.summary(),
+            relay: relay.summary(),
+        }
+    }
+}
+
+// Run every registered scheme on the same network. For each iteration and affected node count,
+// one event set is generated from the seed and replayed for every scheme. Iterations are split
+// between worker threads, each with its own copy of the network; since every event set only
+// depends on its iteration and affected count, the results do not depend on the thread count.
+pub fn simulate(
+    nodes: &NodesVec,
+    registry: &SchemeRegistry,
+    config: &SimulationConfig,
+    seed: u64,
+) -> SimulationResults {
+    let mut results = SimulationResults::new(seed, registry.names(), config);
+    let threads = config.threads().clamp(1, config.iterations.max(1));
+    let chunk_size = config.iterations.div_ceil(threads);
+    let chunks: Vec<Vec<SampleCost>> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|thread| {
+                let start = (thread * chunk_size).min(config.iterations);
+                let end = ((thread + 1) * chunk_size).min(config.iterations);
+                scope.spawn(move || {
+                    let mut nodes = nodes.clone();
+                    simulate_iterations(&mut nodes, registry, config, seed, start..end)
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("simulation thread panicked"))
+            .collect()
+    });
+
+    // Every sample is set by exactly one worker
+    let mut filled = vec![false; results.samples.len()];
+    for sample in chunks.into_iter().flatten() {
+        let index =
+            results.offset(sample.scheme, sample.status, sample.affected) + sample.iteration;
+        assert!(!filled[index], "sample computed twice");
+        filled[index] = true;
+        results.samples[index] = sample.cost;
+    }
+    assert!(
+        filled.iter().all(|filled| *filled),
+        "sample left out by the workers"
+    );
+    results
+}
+
+// Costs of a range of iterations, each with the sample it belongs to
+fn simulate_iterations(
+    nodes: &mut NodesVec,
+    registry: &SchemeRegistry,
+    config: &SimulationConfig,
+    seed: u64,
+    iterations: Range<usize>,
+) -> Vec<SampleCost> {
+    let mut costs = vec![];
+    for iteration in iterations {
+        for affected in config.min_affected_nodes..=config.max_affected_nodes {
+            let events = EventSet::generate(nodes.len(), seed, iteration, affected);
+            for status in NodeStatus::ALL {
+                let replayed = replay(nodes, registry, &events, status);
+                costs.extend(
+                    replayed
+                        .into_iter()
+                        .enumerate()
+                        .map(|(scheme, cost)| SampleCost {
+                            iteration,
+                            affected,
+                            status,
+                            scheme,
+                            cost,
+                        }),
+                );
+            }
+        }
+    }
+    costs
+}
+
+// Cost of one status of an event set for every registered scheme, in registry order
+pub fn replay(
+    nodes: &mut NodesVec,
+    registry: &SchemeRegistry,
+    events: &EventSet,
+    status: NodeStatus,
+) -> Vec<EventCost> {
+    events.apply(status, nodes);
+    let costs = registry
+        .iter()
+        .map(|scheme| scheme.event_cost(nodes, status))
+        .collect();
+    nodes.reset();
+    costs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenario::Scenario, topology};
+
+    fn scenario() -> Scenario {
+        toml::from_str(
+            r#"
+            schemes = ["bkrsc", "others"]
+
+            [topology]
+            number_of_nodes = 40
+            number_of_gateways = 4
+            number_of_min_possible_neighbors = 3
+            number_of_max_possible_neighbors = 6
+            number_of_gateway_members = 9
+
+            [simulation]
+            iterations = 10
+            min_affected_nodes = 1
+            max_affected_nodes = 3
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn results_do_not_depend_on_the_thread_count() {
+        let mut scenario = scenario();
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let nodes = topology::generate(&scenario.topology, 7).unwrap();
+        scenario.simulation.threads = Some(1);
+        let single = simulate(&nodes, &registry, &scenario.simulation, 7);
+        for threads in [3, 8, 20] {
+            scenario.simulation.threads = Some(threads);
+            assert_eq!(simulate(&nodes, &registry, &scenario.simulation, 7), single);
+        }
+    }
+
+    #[test]
+    fn replay_reproduces_the_costs_of_a_run() {
+        let scenario = scenario();
+        let registry = SchemeRegistry::from_scenario(&scenario).unwrap();
+        let mut nodes = topology::generate(&scenario.topology, 7).unwrap();
+        let results = simulate(&nodes, &registry, &scenario.simulation, 7);
+        let (iteration, affected) = (3, 2);
+        let events = EventSet::generate(nodes.len(), 7, iteration, affected);
+        for status in NodeStatus::ALL {
+            let costs = replay(&mut nodes, &registry, &events, status);
+            for (scheme, cost) in costs.into_iter().enumerate() {
+                assert_eq!(results.samples(scheme, status, affected)[iteration], cost);
+            }
+        }
+        // The network is left clean for the next replay
+        assert!(nodes.iter().all(|node| node.is_active()));
+    }
+}
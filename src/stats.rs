@@ -0,0 +1,508 @@
+use serde::Serialize;
+
+// Single pass statistics over a stream of values: Welford's algorithm for the mean and variance,
+// and the P² algorithm (Jain & Chlamtac, 1985) for the quantiles, so no sample has to be kept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamingStats {
+    count: usize,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+    p5: P2Quantile,
+    median: P2Quantile,
+    p95: P2Quantile,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub median: f64,
+    pub p5: f64,
+    pub p95: f64,
+    // 95% confidence interval of the mean, NaN with less than two samples
+    pub ci_low: f64,
+    pub ci_high: f64,
+}
+
+// Estimate of one quantile from five markers whose heights are adjusted with a piecewise
+// parabolic interpolation as values come in
+#[derive(Debug, Clone, PartialEq)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl Default for StreamingStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StreamingStats {
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            p5: P2Quantile::new(0.05),
+            median: P2Quantile::new(0.5),
+            p95: P2Quantile::new(0.95),
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.p5.push(value);
+        self.median.push(value);
+        self.p95.push(value);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            f64::NAN
+        } else {
+            self.mean
+        }
+    }
+
+    // Sample variance, with Bessel's correction
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            f64::NAN
+        } else {
+            self.m2 / (self.count - 1) as f64
+        }
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    pub fn summary(&self) -> Summary {
+        let (ci_low, ci_high) = if self.count < 2 {
+            (f64::NAN, f64::NAN)
+        } else {
+            let degrees_of_freedom = (self.count - 1) as f64;
+            let half_width = student_t_quantile(0.975, degrees_of_freedom) * self.std_dev()
+                / (self.count as f64).sqrt();
+            (self.mean - half_width, self.mean + half_width)
+        };
+        let (min, max) = if self.count == 0 {
+            (f64::NAN, f64::NAN)
+        } else {
+            (self.min, self.max)
+        };
+        Summary {
+            count: self.count,
+            mean: self.mean(),
+            std_dev: self.std_dev(),
+            min,
+            max,
+            median: self.median.estimate(),
+            p5: self.p5.estimate(),
+            p95: self.p95.estimate(),
+            ci_low,
+            ci_high,
+        }
+    }
+}
+
+impl<T: Into<f64>> FromIterator<T> for StreamingStats {
+    fn from_iter<I: IntoIterator<Item = T>>(values: I) -> Self {
+        let mut stats = Self::new();
+        for value in values {
+            stats.push(value.into());
+        }
+        stats
+    }
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> Self {
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+
+        // Find the cell of the new value, extending the extreme markers if needed
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (1..5)
+                .find(|index| value < self.heights[*index])
+                .map(|index| index - 1)
+                .unwrap_or(3)
+        };
+        for position in self.positions[cell + 1..].iter_mut() {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        // Move the middle markers towards their desired positions
+        for index in 1..4 {
+            let offset = self.desired[index] - self.positions[index];
+            let room_after = self.positions[index + 1] - self.positions[index];
+            let room_before = self.positions[index - 1] - self.positions[index];
+            if (offset >= 1.0 && room_after > 1.0) || (offset <= -1.0 && room_before < -1.0) {
+                let step = offset.signum();
+                let parabolic = self.parabolic(index, step);
+                self.heights[index] =
+                    if self.heights[index - 1] < parabolic && parabolic < self.heights[index + 1] {
+                        parabolic
+                    } else {
+                        self.linear(index, step)
+                    };
+                self.positions[index] += step;
+            }
+        }
+    }
+
+    fn parabolic(&self, index: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        q[index]
+            + step / (n[index + 1] - n[index - 1])
+                * ((n[index] - n[index - 1] + step) * (q[index + 1] - q[index])
+                    / (n[index + 1] - n[index])
+                    + (n[index + 1] - n[index] - step) * (q[index] - q[index - 1])
+                        / (n[index] - n[index - 1]))
+    }
+
+    fn linear(&self, index: usize, step: f64) -> f64 {
+        let other = if step > 0.0 { index + 1 } else { index - 1 };
+        self.heights[index]
+            + step * (self.heights[other] - self.heights[index])
+                / (self.positions[other] - self.positions[index])
+    }
+
+    pub fn estimate(&self) -> f64 {
+        match self.count {
+            0 => f64::NAN,
+            // Too few values for the markers, use the exact quantile of what was seen
+            1..=5 => {
+                let mut values = self.heights[..self.count].to_vec();
+                values.sort_by(f64::total_cmp);
+                let rank = self.p * (self.count - 1) as f64;
+                let below = rank.floor() as usize;
+                let above = rank.ceil() as usize;
+                values[below] + (rank - below as f64) * (values[above] - values[below])
+            }
+            _ => self.heights[2],
+        }
+    }
+}
+
+// Quantile of the Student t distribution, found by bisection on its distribution function
+pub fn student_t_quantile(p: f64, degrees_of_freedom: f64) -> f64 {
+    if p == 0.5 {
+        return 0.0;
+    }
+    if p < 0.5 {
+        return -student_t_quantile(1.0 - p, degrees_of_freedom);
+    }
+    let mut low = 0.0;
+    let mut high = 1.0;
+    while student_t_cdf(high, degrees_of_freedom) < p {
+        high *= 2.0;
+    }
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if student_t_cdf(middle, degrees_of_freedom) < p {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    (low + high) / 2.0
+}
+
+pub fn student_t_cdf(t: f64, degrees_of_freedom: f64) -> f64 {
+    let x = degrees_of_freedom / (degrees_of_freedom + t * t);
+    let tail = 0.5 * regularized_incomplete_beta(x, degrees_of_freedom / 2.0, 0.5);
+    if t >= 0.0 {
+        1.0 - tail
+    } else {
+        tail
+    }
+}
+
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+// Complementary error function, Chebyshev fit of Numerical Recipes (section 6.2) with a
+// fractional error below 1.2e-7
+pub fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let result = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+// I_x(a, b), evaluated with the continued fraction of Numerical Recipes (section 6.4)
+pub fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    // The continued fraction converges quickly only below this point, use the symmetry otherwise
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const TINY: f64 = 1e-300;
+    const EPSILON: f64 = 1e-15;
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut result = d;
+    for m in 1..=300 {
+        let m = m as f64;
+        let m2 = 2.0 * m;
+        let even = m * (b - m) * x / ((a + m2 - 1.0) * (a + m2));
+        d = 1.0 + even * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + even / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        result *= d * c;
+        let odd = -(a + m) * (a + b + m) * x / ((a + m2) * (a + m2 + 1.0));
+        d = 1.0 + odd * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + odd / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        result *= delta;
+        if (delta - 1.0).abs() < EPSILON {
+            break;
+        }
+    }
+    result
+}
+
+// Lanczos approximation (g = 7, n = 9), accurate to about 15 digits for positive arguments
+pub fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        // Reflection formula
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = COEFFICIENTS[0];
+    for (index, coefficient) in COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + index as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::derive_rng;
+    use rand::Rng;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn student_t_quantiles_match_the_tables() {
+        assert_close(student_t_quantile(0.975, 10.0), 2.228_139, 1e-6);
+        assert_close(student_t_quantile(0.975, 1.0), 12.706_205, 1e-6);
+        assert_close(student_t_quantile(0.95, 5.0), 2.015_048, 1e-6);
+        assert_close(student_t_quantile(0.995, 30.0), 2.749_996, 1e-6);
+        assert_close(student_t_quantile(0.025, 10.0), -2.228_139, 1e-6);
+        assert_eq!(student_t_quantile(0.5, 4.0), 0.0);
+        assert_close(student_t_cdf(2.228_139, 10.0), 0.975, 1e-7);
+        assert_close(student_t_cdf(-2.228_139, 10.0), 0.025, 1e-7);
+    }
+
+    #[test]
+    fn incomplete_beta_matches_closed_forms() {
+        // I_x(1, 1) = x, I_x(a, 1) = x^a and I_0.5(a, a) = 1/2
+        assert_close(regularized_incomplete_beta(0.3, 1.0, 1.0), 0.3, 1e-12);
+        assert_close(regularized_incomplete_beta(0.6, 3.0, 1.0), 0.216, 1e-12);
+        assert_close(regularized_incomplete_beta(0.5, 4.5, 4.5), 0.5, 1e-12);
+        // Binomial tail: I_0.3(2, 3) = P(X >= 2) for X ~ B(4, 0.3)
+        assert_close(regularized_incomplete_beta(0.3, 2.0, 3.0), 0.3483, 1e-12);
+        // Above the switch to the symmetry relation
+        assert_close(
+            regularized_incomplete_beta(0.8, 5.0, 0.5),
+            0.144_927_605,
+            1e-9,
+        );
+        assert_eq!(regularized_incomplete_beta(0.0, 2.0, 3.0), 0.0);
+        assert_eq!(regularized_incomplete_beta(1.0, 2.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn ln_gamma_matches_known_values() {
+        assert_close(ln_gamma(1.0), 0.0, 1e-13);
+        assert_close(ln_gamma(5.0), 24.0_f64.ln(), 1e-13);
+        assert_close(ln_gamma(0.5), std::f64::consts::PI.sqrt().ln(), 1e-13);
+        assert_close(ln_gamma(10.3), 13.482_036_786_138_36, 1e-12);
+        // Through the reflection formula
+        assert_close(ln_gamma(0.25), 1.288_022_524_698_077_5, 1e-13);
+    }
+
+    #[test]
+    fn erfc_is_within_its_fractional_error() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (0.3, 0.671_373_240_540_872_6),
+            (1.0, 0.157_299_207_050_285_1),
+            (-1.0, 1.842_700_792_949_715),
+            (2.0, 0.004_677_734_981_047_266),
+        ] {
+            assert_close(erfc(x), expected, 1.2e-7 * expected);
+        }
+        assert_close(normal_cdf(1.959_964), 0.975, 1e-7);
+    }
+
+    #[test]
+    fn welford_gives_the_sample_mean_and_variance() {
+        let stats: StreamingStats = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]
+            .into_iter()
+            .collect();
+        assert_eq!(stats.count(), 8);
+        assert_close(stats.mean(), 5.0, 1e-12);
+        assert_close(stats.variance(), 32.0 / 7.0, 1e-12);
+        let summary = stats.summary();
+        assert_eq!((summary.min, summary.max), (2.0, 9.0));
+        // t(0.975, 7) = 2.364624
+        let half_width = 2.364_624 * (32.0_f64 / 7.0).sqrt() / 8.0_f64.sqrt();
+        assert_close(summary.ci_low, 5.0 - half_width, 1e-5);
+        assert_close(summary.ci_high, 5.0 + half_width, 1e-5);
+
+        // A large offset must not eat the variance, as it does in the naive sum of squares
+        let shifted: StreamingStats = [4.0, 7.0, 13.0, 16.0]
+            .into_iter()
+            .map(|value| value + 1e9)
+            .collect();
+        assert_close(shifted.mean(), 1e9 + 10.0, 1e-6);
+        assert_close(shifted.variance(), 30.0, 1e-6);
+
+        let empty = StreamingStats::new();
+        assert!(empty.mean().is_nan() && empty.variance().is_nan());
+        let single: StreamingStats = [3.0].into_iter().collect();
+        assert!(single.variance().is_nan() && single.summary().ci_low.is_nan());
+    }
+
+    #[test]
+    fn p2_follows_the_exact_quantiles_of_a_sorted_sample() {
+        let mut rng = derive_rng(11, &[]);
+        let values: Vec<f64> = (0..5000)
+            .map(|_| rng.gen::<f64>().powi(2) * 100.0)
+            .collect();
+        let mut sorted = values.clone();
+        sorted.sort_by(f64::total_cmp);
+        let exact = |p: f64| sorted[(p * (sorted.len() - 1) as f64).round() as usize];
+        for p in [0.05, 0.5, 0.95] {
+            let mut quantile = P2Quantile::new(p);
+            for value in &values {
+                quantile.push(*value);
+            }
+            assert_close(quantile.estimate(), exact(p), 1.0);
+        }
+
+        // Up to five values the quantile is interpolated exactly
+        let mut median = P2Quantile::new(0.5);
+        assert!(median.estimate().is_nan());
+        for value in [9.0, 1.0, 5.0, 3.0] {
+            median.push(value);
+        }
+        assert_close(median.estimate(), 4.0, 1e-12);
+    }
+}
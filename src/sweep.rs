@@ -0,0 +1,444 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    rng::{derive_rng, SWEEP_STREAM},
+    scenario::Scenario,
+};
+
+// A scenario value a sweep can vary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SweepParameter {
+    NumberOfNodes,
+    NumberOfGateways,
+    NumberOfMinPossibleNeighbors,
+    NumberOfMaxPossibleNeighbors,
+    NumberOfGatewayMembers,
+    Epsb,
+    Eprb,
+    SentMessageSize,
+    ReceivedMessageSize,
+    Iterations,
+    MinAffectedNodes,
+    MaxAffectedNodes,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepMode {
+    // Every combination of the listed values
+    #[default]
+    Cartesian,
+    // `samples` points covering each parameter's values evenly, for grids too large to run whole
+    LatinHypercube,
+}
+
+// The [sweep] table of a scenario, e.g.
+//
+//     [sweep]
+//     mode = "latin_hypercube"
+//     samples = 20
+//     [sweep.parameters]
+//     nodes = "100..100000*10"
+//     "costs.epsb" = [0.00001, 0.0001]
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    #[serde(default)]
+    pub mode: SweepMode,
+    pub samples: Option<usize>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String, SweepValues>,
+}
+
+// The values of one parameter: a list, or an inclusive range written "start..end+step" for a
+// linear progression or "start..end*factor" for a geometric one
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum SweepValues {
+    List(Vec<f64>),
+    Range(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    dimensions: Vec<(SweepParameter, Vec<f64>)>,
+    mode: SweepMode,
+    samples: Option<usize>,
+}
+
+// One scenario of a sweep with the values it was given
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepPoint {
+    pub values: Vec<(SweepParameter, f64)>,
+    pub scenario: Scenario,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepError {
+    UnknownParameter(String),
+    InvalidValues { parameter: String, reason: String },
+    MissingSamples,
+}
+
+impl SweepParameter {
+    pub const ALL: [SweepParameter; 12] = [
+        SweepParameter::NumberOfNodes,
+        SweepParameter::NumberOfGateways,
+        SweepParameter::NumberOfMinPossibleNeighbors,
+        SweepParameter::NumberOfMaxPossibleNeighbors,
+        SweepParameter::NumberOfGatewayMembers,
+        SweepParameter::Epsb,
+        SweepParameter::Eprb,
+        SweepParameter::SentMessageSize,
+        SweepParameter::ReceivedMessageSize,
+        SweepParameter::Iterations,
+        SweepParameter::MinAffectedNodes,
+        SweepParameter::MaxAffectedNodes,
+    ];
+
+    // Short name, the same as the command line flag without its dashes
+    pub fn name(&self) -> &'static str {
+        match self {
+            SweepParameter::NumberOfNodes => "nodes",
+            SweepParameter::NumberOfGateways => "gateways",
+            SweepParameter::NumberOfMinPossibleNeighbors => "min-neighbors",
+            SweepParameter::NumberOfMaxPossibleNeighbors => "max-neighbors",
+            SweepParameter::NumberOfGatewayMembers => "gateway-members",
+            SweepParameter::Epsb => "epsb",
+            SweepParameter::Eprb => "eprb",
+            SweepParameter::SentMessageSize => "sent-message-size",
+            SweepParameter::ReceivedMessageSize => "received-message-size",
+            SweepParameter::Iterations => "iterations",
+            SweepParameter::MinAffectedNodes => "min-affected",
+            SweepParameter::MaxAffectedNodes => "max-affected",
+        }
+    }
+
+    // Path of the value in the scenario file
+    pub fn path(&self) -> &'static str {
+        match self {
+            SweepParameter::NumberOfNodes => "topology.number_of_nodes",
+            SweepParameter::NumberOfGateways => "topology.number_of_gateways",
+            SweepParameter::NumberOfMinPossibleNeighbors => {
+                "topology.number_of_min_possible_neighbors"
+            }
+            SweepParameter::NumberOfMaxPossibleNeighbors => {
+                "topology.number_of_max_possible_neighbors"
+            }
+            SweepParameter::NumberOfGatewayMembers => "topology.number_of_gateway_members",
+            SweepParameter::Epsb => "costs.epsb",
+            SweepParameter::Eprb => "costs.eprb",
+            SweepParameter::SentMessageSize => "costs.sent_message_size",
+            SweepParameter::ReceivedMessageSize => "costs.received_message_size",
+            SweepParameter::Iterations => "simulation.iterations",
+            SweepParameter::MinAffectedNodes => "simulation.min_affected_nodes",
+            SweepParameter::MaxAffectedNodes => "simulation.max_affected_nodes",
+        }
+    }
+
+    // Accept both the short name and the scenario path
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|parameter| parameter.name() == name || parameter.path() == name)
+    }
+
+    fn is_integer(&self) -> bool {
+        !matches!(self, SweepParameter::Epsb | SweepParameter::Eprb)
+    }
+
+    pub fn get(&self, scenario: &Scenario) -> f64 {
+        let (topology, costs, simulation) =
+            (&scenario.topology, &scenario.costs, &scenario.simulation);
+        match self {
+            SweepParameter::NumberOfNodes => topology.number_of_nodes as f64,
+            SweepParameter::NumberOfGateways => topology.number_of_gateways as f64,
+            SweepParameter::NumberOfMinPossibleNeighbors => {
+                topology.number_of_min_possible_neighbors as f64
+            }
+            SweepParameter::NumberOfMaxPossibleNeighbors => {
+                topology.number_of_max_possible_neighbors as f64
+            }
+            SweepParameter::NumberOfGatewayMembers => topology.number_of_gateway_members as f64,
+            SweepParameter::Epsb => costs.epsb as f64,
+            SweepParameter::Eprb => costs.eprb as f64,
+            SweepParameter::SentMessageSize => costs.sent_message_size as f64,
+            SweepParameter::ReceivedMessageSize => costs.received_message_size as f64,
+            SweepParameter::Iterations => simulation.iterations as f64,
+            SweepParameter::MinAffectedNodes => simulation.min_affected_nodes as f64,
+            SweepParameter::MaxAffectedNodes => simulation.max_affected_nodes as f64,
+        }
+    }
+
+    // The value has been checked by Sweep::new, integer parameters only receive whole numbers
+    pub fn set(&self, scenario: &mut Scenario, value: f64) {
+        let (topology, costs, simulation) = (
+            &mut scenario.topology,
+            &mut scenario.costs,
+            &mut scenario.simulation,
+        );
+        match self {
+            SweepParameter::NumberOfNodes => topology.number_of_nodes = value as u32,
+            SweepParameter::NumberOfGateways => topology.number_of_gateways = value as u32,
+            SweepParameter::NumberOfMinPossibleNeighbors => {
+                topology.number_of_min_possible_neighbors = value as u32
+            }
+            SweepParameter::NumberOfMaxPossibleNeighbors => {
+                topology.number_of_max_possible_neighbors = value as u32
+            }
+            SweepParameter::NumberOfGatewayMembers => {
+                topology.number_of_gateway_members = value as u32
+            }
+            SweepParameter::Epsb => costs.epsb = value as f32,
+            SweepParameter::Eprb => costs.eprb = value as f32,
+            SweepParameter::SentMessageSize => costs.sent_message_size = value as u32,
+            SweepParameter::ReceivedMessageSize => costs.received_message_size = value as u32,
+            SweepParameter::Iterations => simulation.iterations = value as usize,
+            SweepParameter::MinAffectedNodes => simulation.min_affected_nodes = value as usize,
+            SweepParameter::MaxAffectedNodes => simulation.max_affected_nodes = value as usize,
+        }
+    }
+}
+
+impl SweepValues {
+    pub fn expand(&self) -> Result<Vec<f64>, String> {
+        let range = match self {
+            SweepValues::List(values) if values.is_empty() => {
+                return Err("the list of values is empty".to_string())
+            }
+            SweepValues::List(values) => return Ok(values.clone()),
+            SweepValues::Range(range) => range,
+        };
+        let invalid = || {
+            format!(
+                "invalid range \"{}\" (expected start..end+step or start..end*factor)",
+                range
+            )
+        };
+        let (start, rest) = range.split_once("..").ok_or_else(invalid)?;
+        let (end, increment, geometric) = match (rest.split_once('+'), rest.split_once('*')) {
+            (Some((end, step)), None) => (end, step, false),
+            (None, Some((end, factor))) => (end, factor, true),
+            _ => return Err(invalid()),
+        };
+        let parse = |value: &str| value.trim().parse::<f64>().map_err(|_| invalid());
+        let (start, end, increment) = (parse(start)?, parse(end)?, parse(increment)?);
+        if !start.is_finite() || !end.is_finite() || start > end {
+            return Err(format!(
+                "range \"{}\" must go from a lower to a higher value",
+                range
+            ));
+        }
+
+        let mut values = vec![];
+        if geometric {
+            if start <= 0.0 || increment <= 1.0 {
+                return Err(format!(
+                    "geometric range \"{}\" needs a positive start and a factor above 1",
+                    range
+                ));
+            }
+            let mut value = start;
+            // Tolerate the rounding of repeated multiplications on the last value
+            while value <= end * (1.0 + 1e-9) {
+                values.push(value);
+                value *= increment;
+            }
+        } else {
+            if increment <= 0.0 {
+                return Err(format!("range \"{}\" needs a positive step", range));
+            }
+            let count = ((end - start) / increment + 1e-9).floor() as usize;
+            // Keep 12 significant digits so 0.00001..0.00003+0.00001 gives 0.00003, not
+            // 0.000030000000000000004
+            values.extend((0..=count).map(|index| {
+                let value = start + index as f64 * increment;
+                format!("{:.11e}", value).parse().unwrap_or(value)
+            }));
+        }
+        Ok(values)
+    }
+}
+
+impl FromStr for SweepValues {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.contains("..") {
+            return Ok(SweepValues::Range(value.to_string()));
+        }
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                item.parse::<f64>()
+                    .map_err(|_| format!("invalid value \"{}\"", item))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(SweepValues::List)
+    }
+}
+
+impl Sweep {
+    pub fn new(config: &SweepConfig) -> Result<Self, SweepError> {
+        let mut dimensions = vec![];
+        for (name, definition) in config.parameters.iter() {
+            let parameter = SweepParameter::from_name(name)
+                .ok_or_else(|| SweepError::UnknownParameter(name.clone()))?;
+            let invalid = |reason: String| SweepError::InvalidValues {
+                parameter: name.clone(),
+                reason,
+            };
+            let values = definition.expand().map_err(invalid)?;
+            let is_range = matches!(definition, SweepValues::Range(_));
+            for value in values.iter() {
+                if !value.is_finite() || *value < 0.0 {
+                    return Err(invalid(format!("{} is not a non-negative number", value)));
+                }
+                // Geometric ranges of integer parameters are rounded, listed values must be exact
+                if parameter.is_integer() && value.fract() != 0.0 && !is_range {
+                    return Err(invalid(format!("{} is not a whole number", value)));
+                }
+            }
+            let mut values = values;
+            if parameter.is_integer() {
+                values.iter_mut().for_each(|value| *value = value.round());
+                values.dedup();
+            }
+            if dimensions
+                .iter()
+                .any(|(other, _): &(SweepParameter, Vec<f64>)| *other == parameter)
+            {
+                return Err(invalid("is listed twice".to_string()));
+            }
+            dimensions.push((parameter, values));
+        }
+        // Loop over the parameters in a fixed order, whatever the order they were written in
+        dimensions.sort_by_key(|(parameter, _)| *parameter);
+
+        if config.mode == SweepMode::LatinHypercube && config.samples.unwrap_or(0) == 0 {
+            return Err(SweepError::MissingSamples);
+        }
+        Ok(Self {
+            dimensions,
+            mode: config.mode,
+            samples: config.samples,
+        })
+    }
+
+    pub fn parameters(&self) -> Vec<SweepParameter> {
+        self.dimensions
+            .iter()
+            .map(|(parameter, _)| *parameter)
+            .collect()
+    }
+
+    // The scenarios to run, derived from `base`. Latin hypercube samples are drawn from their own
+    // stream of the master seed so the same seed always picks the same points.
+    pub fn points(&self, base: &Scenario, seed: u64) -> Vec<SweepPoint> {
+        let indices = match self.mode {
+            SweepMode::Cartesian => self.cartesian_indices(),
+            SweepMode::LatinHypercube => self.latin_hypercube_indices(
+                self.samples.unwrap_or(1),
+                &mut derive_rng(seed, &[SWEEP_STREAM]),
+            ),
+        };
+        indices
+            .into_iter()
+            .map(|point| {
+                let mut scenario = base.clone();
+                let values: Vec<(SweepParameter, f64)> = self
+                    .dimensions
+                    .iter()
+                    .zip(point)
+                    .map(|((parameter, values), index)| (*parameter, values[index]))
+                    .collect();
+                for (parameter, value) in values.iter() {
+                    parameter.set(&mut scenario, *value);
+                }
+                SweepPoint { values, scenario }
+            })
+            .collect()
+    }
+
+    // Value indices of every combination, the last parameter varying fastest
+    fn cartesian_indices(&self) -> Vec<Vec<usize>> {
+        let mut points = vec![vec![]];
+        for (_, values) in self.dimensions.iter() {
+            points = points
+                .into_iter()
+                .flat_map(|point: Vec<usize>| {
+                    (0..values.len()).map(move |index| {
+                        let mut point = point.clone();
+                        point.push(index);
+                        point
+                    })
+                })
+                .collect();
+        }
+        points
+    }
+
+    // Each parameter's values are split into `samples` equal strata, each stratum is used exactly
+    // once, and the strata of the parameters are paired by independent random permutations
+    fn latin_hypercube_indices<R: Rng + ?Sized>(
+        &self,
+        samples: usize,
+        rng: &mut R,
+    ) -> Vec<Vec<usize>> {
+        let mut points = vec![Vec::with_capacity(self.dimensions.len()); samples];
+        for (_, values) in self.dimensions.iter() {
+            let mut strata: Vec<usize> = (0..samples).collect();
+            strata.shuffle(rng);
+            for (point, stratum) in points.iter_mut().zip(strata) {
+                let position = (stratum as f64 + rng.gen::<f64>()) / samples as f64;
+                let index = ((position * values.len() as f64) as usize).min(values.len() - 1);
+                point.push(index);
+            }
+        }
+        points
+    }
+}
+
+impl SweepPoint {
+    pub fn describe(&self) -> String {
+        self.values
+            .iter()
+            .map(|(parameter, value)| format!("{}={}", parameter.name(), value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::UnknownParameter(name) => write!(
+                f,
+                "unknown sweep parameter \"{}\" (expected one of: {})",
+                name,
+                SweepParameter::ALL
+                    .iter()
+                    .map(|parameter| parameter.name())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            SweepError::InvalidValues { parameter, reason } => {
+                write!(f, "invalid sweep values for {}: {}", parameter, reason)
+            }
+            SweepError::MissingSamples => {
+                write!(
+                    f,
+                    "a latin hypercube sweep needs a number of samples above 0"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
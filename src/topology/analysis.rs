@@ -0,0 +1,348 @@
+use std::{collections::VecDeque, fmt};
+
+use serde::Deserialize;
+
+use super::{DegreeDistribution, TopologyError};
+use crate::{NodeType, NodesVec};
+
+// Networks up to this size get their exact diameter, from a search out of every node. Larger ones
+// get a lower bound from a few double sweeps, which is usually the diameter itself.
+const EXACT_DIAMETER_NODES: usize = 4096;
+const DIAMETER_SWEEPS: usize = 4;
+
+const UNREACHED: usize = usize::MAX;
+
+// What a network must satisfy before it is simulated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connectivity {
+    // Any network is simulated
+    #[default]
+    Any,
+    // Every constrained node can reach a gateway
+    Gateways,
+    // All the nodes are in a single component
+    Connected,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TopologyReport {
+    pub nodes: usize,
+    pub links: usize,
+    pub gateways: usize,
+    // Sizes of the connected components, largest first
+    pub components: Vec<usize>,
+    // Links listed by one of their ends only
+    pub asymmetric_links: usize,
+    // Constrained nodes without a gateway in their component
+    pub unreachable: usize,
+    // Constrained nodes by number of hops to their nearest gateway, reachable ones only
+    pub hops: Vec<usize>,
+    pub degrees: DegreeDistribution,
+    // Mean local clustering coefficient of the nodes with at least two neighbors
+    pub clustering: f64,
+    // Longest shortest path within a component
+    pub diameter: usize,
+    // Whether the diameter is exact or a lower bound
+    pub diameter_exact: bool,
+}
+
+impl Connectivity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Connectivity::Any => "any",
+            Connectivity::Gateways => "gateways",
+            Connectivity::Connected => "connected",
+        }
+    }
+}
+
+// Refuse a network that does not satisfy the required connectivity
+pub fn check(nodes: &NodesVec, connectivity: Connectivity) -> Result<(), TopologyError> {
+    if connectivity == Connectivity::Connected {
+        let sizes = component_sizes(&components(nodes));
+        if sizes.len() > 1 {
+            return Err(TopologyError::Disconnected {
+                components: sizes.len(),
+                largest: sizes[0],
+                number_of_nodes: nodes.len(),
+            });
+        }
+    }
+    if connectivity != Connectivity::Any {
+        let hops = gateway_hops(nodes);
+        let unreachable: Vec<usize> = constrained(nodes)
+            .filter(|id| hops[*id] == UNREACHED)
+            .collect();
+        if let Some(first) = unreachable.first() {
+            return Err(TopologyError::Unreachable {
+                nodes: unreachable.len(),
+                first: *first,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl TopologyReport {
+    pub fn of(nodes: &NodesVec) -> Self {
+        let labels = components(nodes);
+        let hops_to_gateway = gateway_hops(nodes);
+        let mut hops = vec![];
+        let mut unreachable = 0;
+        for id in constrained(nodes) {
+            match hops_to_gateway[id] {
+                UNREACHED => unreachable += 1,
+                distance => {
+                    if distance >= hops.len() {
+                        hops.resize(distance + 1, 0);
+                    }
+                    hops[distance] += 1;
+                }
+            }
+        }
+        let (diameter, diameter_exact) = diameter(nodes, &labels);
+        Self {
+            nodes: nodes.len(),
+            links: (0..nodes.len()).map(|id| nodes.degree(id)).sum::<usize>() / 2,
+            gateways: nodes
+                .iter()
+                .filter(|node| node.kind == NodeType::Gateway)
+                .count(),
+            components: component_sizes(&labels),
+            asymmetric_links: asymmetric_links(nodes),
+            unreachable,
+            hops,
+            degrees: DegreeDistribution::of(nodes),
+            clustering: clustering(nodes),
+            diameter,
+            diameter_exact,
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.components.len() <= 1
+    }
+}
+
+fn constrained(nodes: &NodesVec) -> impl Iterator<Item = usize> + '_ {
+    nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained)
+        .map(|node| node.id)
+}
+
+// Hops from the closest of the sources to every node, UNREACHED when there is no path
+fn hops_from(nodes: &NodesVec, sources: &[usize]) -> Vec<usize> {
+    let mut hops = vec![UNREACHED; nodes.len()];
+    let mut queue = VecDeque::new();
+    for source in sources {
+        hops[*source] = 0;
+        queue.push_back(*source);
+    }
+    while let Some(node) = queue.pop_front() {
+        for neighbor in nodes.neighbors(node) {
+            if hops[*neighbor] == UNREACHED {
+                hops[*neighbor] = hops[node] + 1;
+                queue.push_back(*neighbor);
+            }
+        }
+    }
+    hops
+}
+
+fn gateway_hops(nodes: &NodesVec) -> Vec<usize> {
+    let gateways: Vec<usize> = nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Gateway)
+        .map(|node| node.id)
+        .collect();
+    hops_from(nodes, &gateways)
+}
+
+// Component of every node, numbered in the order of their first node
+fn components(nodes: &NodesVec) -> Vec<usize> {
+    let mut labels = vec![UNREACHED; nodes.len()];
+    let mut queue = VecDeque::new();
+    let mut count = 0;
+    for start in 0..nodes.len() {
+        if labels[start] != UNREACHED {
+            continue;
+        }
+        labels[start] = count;
+        queue.push_back(start);
+        while let Some(node) = queue.pop_front() {
+            for neighbor in nodes.neighbors(node) {
+                if labels[*neighbor] == UNREACHED {
+                    labels[*neighbor] = count;
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+        count += 1;
+    }
+    labels
+}
+
+// Size of every component, by component number
+fn label_sizes(labels: &[usize]) -> Vec<usize> {
+    let mut sizes = vec![];
+    for label in labels {
+        if *label >= sizes.len() {
+            sizes.resize(label + 1, 0);
+        }
+        sizes[*label] += 1;
+    }
+    sizes
+}
+
+fn component_sizes(labels: &[usize]) -> Vec<usize> {
+    let mut sizes = label_sizes(labels);
+    sizes.sort_unstable_by(|a, b| b.cmp(a));
+    sizes
+}
+
+// Links (a, b) where b does not list a, found by looking up every reversed link
+fn asymmetric_links(nodes: &NodesVec) -> usize {
+    let mut links: Vec<(usize, usize)> = (0..nodes.len())
+        .flat_map(|a| nodes.neighbors(a).iter().map(move |b| (a, *b)))
+        .collect();
+    links.sort_unstable();
+    links
+        .iter()
+        .filter(|(a, b)| links.binary_search(&(*b, *a)).is_err())
+        .count()
+}
+
+// A node's coefficient is the share of its pairs of neighbors that are linked. Neighbors are
+// marked so each pair is found by walking the lists of the neighbors.
+fn clustering(nodes: &NodesVec) -> f64 {
+    let mut marked = vec![false; nodes.len()];
+    let (mut total, mut counted) = (0.0, 0);
+    for node in 0..nodes.len() {
+        let neighbors = nodes.neighbors(node);
+        if neighbors.len() < 2 {
+            continue;
+        }
+        for neighbor in neighbors {
+            marked[*neighbor] = true;
+        }
+        // Every link between two neighbors is seen from both ends
+        let ends: usize = neighbors
+            .iter()
+            .map(|neighbor| {
+                nodes
+                    .neighbors(*neighbor)
+                    .iter()
+                    .filter(|other| marked[**other])
+                    .count()
+            })
+            .sum();
+        for neighbor in neighbors {
+            marked[*neighbor] = false;
+        }
+        let pairs = neighbors.len() * (neighbors.len() - 1) / 2;
+        total += (ends / 2) as f64 / pairs as f64;
+        counted += 1;
+    }
+    if counted == 0 {
+        0.0
+    } else {
+        total / counted as f64
+    }
+}
+
+// Farthest node from a start and its distance
+fn farthest(nodes: &NodesVec, start: usize) -> (usize, usize) {
+    hops_from(nodes, &[start])
+        .into_iter()
+        .enumerate()
+        .filter(|(_, hops)| *hops != UNREACHED)
+        .max_by_key(|(node, hops)| (*hops, std::cmp::Reverse(*node)))
+        .unwrap_or((start, 0))
+}
+
+fn diameter(nodes: &NodesVec, labels: &[usize]) -> (usize, bool) {
+    if nodes.len() <= EXACT_DIAMETER_NODES {
+        let diameter = (0..nodes.len())
+            .map(|start| farthest(nodes, start).1)
+            .max()
+            .unwrap_or(0);
+        return (diameter, true);
+    }
+    // Double sweeps in the largest component: the node farthest from the previous one is the
+    // start of the next sweep
+    let sizes = label_sizes(labels);
+    let largest = (0..sizes.len())
+        .max_by_key(|label| sizes[*label])
+        .unwrap_or(0);
+    let mut start = labels
+        .iter()
+        .position(|label| *label == largest)
+        .unwrap_or(0);
+    let mut diameter = 0;
+    for _ in 0..DIAMETER_SWEEPS {
+        let (end, hops) = farthest(nodes, start);
+        diameter = diameter.max(hops);
+        start = end;
+    }
+    (diameter, false)
+}
+
+// Histogram as "0:10,1:40,2:50"
+fn counts(histogram: &[usize]) -> String {
+    let counts: Vec<String> = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(value, count)| format!("{}:{}", value, count))
+        .collect();
+    counts.join(",")
+}
+
+// One line per metric, e.g.
+//   nodes=100 links=301 gateways=10
+//   components count=1 sizes=100
+impl fmt::Display for TopologyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "nodes={} links={} gateways={}",
+            self.nodes, self.links, self.gateways
+        )?;
+        let sizes: Vec<String> = self
+            .components
+            .iter()
+            .map(|size| size.to_string())
+            .collect();
+        writeln!(
+            f,
+            "components count={} sizes={}",
+            self.components.len(),
+            sizes.join(",")
+        )?;
+        writeln!(f, "asymmetric_links={}", self.asymmetric_links)?;
+        let reachable: usize = self.hops.iter().sum();
+        let total: usize = self
+            .hops
+            .iter()
+            .enumerate()
+            .map(|(hops, count)| hops * count)
+            .sum();
+        writeln!(
+            f,
+            "gateway_hops max={} mean={} unreachable={} counts={}",
+            self.hops.len().saturating_sub(1),
+            total as f64 / reachable.max(1) as f64,
+            self.unreachable,
+            counts(&self.hops)
+        )?;
+        writeln!(f, "degrees {}", self.degrees)?;
+        writeln!(f, "clustering={}", self.clustering)?;
+        write!(
+            f,
+            "diameter={} exact={}",
+            self.diameter, self.diameter_exact
+        )
+    }
+}
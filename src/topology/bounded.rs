@@ -0,0 +1,214 @@
+use rand::{seq::SliceRandom, Rng};
+
+use super::{Links, TopologyError};
+use crate::{Node, NodeType, NodesVec};
+
+// Shuffles of the remaining stubs before falling back to rewiring existing links
+const MATCHING_ROUNDS: usize = 4;
+// Links tried for each pair of stubs left unmatched
+const REWIRING_ATTEMPTS: usize = 1000;
+
+// Random network where every node has between min_degree and max_degree neighbors, generated with
+// the configuration model: every node gets a target degree drawn from the bounds and as many link
+// ends (stubs), which are paired at random. Pairs that would make a self loop or a duplicate link
+// are rejected and matched again, and the few stubs left at the end are connected by rewiring
+// existing links, so the generation runs in time linear in the number of links.
+pub fn generate<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    number_of_gateways: usize,
+    min_degree: usize,
+    max_degree: usize,
+    rng: &mut R,
+) -> Result<NodesVec, TopologyError> {
+    if min_degree > max_degree || (number_of_nodes > 0 && max_degree >= number_of_nodes) {
+        return Err(TopologyError::DegreeBounds {
+            min: min_degree,
+            max: max_degree,
+            number_of_nodes,
+        });
+    }
+    if number_of_gateways > number_of_nodes {
+        return Err(TopologyError::Gateways {
+            number_of_gateways,
+            number_of_nodes,
+        });
+    }
+
+    // Gateways first, then constrained nodes, in a random order
+    let mut nodes: Vec<Node> = (0..number_of_nodes)
+        .map(|id| {
+            let kind = if id < number_of_gateways {
+                NodeType::Gateway
+            } else {
+                NodeType::Constrained
+            };
+            Node::new(id, kind, rng.gen_range(min_degree..=max_degree))
+        })
+        .collect();
+    nodes.shuffle(rng);
+
+    // Every link has two ends, so the target degrees must add up to an even number
+    let total_degree: usize = nodes.iter().map(|node| node.max_possible_neighbors).sum();
+    if total_degree % 2 == 1 {
+        if let Some(node) = nodes
+            .iter_mut()
+            .find(|node| node.max_possible_neighbors < max_degree)
+        {
+            node.max_possible_neighbors += 1;
+        } else if let Some(node) = nodes
+            .iter_mut()
+            .find(|node| node.max_possible_neighbors > min_degree)
+        {
+            node.max_possible_neighbors -= 1;
+        } else {
+            return Err(TopologyError::OddDegreeSum {
+                degree: min_degree,
+                number_of_nodes,
+            });
+        }
+    }
+
+    let mut stubs: Vec<usize> = vec![];
+    for (id, node) in nodes.iter().enumerate() {
+        stubs.extend(std::iter::repeat_n(id, node.max_possible_neighbors));
+    }
+    let mut links = Links::default();
+    for _ in 0..MATCHING_ROUNDS {
+        if stubs.is_empty() {
+            break;
+        }
+        stubs.shuffle(rng);
+        let mut rejected = vec![];
+        for pair in stubs.chunks_exact(2) {
+            if !links.insert(pair[0], pair[1]) {
+                rejected.extend_from_slice(pair);
+            }
+        }
+        stubs = rejected;
+    }
+
+    // Connect the stubs left by replacing a random link x-y with a-x and b-y, which keeps the
+    // degrees of x and y. Stubs that still cannot be placed leave their node below its target.
+    stubs.shuffle(rng);
+    for pair in stubs.chunks_exact(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if links.insert(a, b) {
+            continue;
+        }
+        for _ in 0..REWIRING_ATTEMPTS {
+            if links.is_empty() {
+                break;
+            }
+            let index = rng.gen_range(0..links.len());
+            let (mut x, mut y) = links.edges[index];
+            if rng.gen() {
+                (x, y) = (y, x);
+            }
+            if [x, y].iter().any(|end| *end == a || *end == b)
+                || links.contains(a, x)
+                || links.contains(b, y)
+            {
+                continue;
+            }
+            links.swap_remove(index);
+            links.insert(a, x);
+            links.insert(b, y);
+            break;
+        }
+    }
+
+    let network = NodesVec::from_edges(nodes, &links.edges);
+    if let Some(node) = network
+        .iter()
+        .find(|node| network.degree(node.id) < min_degree)
+    {
+        return Err(TopologyError::Unsatisfied {
+            node: node.id,
+            degree: network.degree(node.id),
+            min: min_degree,
+        });
+    }
+    Ok(network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::derive_rng;
+
+    // Links are listed at both ends, without self loops or duplicates
+    fn assert_symmetric(network: &NodesVec) {
+        for node in network.iter() {
+            let neighbors = network.neighbors(node.id);
+            for (index, neighbor) in neighbors.iter().enumerate() {
+                assert_ne!(*neighbor, node.id);
+                assert!(!neighbors[..index].contains(neighbor));
+                assert!(network.neighbors(*neighbor).contains(&node.id));
+            }
+        }
+    }
+
+    #[test]
+    fn degrees_stay_within_the_bounds() {
+        for seed in 0..5 {
+            let rng = &mut derive_rng(seed, &[]);
+            let network = generate(200, 10, 2, 5, rng).unwrap();
+            assert_eq!(network.len(), 200);
+            let gateways = network
+                .iter()
+                .filter(|node| node.kind == NodeType::Gateway)
+                .count();
+            assert_eq!(gateways, 10);
+            for node in network.iter() {
+                let degree = network.degree(node.id);
+                assert!(
+                    (2..=5).contains(&degree),
+                    "node {} has {} links",
+                    node.id,
+                    degree
+                );
+                assert!(degree <= node.max_possible_neighbors);
+            }
+            assert_symmetric(&network);
+        }
+    }
+
+    #[test]
+    fn equal_bounds_give_a_regular_network() {
+        let rng = &mut derive_rng(3, &[]);
+        let network = generate(100, 5, 4, 4, rng).unwrap();
+        assert!(network.iter().all(|node| network.degree(node.id) == 4));
+        assert_symmetric(&network);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_network() {
+        let first = generate(50, 3, 1, 4, &mut derive_rng(9, &[])).unwrap();
+        let second = generate(50, 3, 1, 4, &mut derive_rng(9, &[])).unwrap();
+        for node in first.iter() {
+            assert_eq!(first.neighbors(node.id), second.neighbors(node.id));
+            assert_eq!(node.kind, second[node.id].kind);
+        }
+    }
+
+    #[test]
+    fn impossible_bounds_are_rejected() {
+        let rng = &mut derive_rng(0, &[]);
+        assert!(matches!(
+            generate(10, 1, 4, 2, rng),
+            Err(TopologyError::DegreeBounds { .. })
+        ));
+        assert!(matches!(
+            generate(10, 1, 2, 10, rng),
+            Err(TopologyError::DegreeBounds { .. })
+        ));
+        assert!(matches!(
+            generate(10, 11, 2, 4, rng),
+            Err(TopologyError::Gateways { .. })
+        ));
+        assert!(matches!(
+            generate(5, 1, 3, 3, rng),
+            Err(TopologyError::OddDegreeSum { .. })
+        ));
+    }
+}
@@ -0,0 +1,537 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use super::TopologyError;
+use crate::{NodeType, NodesVec};
+
+// How constrained nodes are assigned to the gateways whose members they are
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterStrategy {
+    // Every gateway a node is linked to
+    #[default]
+    Adjacent,
+    // The closest gateways, by distance when nodes have a position and by hops otherwise
+    Nearest,
+    // Gateways taking at most the same number of members, for the smallest total distance
+    // between members and gateways found by moving and exchanging members: the assignment step
+    // of a balanced k-means with the gateways as centers
+    Balanced,
+    // Random gateways that still have room for a member
+    Random,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct ClusterConfig {
+    pub strategy: ClusterStrategy,
+    // Gateways every constrained node joins, 1 by default (not used by the adjacent strategy)
+    pub memberships: Option<usize>,
+    // Members a gateway accepts with the random strategy, number_of_gateway_members by default
+    pub capacity: Option<usize>,
+}
+
+// Gateway membership of the network, in both directions. Members of gateway g are
+// members[member_offsets[g]..member_offsets[g + 1]] and gateways of node i are
+// gateways[gateway_offsets[i]..gateway_offsets[i + 1]].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ClusterMap {
+    member_offsets: Vec<usize>,
+    members: Vec<usize>,
+    gateway_offsets: Vec<usize>,
+    gateways: Vec<usize>,
+}
+
+impl ClusterStrategy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ClusterStrategy::Adjacent => "adjacent",
+            ClusterStrategy::Nearest => "nearest",
+            ClusterStrategy::Balanced => "balanced",
+            ClusterStrategy::Random => "random",
+        }
+    }
+}
+
+impl ClusterConfig {
+    pub fn memberships(&self) -> usize {
+        self.memberships.unwrap_or(1)
+    }
+
+    pub fn validate(
+        &self,
+        number_of_nodes: usize,
+        number_of_gateways: usize,
+        number_of_gateway_members: usize,
+    ) -> Result<(), TopologyError> {
+        let memberships = self.memberships();
+        if memberships == 0 || (number_of_gateways > 0 && memberships > number_of_gateways) {
+            return Err(TopologyError::InvalidParameter {
+                field: "clusters.memberships",
+                reason: format!("must be between 1 and the {} gateways", number_of_gateways),
+            });
+        }
+        if self.strategy == ClusterStrategy::Random && number_of_gateways > 0 {
+            let capacity = self.capacity.unwrap_or(number_of_gateway_members);
+            let demand = (number_of_nodes - number_of_gateways) * memberships;
+            if capacity * number_of_gateways < demand {
+                return Err(TopologyError::InvalidParameter {
+                    field: "clusters.capacity",
+                    reason: format!(
+                        "{} gateways of {} members cannot take {} memberships",
+                        number_of_gateways, capacity, demand
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ClusterMap {
+    // Build the map from (gateway, member) pairs
+    pub fn from_memberships(number_of_nodes: usize, memberships: &[(usize, usize)]) -> Self {
+        let (member_offsets, members) = index(
+            number_of_nodes,
+            memberships
+                .iter()
+                .map(|(gateway, member)| (*gateway, *member)),
+        );
+        let (gateway_offsets, gateways) = index(
+            number_of_nodes,
+            memberships
+                .iter()
+                .map(|(gateway, member)| (*member, *gateway)),
+        );
+        Self {
+            member_offsets,
+            members,
+            gateway_offsets,
+            gateways,
+        }
+    }
+
+    pub fn adjacent(nodes: &NodesVec) -> Self {
+        let mut memberships = vec![];
+        for gateway in nodes.iter().filter(|node| node.kind == NodeType::Gateway) {
+            for member in nodes.neighbors(gateway.id) {
+                if nodes[*member].kind == NodeType::Constrained {
+                    memberships.push((gateway.id, *member));
+                }
+            }
+        }
+        Self::from_memberships(nodes.len(), &memberships)
+    }
+
+    pub fn assign<R: Rng + ?Sized>(
+        nodes: &NodesVec,
+        config: &ClusterConfig,
+        number_of_gateway_members: usize,
+        rng: &mut R,
+    ) -> Self {
+        let memberships = match config.strategy {
+            ClusterStrategy::Adjacent => return Self::adjacent(nodes),
+            ClusterStrategy::Nearest => nearest(nodes, config.memberships()),
+            ClusterStrategy::Balanced => balanced(nodes, config.memberships()),
+            ClusterStrategy::Random => random(
+                nodes,
+                config.memberships(),
+                config.capacity.unwrap_or(number_of_gateway_members),
+                rng,
+            ),
+        };
+        Self::from_memberships(nodes.len(), &memberships)
+    }
+
+    // Constrained nodes of a gateway's cluster
+    pub fn members(&self, gateway: usize) -> &[usize] {
+        &self.members[self.member_offsets[gateway]..self.member_offsets[gateway + 1]]
+    }
+
+    // Gateways a constrained node is a member of
+    pub fn gateways(&self, node: usize) -> &[usize] {
+        &self.gateways[self.gateway_offsets[node]..self.gateway_offsets[node + 1]]
+    }
+
+    // (gateway, member) pairs, by gateway
+    pub fn memberships(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.member_offsets.len().saturating_sub(1)).flat_map(move |gateway| {
+            self.members(gateway)
+                .iter()
+                .map(move |member| (gateway, *member))
+        })
+    }
+}
+
+// Rows of a sparse matrix from its (row, column) entries, keeping their order within a row
+fn index(
+    rows: usize,
+    entries: impl Iterator<Item = (usize, usize)> + Clone,
+) -> (Vec<usize>, Vec<usize>) {
+    let mut offsets = vec![0; rows + 1];
+    for (row, _) in entries.clone() {
+        offsets[row + 1] += 1;
+    }
+    for row in 1..offsets.len() {
+        offsets[row] += offsets[row - 1];
+    }
+    let mut next = offsets.clone();
+    let mut columns = vec![0; offsets[rows]];
+    for (row, column) in entries {
+        columns[next[row]] = column;
+        next[row] += 1;
+    }
+    (offsets, columns)
+}
+
+fn gateway_ids(nodes: &NodesVec) -> Vec<usize> {
+    nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Gateway)
+        .map(|node| node.id)
+        .collect()
+}
+
+// Distance of every node to a gateway: euclidean when all the nodes have a position, number of
+// hops otherwise (infinite when unreachable)
+fn distances(nodes: &NodesVec, gateway: usize) -> Vec<f64> {
+    if let Some(origin) = nodes[gateway].position {
+        if nodes.iter().all(|node| node.position.is_some()) {
+            return nodes
+                .iter()
+                .map(|node| {
+                    let position = node.position.unwrap_or_default();
+                    origin
+                        .iter()
+                        .zip(position.iter())
+                        .map(|(a, b)| (a - b) * (a - b))
+                        .sum::<f64>()
+                        .sqrt()
+                })
+                .collect();
+        }
+    }
+    let mut hops = vec![f64::INFINITY; nodes.len()];
+    hops[gateway] = 0.0;
+    let mut queue = VecDeque::from([gateway]);
+    while let Some(node) = queue.pop_front() {
+        for neighbor in nodes.neighbors(node) {
+            if hops[*neighbor].is_infinite() {
+                hops[*neighbor] = hops[node] + 1.0;
+                queue.push_back(*neighbor);
+            }
+        }
+    }
+    hops
+}
+
+fn nearest(nodes: &NodesVec, memberships: usize) -> Vec<(usize, usize)> {
+    // Closest gateways found so far for every node, sorted by distance then gateway id
+    let mut closest: Vec<Vec<(f64, usize)>> = vec![vec![]; nodes.len()];
+    for gateway in gateway_ids(nodes) {
+        for (node, distance) in distances(nodes, gateway).into_iter().enumerate() {
+            let candidates = &mut closest[node];
+            if nodes[node].kind != NodeType::Constrained || distance.is_infinite() {
+                continue;
+            }
+            let rank = candidates.partition_point(|(other, _)| *other <= distance);
+            if rank < memberships {
+                candidates.insert(rank, (distance, gateway));
+                candidates.truncate(memberships);
+            }
+        }
+    }
+    closest
+        .into_iter()
+        .enumerate()
+        .flat_map(|(node, gateways)| {
+            gateways
+                .into_iter()
+                .map(move |(_, gateway)| (gateway, node))
+        })
+        .collect()
+}
+
+// Gains under this are rounding errors, which could undo each other forever
+const MIN_GAIN: f64 = 1e-9;
+
+fn balanced(nodes: &NodesVec, memberships: usize) -> Vec<(usize, usize)> {
+    let gateways = gateway_ids(nodes);
+    if gateways.is_empty() {
+        return vec![];
+    }
+    let constrained = nodes.len() - gateways.len();
+    let mut clusters = Clusters {
+        distances: gateways
+            .iter()
+            .map(|gateway| distances(nodes, *gateway))
+            .collect(),
+        capacity: (constrained * memberships).div_ceil(gateways.len()),
+        members: vec![vec![]; gateways.len()],
+        is_member: vec![vec![false; nodes.len()]; gateways.len()],
+    };
+
+    // Closest pairs first, each gateway taking members until its share is reached
+    let mut pairs = vec![];
+    for (index, row) in clusters.distances.iter().enumerate() {
+        for (node, distance) in row.iter().enumerate() {
+            if nodes[node].kind == NodeType::Constrained && distance.is_finite() {
+                pairs.push((*distance, node, index));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+    let mut joined = vec![0; nodes.len()];
+    for (_, node, index) in pairs {
+        if joined[node] < memberships && clusters.members[index].len() < clusters.capacity {
+            joined[node] += 1;
+            clusters.join(index, node);
+        }
+    }
+    // A node may find the gateways it is not a member of full while others have room
+    for node in 0..nodes.len() {
+        while nodes[node].kind == NodeType::Constrained
+            && joined[node] < memberships
+            && clusters.make_room(node)
+        {
+            joined[node] += 1;
+        }
+    }
+
+    // The last nodes of the greedy pass take whatever room is left, far from their gateways:
+    // move members between every two gateways while that shortens the total distance
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for from in 0..gateways.len() {
+            for to in 0..gateways.len() {
+                if from != to && clusters.transfer(from, to) {
+                    improved = true;
+                }
+            }
+        }
+    }
+    clusters
+        .members
+        .into_iter()
+        .enumerate()
+        .flat_map(|(index, mut members)| {
+            members.sort_unstable();
+            let gateway = gateways[index];
+            members.into_iter().map(move |node| (gateway, node))
+        })
+        .collect()
+}
+
+// Members of the gateways while they are balanced, by gateway index
+struct Clusters {
+    distances: Vec<Vec<f64>>,
+    capacity: usize,
+    members: Vec<Vec<usize>>,
+    is_member: Vec<Vec<bool>>,
+}
+
+impl Clusters {
+    fn join(&mut self, index: usize, node: usize) {
+        self.members[index].push(node);
+        self.is_member[index][node] = true;
+    }
+
+    fn leave(&mut self, index: usize, node: usize) {
+        self.members[index].retain(|member| *member != node);
+        self.is_member[index][node] = false;
+    }
+
+    // Join the node to a full gateway after moving one of its members to a gateway with room, at
+    // the smallest increase of the total distance. Returns whether the node found a place.
+    fn make_room(&mut self, node: usize) -> bool {
+        let gateways = 0..self.members.len();
+        let mut best: Option<(f64, usize, usize, usize)> = None;
+        for full in gateways.clone().filter(|full| !self.is_member[*full][node]) {
+            for open in gateways
+                .clone()
+                .filter(|open| self.members[*open].len() < self.capacity)
+            {
+                for member in self.members[full].iter() {
+                    if self.is_member[open][*member] {
+                        continue;
+                    }
+                    let increase = self.distances[full][node] + self.distances[open][*member]
+                        - self.distances[full][*member];
+                    if increase.is_finite() && best.is_none_or(|(best, ..)| increase < best) {
+                        best = Some((increase, full, open, *member));
+                    }
+                }
+            }
+        }
+        match best {
+            Some((_, full, open, member)) => {
+                self.leave(full, member);
+                self.join(open, member);
+                self.join(full, node);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Move members of `from` closer to `to` while it has room, then exchange them for members of
+    // `to`, the largest gains first. Returns whether any member changed gateway.
+    fn transfer(&mut self, from: usize, to: usize) -> bool {
+        // Gain in distance of each node able to change gateway, largest first
+        let candidates = |clusters: &Self, from: usize, to: usize| {
+            let mut candidates: Vec<(f64, usize)> = clusters.members[from]
+                .iter()
+                .filter(|node| !clusters.is_member[to][**node])
+                .map(|node| {
+                    let distances = &clusters.distances;
+                    (distances[from][*node] - distances[to][*node], *node)
+                })
+                .collect();
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+            candidates
+        };
+        let leaving = candidates(self, from, to);
+        let mut coming = candidates(self, to, from).into_iter();
+        let mut room = self.capacity.saturating_sub(self.members[to].len());
+        let mut moved = false;
+        for (gain, node) in leaving {
+            if room > 0 {
+                if gain <= MIN_GAIN {
+                    break;
+                }
+                room -= 1;
+            } else {
+                match coming.next() {
+                    Some((other_gain, other)) if gain + other_gain > MIN_GAIN => {
+                        self.is_member[to][other] = false;
+                        self.join(from, other);
+                    }
+                    _ => break,
+                }
+            }
+            self.is_member[from][node] = false;
+            self.join(to, node);
+            moved = true;
+        }
+        // Members that left are only flagged above, so the candidates stay valid
+        if moved {
+            for index in [from, to] {
+                let is_member = &self.is_member[index];
+                self.members[index].retain(|node| is_member[*node]);
+            }
+        }
+        moved
+    }
+}
+
+fn random<R: Rng + ?Sized>(
+    nodes: &NodesVec,
+    memberships: usize,
+    capacity: usize,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    // Gateways with room left and the room they have
+    let mut open: Vec<(usize, usize)> = gateway_ids(nodes)
+        .into_iter()
+        .map(|gateway| (gateway, capacity))
+        .filter(|(_, room)| *room > 0)
+        .collect();
+    let mut assignment = vec![];
+    let mut chosen = vec![];
+    for node in nodes
+        .iter()
+        .filter(|node| node.kind == NodeType::Constrained)
+    {
+        chosen.clear();
+        while chosen.len() < memberships.min(open.len()) {
+            let index = rng.gen_range(0..open.len());
+            if !chosen.contains(&open[index].0) {
+                chosen.push(open[index].0);
+            }
+        }
+        for gateway in chosen.iter() {
+            assignment.push((*gateway, node.id));
+            let index = open
+                .iter()
+                .position(|(open, _)| open == gateway)
+                .unwrap_or(0);
+            open[index].1 -= 1;
+            if open[index].1 == 0 {
+                open.swap_remove(index);
+            }
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scenario::TopologyConfig, topology};
+
+    fn network(memberships: usize) -> NodesVec {
+        let config: TopologyConfig = toml::from_str(&format!(
+            r#"
+            number_of_nodes = 60
+            number_of_gateways = 4
+            number_of_min_possible_neighbors = 0
+            number_of_max_possible_neighbors = 0
+            number_of_gateway_members = 15
+            model = "geometric"
+            geometric = {{ area = [100.0, 100.0], radius = 40.0, layout = {{ kind = "clusters", clusters = 2, spread = 10.0 }} }}
+            clusters = {{ strategy = "balanced", memberships = {} }}
+            "#,
+            memberships
+        ))
+        .unwrap();
+        topology::generate(&config, 3).unwrap()
+    }
+
+    #[test]
+    fn balanced_clusters_share_the_members_and_cannot_get_closer() {
+        for memberships in [1, 2] {
+            let nodes = network(memberships);
+            let gateways = gateway_ids(&nodes);
+            let constrained = nodes.len() - gateways.len();
+            let capacity = (constrained * memberships).div_ceil(gateways.len());
+            let clusters = nodes.clusters();
+            let members: Vec<&[usize]> = gateways
+                .iter()
+                .map(|gateway| clusters.members(*gateway))
+                .collect();
+            assert!(members.iter().all(|members| members.len() <= capacity));
+            for node in nodes
+                .iter()
+                .filter(|node| node.kind == NodeType::Constrained)
+            {
+                assert_eq!(clusters.gateways(node.id).len(), memberships);
+            }
+
+            // No member moves to a gateway with room, and no two members swap, to get closer
+            let distances: Vec<Vec<f64>> = gateways
+                .iter()
+                .map(|gateway| distances(&nodes, *gateway))
+                .collect();
+            for from in 0..gateways.len() {
+                for to in (0..gateways.len()).filter(|to| *to != from) {
+                    let gains = |from: usize, to: usize| -> Vec<f64> {
+                        members[from]
+                            .iter()
+                            .filter(|node| !members[to].contains(node))
+                            .map(|node| distances[from][*node] - distances[to][*node])
+                            .collect()
+                    };
+                    let best = |gains: Vec<f64>| gains.into_iter().fold(f64::MIN, f64::max);
+                    let leaving = best(gains(from, to));
+                    if members[to].len() < capacity {
+                        assert!(leaving <= MIN_GAIN);
+                    }
+                    assert!(leaving + best(gains(to, from)) <= MIN_GAIN);
+                }
+            }
+        }
+    }
+}
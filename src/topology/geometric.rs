@@ -0,0 +1,409 @@
+use std::{collections::HashMap, f64::consts::PI, path::PathBuf};
+
+use rand::Rng;
+use serde::Deserialize;
+
+use super::{random_kinds, TopologyError};
+use crate::{Node, NodesVec};
+
+// Deployment of the geometric model: nodes are placed in an area and two nodes are linked when
+// they are within radio range of each other
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeometricConfig {
+    // Side lengths of the deployment area, two for a plane and three for a volume
+    pub area: Vec<f64>,
+    // Communication range, in the unit of the area
+    pub radius: f64,
+    #[serde(default)]
+    pub layout: Layout,
+    // Without shadowing links follow the radius exactly (unit disk)
+    #[serde(default)]
+    pub shadowing: Option<Shadowing>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Layout {
+    #[default]
+    Uniform,
+    // Regular lattice covering the area, filled in order
+    Grid,
+    // Nodes spread normally around cluster centers drawn uniformly in the area
+    Clusters {
+        clusters: usize,
+        spread: f64,
+    },
+    // Coordinates of every node, one comma separated line per node, relative to the scenario
+    File {
+        positions: PathBuf,
+    },
+}
+
+// Log-normal shadowing: the path loss at distance d exceeds the one at the radius by
+// 10 * path_loss_exponent * log10(d / radius) plus a normal variable of std_dev dB, and a link
+// exists when this difference is negative. The radius is then the range on average.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Shadowing {
+    pub path_loss_exponent: f64,
+    pub std_dev: f64,
+}
+
+impl GeometricConfig {
+    pub fn dimensions(&self) -> usize {
+        self.area.len()
+    }
+
+    pub fn validate(&self) -> Result<(), TopologyError> {
+        let invalid =
+            |field, reason: String| Err(TopologyError::InvalidParameter { field, reason });
+        if self.area.len() != 2 && self.area.len() != 3 {
+            return invalid(
+                "area",
+                format!("expected 2 or 3 side lengths, got {}", self.area.len()),
+            );
+        }
+        if let Some(side) = self
+            .area
+            .iter()
+            .find(|side| !side.is_finite() || **side <= 0.0)
+        {
+            return invalid("area", format!("{} is not a positive length", side));
+        }
+        if !self.radius.is_finite() || self.radius <= 0.0 {
+            return invalid(
+                "radius",
+                format!("{} is not a positive length", self.radius),
+            );
+        }
+        if let Layout::Clusters { clusters, spread } = self.layout {
+            if clusters == 0 {
+                return invalid("layout.clusters", "must be greater than 0".to_string());
+            }
+            if !spread.is_finite() || spread < 0.0 {
+                return invalid(
+                    "layout.spread",
+                    format!("{} is not a non-negative length", spread),
+                );
+            }
+        }
+        if let Some(shadowing) = self.shadowing {
+            if !shadowing.path_loss_exponent.is_finite() || shadowing.path_loss_exponent <= 0.0 {
+                return invalid(
+                    "shadowing.path_loss_exponent",
+                    format!("{} is not a positive number", shadowing.path_loss_exponent),
+                );
+            }
+            if !shadowing.std_dev.is_finite() || shadowing.std_dev < 0.0 {
+                return invalid(
+                    "shadowing.std_dev",
+                    format!("{} is not a non-negative number", shadowing.std_dev),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Distance beyond which no link is made. With shadowing, links further than three standard
+    // deviations of fading are rare enough (0.13%) to be left out.
+    fn cutoff(&self) -> f64 {
+        match self.shadowing {
+            None => self.radius,
+            Some(shadowing) => {
+                self.radius
+                    * 10f64.powf(3.0 * shadowing.std_dev / (10.0 * shadowing.path_loss_exponent))
+            }
+        }
+    }
+
+    fn linked<R: Rng + ?Sized>(&self, distance: f64, rng: &mut R) -> bool {
+        match self.shadowing {
+            None => distance <= self.radius,
+            Some(shadowing) => {
+                let excess = 10.0 * shadowing.path_loss_exponent * (distance / self.radius).log10();
+                excess + shadowing.std_dev * standard_normal(rng) <= 0.0
+            }
+        }
+    }
+}
+
+pub fn generate<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    number_of_gateways: usize,
+    config: &GeometricConfig,
+    rng: &mut R,
+) -> Result<NodesVec, TopologyError> {
+    config.validate()?;
+    if number_of_gateways > number_of_nodes {
+        return Err(TopologyError::Gateways {
+            number_of_gateways,
+            number_of_nodes,
+        });
+    }
+
+    let positions = match &config.layout {
+        Layout::Uniform => (0..number_of_nodes)
+            .map(|_| position(config, |side| rng.gen_range(0.0..side)))
+            .collect(),
+        Layout::Grid => grid(number_of_nodes, &config.area),
+        Layout::Clusters { clusters, spread } => {
+            let centers: Vec<[f64; 3]> = (0..*clusters)
+                .map(|_| position(config, |side| rng.gen_range(0.0..side)))
+                .collect();
+            (0..number_of_nodes)
+                .map(|_| {
+                    let center = centers[rng.gen_range(0..centers.len())];
+                    let mut axis = 0;
+                    position(config, |side| {
+                        let coordinate = center[axis] + spread * standard_normal(rng);
+                        axis += 1;
+                        coordinate.clamp(0.0, side)
+                    })
+                })
+                .collect()
+        }
+        Layout::File { positions } => read_positions(positions, number_of_nodes, config)?,
+    };
+
+    let kinds = random_kinds(number_of_nodes, number_of_gateways, rng);
+
+    let edges = links(&positions, config, rng);
+    let mut degrees = vec![0; number_of_nodes];
+    for (a, b) in edges.iter() {
+        degrees[*a] += 1;
+        degrees[*b] += 1;
+    }
+    let nodes = kinds
+        .into_iter()
+        .zip(positions)
+        .enumerate()
+        .map(|(id, (kind, position))| {
+            let mut node = Node::new(id, kind, degrees[id]);
+            node.position = Some(position);
+            node
+        })
+        .collect();
+    Ok(NodesVec::from_edges(nodes, &edges))
+}
+
+// Position with one coordinate per side of the area, the third one is 0 on a plane
+fn position<F: FnMut(f64) -> f64>(config: &GeometricConfig, mut coordinate: F) -> [f64; 3] {
+    let mut position = [0.0; 3];
+    for (value, side) in position.iter_mut().zip(config.area.iter()) {
+        *value = coordinate(*side);
+    }
+    position
+}
+
+// Smallest lattice with enough points, points at the center of their cells
+fn grid(number_of_nodes: usize, area: &[f64]) -> Vec<[f64; 3]> {
+    let mut per_axis: usize = 1;
+    while per_axis.pow(area.len() as u32) < number_of_nodes {
+        per_axis += 1;
+    }
+    (0..number_of_nodes)
+        .map(|index| {
+            let mut position = [0.0; 3];
+            let mut rest = index;
+            for (value, side) in position.iter_mut().zip(area.iter()) {
+                *value = ((rest % per_axis) as f64 + 0.5) * side / per_axis as f64;
+                rest /= per_axis;
+            }
+            position
+        })
+        .collect()
+}
+
+fn read_positions(
+    path: &PathBuf,
+    number_of_nodes: usize,
+    config: &GeometricConfig,
+) -> Result<Vec<[f64; 3]>, TopologyError> {
+    let error = |reason: String| TopologyError::Positions {
+        path: path.clone(),
+        reason,
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(|err| error(err.to_string()))?;
+    let mut positions = vec![];
+    for record in reader.deserialize::<Vec<f64>>() {
+        let coordinates = record.map_err(|err| error(err.to_string()))?;
+        if coordinates.len() != config.dimensions() {
+            return Err(error(format!(
+                "line {} has {} coordinates instead of {}",
+                positions.len() + 1,
+                coordinates.len(),
+                config.dimensions()
+            )));
+        }
+        let mut position = [0.0; 3];
+        position[..coordinates.len()].copy_from_slice(&coordinates);
+        positions.push(position);
+    }
+    if positions.len() != number_of_nodes {
+        return Err(error(format!(
+            "{} positions for {} nodes",
+            positions.len(),
+            number_of_nodes
+        )));
+    }
+    Ok(positions)
+}
+
+// Links between nodes in range. Nodes are hashed into cubic cells as wide as the cutoff distance,
+// so only the nodes of the neighboring cells have to be checked.
+fn links<R: Rng + ?Sized>(
+    positions: &[[f64; 3]],
+    config: &GeometricConfig,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    let cutoff = config.cutoff();
+    let cell_of =
+        |position: &[f64; 3]| position.map(|coordinate| (coordinate / cutoff).floor() as i64);
+    let mut cells: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for (id, position) in positions.iter().enumerate() {
+        cells.entry(cell_of(position)).or_default().push(id);
+    }
+    let depth = if config.dimensions() == 3 { 1 } else { 0 };
+
+    let mut edges = vec![];
+    let mut candidates = vec![];
+    for (a, position) in positions.iter().enumerate() {
+        let cell = cell_of(position);
+        candidates.clear();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -depth..=depth {
+                    if let Some(ids) = cells.get(&[cell[0] + dx, cell[1] + dy, cell[2] + dz]) {
+                        candidates.extend(ids.iter().copied().filter(|b| *b > a));
+                    }
+                }
+            }
+        }
+        // Visit candidates in id order so the draws do not depend on the hashing
+        candidates.sort_unstable();
+        for b in candidates.iter().copied() {
+            let distance = positions[a]
+                .iter()
+                .zip(positions[b].iter())
+                .map(|(x, y)| (x - y) * (x - y))
+                .sum::<f64>()
+                .sqrt();
+            if distance <= cutoff && config.linked(distance, rng) {
+                edges.push((a, b));
+            }
+        }
+    }
+    edges
+}
+
+// Box-Muller transform, 1 - u keeps the logarithm finite
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u: f64 = rng.gen();
+    let v: f64 = rng.gen();
+    (-2.0 * (1.0 - u).ln()).sqrt() * (2.0 * PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rng::derive_rng, NodeType};
+
+    fn config(layout: Layout, shadowing: Option<Shadowing>) -> GeometricConfig {
+        GeometricConfig {
+            area: vec![100.0, 100.0],
+            radius: 20.0,
+            layout,
+            shadowing,
+        }
+    }
+
+    #[test]
+    fn unit_disk_links_every_pair_in_range() {
+        for seed in 0..3 {
+            let rng = &mut derive_rng(seed, &[]);
+            let network = generate(150, 8, &config(Layout::Uniform, None), rng).unwrap();
+            assert_eq!(network.len(), 150);
+            let gateways = network
+                .iter()
+                .filter(|node| node.kind == NodeType::Gateway)
+                .count();
+            assert_eq!(gateways, 8);
+            for a in network.iter() {
+                let position = a.position.unwrap();
+                assert!(position[..2].iter().all(|x| (0.0..100.0).contains(x)));
+                assert_eq!(position[2], 0.0);
+                assert_eq!(network.degree(a.id), a.max_possible_neighbors);
+                for b in network.iter().filter(|b| b.id != a.id) {
+                    let linked = network.neighbors(a.id).contains(&b.id);
+                    assert_eq!(linked, network.neighbors(b.id).contains(&a.id));
+                    assert_eq!(linked, a.distance(b).unwrap() <= 20.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_links_the_nearest_points() {
+        // 5 x 5 points 20 apart: the radius only reaches the points next to each other
+        let rng = &mut derive_rng(0, &[]);
+        let network = generate(25, 2, &config(Layout::Grid, None), rng).unwrap();
+        let degrees: Vec<usize> = network.iter().map(|node| network.degree(node.id)).collect();
+        let corners = degrees.iter().filter(|degree| **degree == 2).count();
+        let sides = degrees.iter().filter(|degree| **degree == 3).count();
+        let inside = degrees.iter().filter(|degree| **degree == 4).count();
+        assert_eq!((corners, sides, inside), (4, 12, 9));
+        assert_eq!(network[0].position, Some([10.0, 10.0, 0.0]));
+    }
+
+    #[test]
+    fn shadowing_keeps_links_within_the_cutoff() {
+        let shadowing = Shadowing {
+            path_loss_exponent: 3.0,
+            std_dev: 4.0,
+        };
+        let config = config(Layout::Uniform, Some(shadowing));
+        let cutoff = config.cutoff();
+        let rng = &mut derive_rng(4, &[]);
+        let network = generate(150, 8, &config, rng).unwrap();
+        let mut beyond_radius = 0;
+        for a in network.iter() {
+            for b in network.neighbors(a.id).iter().map(|id| &network[*id]) {
+                assert!(network.neighbors(b.id).contains(&a.id));
+                let distance = a.distance(b).unwrap();
+                assert!(distance <= cutoff);
+                if distance > 20.0 {
+                    beyond_radius += 1;
+                }
+            }
+        }
+        // Fading lets some links reach further than the radius
+        assert!(beyond_radius > 0);
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_deployment() {
+        let layout = Layout::Clusters {
+            clusters: 3,
+            spread: 10.0,
+        };
+        let first = generate(
+            60,
+            4,
+            &config(layout.clone(), None),
+            &mut derive_rng(7, &[]),
+        )
+        .unwrap();
+        let second = generate(60, 4, &config(layout, None), &mut derive_rng(7, &[])).unwrap();
+        for node in first.iter() {
+            assert_eq!(node.position, second[node.id].position);
+            assert_eq!(node.kind, second[node.id].kind);
+            assert_eq!(first.neighbors(node.id), second.neighbors(node.id));
+        }
+    }
+}
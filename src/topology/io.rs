@@ -0,0 +1,1199 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{clusters::ClusterMap, random_kinds, Links, TopologyError};
+use crate::{Node, NodeType, NodesVec};
+
+// File formats a network is exported to and imported from. All of them but the adjacency list
+// carry the kind, coordinates (x, y, z) and cluster of every node, the cluster being the comma
+// separated gateways the node is a member of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkFormat {
+    // One line per node: id, kind and the comma separated ids of its neighbors
+    #[default]
+    Adjacency,
+    // One line per link with its two ends, the nodes are described by "# node" comment lines
+    EdgeList,
+    // Graphviz, with a pos attribute so that neato draws the nodes where they are
+    Dot,
+    #[serde(rename = "graphml")]
+    GraphMl,
+    // Node-link document, as read and written by networkx and d3
+    Json,
+}
+
+// Network read from a file instead of being generated, e.g. one exported by the topology command
+// or the nodes and links of a testbed. Nodes keep the order of the file; those without a kind get
+// one at random and those without a cluster are assigned with the clusters strategy.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileConfig {
+    // Relative to the scenario
+    pub path: PathBuf,
+    // Guessed from the extension by default
+    #[serde(default)]
+    pub format: Option<NetworkFormat>,
+}
+
+impl NetworkFormat {
+    pub const ALL: [NetworkFormat; 5] = [
+        NetworkFormat::Adjacency,
+        NetworkFormat::EdgeList,
+        NetworkFormat::Dot,
+        NetworkFormat::GraphMl,
+        NetworkFormat::Json,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NetworkFormat::Adjacency => "adjacency",
+            NetworkFormat::EdgeList => "edge_list",
+            NetworkFormat::Dot => "dot",
+            NetworkFormat::GraphMl => "graphml",
+            NetworkFormat::Json => "json",
+        }
+    }
+
+    // Guess the format from the file extension, defaulting to the adjacency list
+    pub fn from_path(path: &Path) -> Self {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("edges" | "edgelist") => NetworkFormat::EdgeList,
+            Some("dot" | "gv") => NetworkFormat::Dot,
+            Some("graphml" | "xml") => NetworkFormat::GraphMl,
+            Some("json") => NetworkFormat::Json,
+            _ => NetworkFormat::Adjacency,
+        }
+    }
+}
+
+impl FromStr for NetworkFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.to_ascii_lowercase();
+        NetworkFormat::ALL
+            .into_iter()
+            .find(|format| format.label() == value)
+            .ok_or_else(|| {
+                let labels: Vec<&str> = NetworkFormat::ALL
+                    .iter()
+                    .map(|format| format.label())
+                    .collect();
+                format!(
+                    "unknown network format \"{}\" (expected one of: {})",
+                    value,
+                    labels.join(", ")
+                )
+            })
+    }
+}
+
+impl FileConfig {
+    pub fn format(&self) -> NetworkFormat {
+        self.format
+            .unwrap_or_else(|| NetworkFormat::from_path(&self.path))
+    }
+}
+
+// Write the network in the given format, with the description as a comment (or as a graph
+// attribute in JSON)
+pub fn write_network(nodes: &NodesVec, format: NetworkFormat, description: &str) -> String {
+    match format {
+        NetworkFormat::Adjacency => write_adjacency(nodes, description),
+        NetworkFormat::EdgeList => write_edge_list(nodes, description),
+        NetworkFormat::Dot => write_dot(nodes, description),
+        NetworkFormat::GraphMl => write_graphml(nodes, description),
+        NetworkFormat::Json => write_json(nodes, description),
+    }
+}
+
+// Read the network of the file. The clusters are only returned when the file gives some.
+pub fn import<R: Rng + ?Sized>(
+    config: &FileConfig,
+    number_of_nodes: usize,
+    number_of_gateways: usize,
+    rng: &mut R,
+) -> Result<(NodesVec, Option<ClusterMap>), TopologyError> {
+    let error = |reason: String| TopologyError::Import {
+        path: config.path.clone(),
+        reason,
+    };
+    let contents = fs::read_to_string(&config.path).map_err(|err| error(err.to_string()))?;
+    let mut graph = Graph::default();
+    let read = match config.format() {
+        NetworkFormat::Adjacency => read_adjacency(&contents, &mut graph),
+        NetworkFormat::EdgeList => read_edge_list(&contents, &mut graph),
+        NetworkFormat::Dot => read_dot(&contents, &mut graph),
+        NetworkFormat::GraphMl => read_graphml(&contents, &mut graph),
+        NetworkFormat::Json => read_json(&contents, &mut graph),
+    };
+    read.and_then(|_| graph.into_network(number_of_nodes, number_of_gateways, rng))
+        .map_err(error)
+}
+
+// Undirected links, each written once from its lower end
+fn edges(nodes: &NodesVec) -> impl Iterator<Item = (usize, usize)> + '_ {
+    nodes.iter().flat_map(move |node| {
+        nodes
+            .neighbors(node.id)
+            .iter()
+            .filter(move |neighbor| **neighbor > node.id)
+            .map(move |neighbor| (node.id, *neighbor))
+    })
+}
+
+// Attributes of a node as (name, value) pairs, coordinates and cluster only when it has them
+fn attributes(nodes: &NodesVec, node: &Node) -> Vec<(&'static str, String)> {
+    let mut attributes = vec![("kind", node.kind.label().to_string())];
+    if let Some(position) = node.position {
+        for (name, coordinate) in ["x", "y", "z"].into_iter().zip(position) {
+            attributes.push((name, coordinate.to_string()));
+        }
+    }
+    let gateways = nodes.clusters().gateways(node.id);
+    if !gateways.is_empty() {
+        let gateways: Vec<String> = gateways.iter().map(|id| id.to_string()).collect();
+        attributes.push(("cluster", gateways.join(",")));
+    }
+    attributes
+}
+
+fn comment(prefix: &str, description: &str, suffix: &str) -> String {
+    description
+        .lines()
+        .map(|line| format!("{}{}{}\n", prefix, line, suffix))
+        .collect()
+}
+
+fn write_adjacency(nodes: &NodesVec, description: &str) -> String {
+    let mut contents = comment("# ", description, "");
+    for node in nodes.iter() {
+        let neighbors: Vec<String> = nodes
+            .neighbors(node.id)
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        contents.push_str(&format!(
+            "{} {} {}\n",
+            node.id,
+            node.kind.label(),
+            neighbors.join(",")
+        ));
+    }
+    contents
+}
+
+fn write_edge_list(nodes: &NodesVec, description: &str) -> String {
+    let mut contents = comment("# ", description, "");
+    for node in nodes.iter() {
+        let attributes: Vec<String> = attributes(nodes, node)
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        contents.push_str(&format!("# node {} {}\n", node.id, attributes.join(" ")));
+    }
+    for (a, b) in edges(nodes) {
+        contents.push_str(&format!("{} {}\n", a, b));
+    }
+    contents
+}
+
+fn write_dot(nodes: &NodesVec, description: &str) -> String {
+    let mut contents = comment("// ", description, "");
+    contents.push_str("graph network {\n");
+    for node in nodes.iter() {
+        let mut attributes: Vec<String> = attributes(nodes, node)
+            .into_iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, value))
+            .collect();
+        if let Some([x, y, _]) = node.position {
+            attributes.push(format!("pos=\"{},{}!\"", x, y));
+        }
+        contents.push_str(&format!("  {} [{}];\n", node.id, attributes.join(", ")));
+    }
+    for (a, b) in edges(nodes) {
+        contents.push_str(&format!("  {} -- {};\n", a, b));
+    }
+    contents.push_str("}\n");
+    contents
+}
+
+fn write_graphml(nodes: &NodesVec, description: &str) -> String {
+    let mut contents = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    contents.push_str(&comment("<!-- ", &escape(description), " -->"));
+    contents.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (name, kind) in [
+        ("kind", "string"),
+        ("x", "double"),
+        ("y", "double"),
+        ("z", "double"),
+        ("cluster", "string"),
+    ] {
+        contents.push_str(&format!(
+            "  <key id=\"{}\" for=\"node\" attr.name=\"{}\" attr.type=\"{}\"/>\n",
+            name, name, kind
+        ));
+    }
+    contents.push_str("  <graph id=\"network\" edgedefault=\"undirected\">\n");
+    for node in nodes.iter() {
+        contents.push_str(&format!("    <node id=\"{}\">", node.id));
+        for (name, value) in attributes(nodes, node) {
+            contents.push_str(&format!("<data key=\"{}\">{}</data>", name, value));
+        }
+        contents.push_str("</node>\n");
+    }
+    for (a, b) in edges(nodes) {
+        contents.push_str(&format!("    <edge source=\"{}\" target=\"{}\"/>\n", a, b));
+    }
+    contents.push_str("  </graph>\n</graphml>\n");
+    contents
+}
+
+#[derive(Serialize)]
+struct JsonNetwork<'a> {
+    directed: bool,
+    multigraph: bool,
+    graph: JsonGraph<'a>,
+    nodes: Vec<JsonNode>,
+    links: Vec<JsonLink>,
+}
+
+#[derive(Serialize)]
+struct JsonGraph<'a> {
+    description: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonNode {
+    id: usize,
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    z: Option<f64>,
+    #[serde(skip_serializing_if = "<[usize]>::is_empty")]
+    cluster: Vec<usize>,
+}
+
+#[derive(Serialize)]
+struct JsonLink {
+    source: usize,
+    target: usize,
+}
+
+fn write_json(nodes: &NodesVec, description: &str) -> String {
+    let network = JsonNetwork {
+        directed: false,
+        multigraph: false,
+        graph: JsonGraph { description },
+        nodes: nodes
+            .iter()
+            .map(|node| JsonNode {
+                id: node.id,
+                kind: node.kind.label(),
+                x: node.position.map(|position| position[0]),
+                y: node.position.map(|position| position[1]),
+                z: node.position.map(|position| position[2]),
+                cluster: nodes.clusters().gateways(node.id).to_vec(),
+            })
+            .collect(),
+        links: edges(nodes)
+            .map(|(source, target)| JsonLink { source, target })
+            .collect(),
+    };
+    let mut contents = serde_json::to_string_pretty(&network).expect("a network is valid JSON");
+    contents.push('\n');
+    contents
+}
+
+// Network being read: nodes by name in the order they are declared, followed by the nodes only
+// found in links
+#[derive(Default)]
+struct Graph {
+    ids: HashMap<String, usize>,
+    nodes: Vec<NodeEntry>,
+    links: Vec<(String, String)>,
+}
+
+#[derive(Default)]
+struct NodeEntry {
+    name: String,
+    kind: Option<NodeType>,
+    coordinates: [Option<f64>; 3],
+    // Names of the gateways, resolved once every node is known
+    cluster: Option<Vec<String>>,
+}
+
+impl Graph {
+    fn node(&mut self, name: &str) -> usize {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let id = self.nodes.len();
+        self.ids.insert(name.to_string(), id);
+        self.nodes.push(NodeEntry {
+            name: name.to_string(),
+            ..NodeEntry::default()
+        });
+        id
+    }
+
+    fn link(&mut self, a: &str, b: &str) {
+        self.links.push((a.to_string(), b.to_string()));
+    }
+
+    // Attributes unknown to the simulation, as testbed dumps have many, are left out, and so are
+    // empty values
+    fn set(&mut self, id: usize, attribute: &str, value: &str) -> Result<(), String> {
+        let node = &mut self.nodes[id];
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(());
+        }
+        let coordinate = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|coordinate| coordinate.is_finite())
+                .ok_or_else(|| {
+                    format!("node {} has an invalid coordinate \"{}\"", node.name, value)
+                })
+        };
+        match attribute {
+            "kind" => {
+                node.kind = Some(match value.to_ascii_lowercase().as_str() {
+                    "gateway" => NodeType::Gateway,
+                    "constrained" => NodeType::Constrained,
+                    _ => {
+                        return Err(format!(
+                            "node {} has an unknown kind \"{}\" (expected gateway or constrained)",
+                            node.name, value
+                        ))
+                    }
+                })
+            }
+            "x" => node.coordinates[0] = Some(coordinate(value)?),
+            "y" => node.coordinates[1] = Some(coordinate(value)?),
+            "z" => node.coordinates[2] = Some(coordinate(value)?),
+            // Graphviz position "x,y" or "x,y,z", pinned with a trailing "!", unless the
+            // coordinates are given on their own
+            "pos" => {
+                let values: Vec<&str> = value.trim_end_matches('!').split(',').collect();
+                let mut parsed = [None; 3];
+                for (slot, value) in parsed.iter_mut().zip(values.iter()) {
+                    *slot = Some(coordinate(value.trim())?);
+                }
+                for (slot, value) in node.coordinates.iter_mut().zip(parsed) {
+                    if slot.is_none() {
+                        *slot = value;
+                    }
+                }
+            }
+            "cluster" => {
+                node.cluster = Some(
+                    value
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|gateway| !gateway.is_empty())
+                        .map(str::to_string)
+                        .collect(),
+                )
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn into_network<R: Rng + ?Sized>(
+        mut self,
+        number_of_nodes: usize,
+        number_of_gateways: usize,
+        rng: &mut R,
+    ) -> Result<(NodesVec, Option<ClusterMap>), String> {
+        // Both directions of a directed dump make a single link, self loops none
+        let mut links = Links::default();
+        for (a, b) in std::mem::take(&mut self.links) {
+            let (a, b) = (self.node(&a), self.node(&b));
+            links.insert(a, b);
+        }
+        if self.nodes.len() != number_of_nodes {
+            return Err(format!(
+                "the file has {} nodes but number_of_nodes is {}",
+                self.nodes.len(),
+                number_of_nodes
+            ));
+        }
+
+        // Kinds are either given for every node or drawn like the generated models do
+        let kinds = match self.nodes.iter().position(|node| node.kind.is_none()) {
+            Some(0) if self.nodes.iter().all(|node| node.kind.is_none()) => {
+                random_kinds(number_of_nodes, number_of_gateways, rng)
+            }
+            Some(index) => {
+                return Err(format!(
+                    "node {} has no kind while other nodes have one",
+                    self.nodes[index].name
+                ))
+            }
+            None => self.nodes.iter().filter_map(|node| node.kind).collect(),
+        };
+        let gateways = kinds
+            .iter()
+            .filter(|kind| **kind == NodeType::Gateway)
+            .count();
+        if gateways != number_of_gateways {
+            return Err(format!(
+                "the file has {} gateways but number_of_gateways is {}",
+                gateways, number_of_gateways
+            ));
+        }
+
+        let mut degrees = vec![0; number_of_nodes];
+        for (a, b) in links.edges.iter() {
+            degrees[*a] += 1;
+            degrees[*b] += 1;
+        }
+        let mut nodes = Vec::with_capacity(number_of_nodes);
+        for (id, entry) in self.nodes.iter().enumerate() {
+            let mut node = Node::new(id, kinds[id], degrees[id]);
+            node.position = match entry.coordinates {
+                [Some(x), Some(y), z] => Some([x, y, z.unwrap_or(0.0)]),
+                [None, None, None] => None,
+                _ => {
+                    return Err(format!(
+                        "node {} has a coordinate without the others",
+                        entry.name
+                    ))
+                }
+            };
+            nodes.push(node);
+        }
+        let network = NodesVec::from_edges(nodes, &links.edges);
+
+        if self.nodes.iter().all(|node| node.cluster.is_none()) {
+            return Ok((network, None));
+        }
+        let mut memberships = vec![];
+        for (member, entry) in self.nodes.iter().enumerate() {
+            for name in entry.cluster.iter().flatten() {
+                let gateway = *self
+                    .ids
+                    .get(name)
+                    .ok_or_else(|| format!("node {} joins unknown node {}", entry.name, name))?;
+                if kinds[gateway] != NodeType::Gateway || kinds[member] != NodeType::Constrained {
+                    return Err(format!(
+                        "node {} cannot be a member of node {}, clusters link constrained nodes \
+                         to gateways",
+                        entry.name, name
+                    ));
+                }
+                memberships.push((gateway, member));
+            }
+        }
+        Ok((
+            network,
+            Some(ClusterMap::from_memberships(number_of_nodes, &memberships)),
+        ))
+    }
+}
+
+fn read_adjacency(contents: &str, graph: &mut Graph) -> Result<(), String> {
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (name, kind) = match (fields.next(), fields.next()) {
+            (Some(name), Some(kind)) => (name, kind),
+            _ => return Err(format!("line {} has no kind", index + 1)),
+        };
+        let id = graph.node(name);
+        graph.set(id, "kind", kind)?;
+        for neighbor in fields.flat_map(|field| field.split(',')) {
+            if !neighbor.is_empty() {
+                graph.link(name, neighbor);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Lines of two node names separated by spaces, tabs or commas, other columns such as a link
+// quality are left out. A line with a single name declares a node without links.
+fn read_edge_list(contents: &str, graph: &mut Graph) -> Result<(), String> {
+    let mut header = true;
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if let Some(comment) = line.strip_prefix('#') {
+            if let Some(node) = comment.trim_start().strip_prefix("node ") {
+                let mut fields = node.split_whitespace();
+                let id = match fields.next() {
+                    Some(name) => graph.node(name),
+                    None => continue,
+                };
+                for field in fields {
+                    let (attribute, value) = field.split_once('=').ok_or_else(|| {
+                        format!("line {}: expected name=value, got \"{}\"", index + 1, field)
+                    })?;
+                    graph.set(id, attribute, value)?;
+                }
+            }
+            continue;
+        }
+        let fields: Vec<&str> = line
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+        // Column names of a CSV dump
+        if std::mem::take(&mut header) && fields.len() >= 2 {
+            let columns = (
+                fields[0].to_ascii_lowercase(),
+                fields[1].to_ascii_lowercase(),
+            );
+            if matches!(
+                (columns.0.as_str(), columns.1.as_str()),
+                ("source", "target") | ("from", "to")
+            ) {
+                continue;
+            }
+        }
+        match fields[..] {
+            [name] => {
+                graph.node(name);
+            }
+            [a, b, ..] => graph.link(a, b),
+            [] => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Id(String),
+    // -- or ->
+    Edge,
+    Symbol(char),
+}
+
+// The statements of a DOT graph that describe nodes and links: node statements with their
+// attributes and chains of links. Subgraphs are flattened, default attributes are left out.
+fn read_dot(contents: &str, graph: &mut Graph) -> Result<(), String> {
+    let tokens = dot_tokens(contents)?;
+    let mut tokens = tokens.into_iter().peekable();
+    let keyword = |token: &Option<Token>, words: &[&str]| match token {
+        Some(Token::Id(id)) => words.iter().any(|word| id.eq_ignore_ascii_case(word)),
+        _ => false,
+    };
+
+    let mut token = tokens.next();
+    if keyword(&token, &["strict"]) {
+        token = tokens.next();
+    }
+    if !keyword(&token, &["graph", "digraph"]) {
+        return Err("expected a graph or digraph".to_string());
+    }
+    if let Some(Token::Id(_)) = tokens.peek() {
+        tokens.next();
+    }
+    if tokens.next() != Some(Token::Symbol('{')) {
+        return Err("expected { after the graph name".to_string());
+    }
+
+    let mut depth = 1;
+    while depth > 0 {
+        let name = match tokens.next() {
+            None => return Err("missing closing }".to_string()),
+            Some(Token::Symbol('{')) => {
+                depth += 1;
+                continue;
+            }
+            Some(Token::Symbol('}')) => {
+                depth -= 1;
+                continue;
+            }
+            Some(Token::Id(name)) => name,
+            Some(_) => continue,
+        };
+        if name.eq_ignore_ascii_case("subgraph") {
+            if let Some(Token::Id(_)) = tokens.peek() {
+                tokens.next();
+            }
+            continue;
+        }
+        if tokens.peek() == Some(&Token::Symbol('=')) {
+            tokens.next();
+            tokens.next();
+            continue;
+        }
+        skip_port(&mut tokens);
+        let default_attributes = ["graph", "node", "edge"]
+            .iter()
+            .any(|word| name.eq_ignore_ascii_case(word));
+        if default_attributes && tokens.peek() == Some(&Token::Symbol('[')) {
+            dot_attributes(&mut tokens)?;
+            continue;
+        }
+
+        if tokens.peek() != Some(&Token::Edge) {
+            let id = graph.node(&name);
+            for (attribute, value) in dot_attributes(&mut tokens)? {
+                graph.set(id, &attribute, &value)?;
+            }
+            continue;
+        }
+        let mut previous = name;
+        while tokens.peek() == Some(&Token::Edge) {
+            tokens.next();
+            let next = match tokens.next() {
+                Some(Token::Id(next)) => next,
+                _ => return Err(format!("link from {} to a subgraph or nothing", previous)),
+            };
+            skip_port(&mut tokens);
+            graph.link(&previous, &next);
+            previous = next;
+        }
+        dot_attributes(&mut tokens)?;
+    }
+    Ok(())
+}
+
+fn skip_port<I: Iterator<Item = Token>>(tokens: &mut std::iter::Peekable<I>) {
+    while tokens.peek() == Some(&Token::Symbol(':')) {
+        tokens.next();
+        tokens.next();
+    }
+}
+
+// Attribute lists following a statement, "[a=1, b=2][c=3]"
+fn dot_attributes<I: Iterator<Item = Token>>(
+    tokens: &mut std::iter::Peekable<I>,
+) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = vec![];
+    while tokens.peek() == Some(&Token::Symbol('[')) {
+        tokens.next();
+        loop {
+            match tokens.next() {
+                Some(Token::Symbol(']')) => break,
+                Some(Token::Id(attribute)) => {
+                    if tokens.peek() == Some(&Token::Symbol('=')) {
+                        tokens.next();
+                        match tokens.next() {
+                            Some(Token::Id(value)) => attributes.push((attribute, value)),
+                            _ => return Err(format!("attribute {} has no value", attribute)),
+                        }
+                    }
+                }
+                Some(_) => {}
+                None => return Err("missing closing ]".to_string()),
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+fn dot_tokens(contents: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = contents.chars().peekable();
+    let is_id = |c: char| c.is_alphanumeric() || c == '_' || c == '.' || !c.is_ascii();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '#' => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            '"' => {
+                let mut id = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('"') => id.push('"'),
+                            Some('\n') => {}
+                            Some(c) => {
+                                id.push('\\');
+                                id.push(c);
+                            }
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => id.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Id(id));
+            }
+            '<' => {
+                let (mut id, mut depth) = (String::new(), 1);
+                for c in chars.by_ref() {
+                    depth += match c {
+                        '<' => 1,
+                        '>' => -1,
+                        _ => 0,
+                    };
+                    if depth == 0 {
+                        break;
+                    }
+                    id.push(c);
+                }
+                tokens.push(Token::Id(id));
+            }
+            '-' if matches!(chars.peek(), Some('-' | '>')) => {
+                chars.next();
+                tokens.push(Token::Edge);
+            }
+            '{' | '}' | '[' | ']' | ';' | ',' | '=' | ':' => tokens.push(Token::Symbol(c)),
+            c if is_id(c) || c == '-' => {
+                let mut id = c.to_string();
+                while let Some(next) = chars.next_if(|next| is_id(*next)) {
+                    id.push(next);
+                }
+                tokens.push(Token::Id(id));
+            }
+            c => return Err(format!("unexpected character '{}'", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+// The keys, nodes with their data and edges of a GraphML document. Nested graphs are flattened
+// and the data of edges and graphs is left out.
+fn read_graphml(contents: &str, graph: &mut Graph) -> Result<(), String> {
+    // Attribute name of every node key
+    let mut keys: HashMap<String, String> = HashMap::new();
+    let mut node = None;
+    let mut data = None;
+    let mut text = String::new();
+    let mut rest = contents;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&unescape(&rest[..start]));
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("<![CDATA[") {
+            let end = after.find("]]>").ok_or("unterminated CDATA section")?;
+            text.push_str(&after[..end]);
+            rest = &after[end + 3..];
+            continue;
+        }
+        let closing = if rest.starts_with("<!--") { "-->" } else { ">" };
+        let end = rest.find(closing).ok_or("unterminated tag")?;
+        let tag = &rest[1..end];
+        rest = &rest[end + closing.len()..];
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            match local_name(name.trim()) {
+                "node" => node = None,
+                "data" => {
+                    if let (Some(key), Some(id)) = (data.take(), node) {
+                        let attribute = keys.get(&key).unwrap_or(&key).clone();
+                        graph.set(id, &attribute, &text)?;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        let open = !tag.ends_with('/');
+        let mut fields = tag.trim_end_matches('/').splitn(2, char::is_whitespace);
+        let name = local_name(fields.next().unwrap_or(""));
+        let attributes = xml_attributes(fields.next().unwrap_or(""))?;
+        let attribute = |name: &str| {
+            attributes
+                .iter()
+                .find(|(attribute, _)| attribute == name)
+                .map(|(_, value)| value.as_str())
+        };
+        match name {
+            "key" if matches!(attribute("for"), None | Some("node" | "all")) => {
+                if let Some(id) = attribute("id") {
+                    let name = attribute("attr.name").unwrap_or(id);
+                    keys.insert(id.to_string(), name.to_string());
+                }
+            }
+            "node" => {
+                let id = graph.node(attribute("id").ok_or("node without id")?);
+                node = Some(id).filter(|_| open);
+            }
+            "edge" => match (attribute("source"), attribute("target")) {
+                (Some(source), Some(target)) => graph.link(source, target),
+                _ => return Err("edge without source or target".to_string()),
+            },
+            "data" => {
+                text.clear();
+                data = attribute("key").map(str::to_string).filter(|_| open);
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Name of an element without its namespace prefix
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn xml_attributes(mut rest: &str) -> Result<Vec<(String, String)>, String> {
+    let mut attributes = vec![];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok(attributes);
+        }
+        let (name, value) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("invalid attribute \"{}\"", rest))?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|quote| *quote == '"' || *quote == '\'')
+            .ok_or_else(|| format!("attribute {} is not quoted", name.trim()))?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or_else(|| format!("attribute {} is not closed", name.trim()))?;
+        attributes.push((name.trim().to_string(), unescape(&value[1..end + 1])));
+        rest = &value[end + 2..];
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest.find(';').map(|end| (&rest[1..end], end));
+        let character = entity.and_then(|(entity, _)| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|code| u32::from_str_radix(code, 16))
+                .or_else(|| entity.strip_prefix('#').map(str::parse))
+                .and_then(Result::ok)
+                .and_then(char::from_u32),
+        });
+        match (character, entity) {
+            (Some(character), Some((_, end))) => {
+                result.push(character);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// A node-link document, {"nodes": [...], "links": [...]}, or a bare list of nodes. Nodes are
+// named by "id" or, as in testbed node listings, "network_address", and may be found under
+// "items"; links are objects with a source and a target or pairs, and may be found under "edges".
+fn read_json(contents: &str, graph: &mut Graph) -> Result<(), String> {
+    let document: Value = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    let list = |object: &serde_json::Map<String, Value>, names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| object.get(*name))
+            .and_then(Value::as_array)
+            .cloned()
+            .unwrap_or_default()
+    };
+    let (nodes, links) = match document {
+        Value::Array(nodes) => (nodes, vec![]),
+        Value::Object(object) => (
+            list(&object, &["nodes", "items"]),
+            list(&object, &["links", "edges"]),
+        ),
+        _ => return Err("expected an object or a list of nodes".to_string()),
+    };
+
+    for (index, node) in nodes.iter().enumerate() {
+        let object = node
+            .as_object()
+            .ok_or_else(|| format!("node {} is not an object", index))?;
+        let name = ["id", "network_address"]
+            .iter()
+            .find_map(|key| object.get(*key))
+            .map(json_text)
+            .ok_or_else(|| format!("node {} has no id", index))?;
+        let id = graph.node(&name);
+        for (attribute, value) in object {
+            let value = match value {
+                Value::Array(values) => {
+                    let values: Vec<String> = values.iter().map(json_text).collect();
+                    values.join(",")
+                }
+                value => json_text(value),
+            };
+            graph.set(id, attribute, &value)?;
+        }
+    }
+    for (index, link) in links.iter().enumerate() {
+        let ends = match link {
+            Value::Array(ends) => (ends.first(), ends.get(1)),
+            Value::Object(object) => (object.get("source"), object.get("target")),
+            _ => (None, None),
+        };
+        match ends {
+            (Some(source), Some(target)) => graph.link(&json_text(source), &json_text(target)),
+            _ => return Err(format!("link {} has no source or target", index)),
+        }
+    }
+    Ok(())
+}
+
+fn json_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rng::derive_rng, scenario::TopologyConfig, topology};
+
+    fn network() -> NodesVec {
+        let config: TopologyConfig = toml::from_str(
+            r#"
+            number_of_nodes = 40
+            number_of_gateways = 4
+            number_of_min_possible_neighbors = 0
+            number_of_max_possible_neighbors = 0
+            number_of_gateway_members = 12
+            model = "geometric"
+            geometric = { area = [100.0, 100.0, 30.0], radius = 35.0 }
+            clusters = { strategy = "nearest", memberships = 2 }
+            "#,
+        )
+        .unwrap();
+        topology::generate(&config, 5).unwrap()
+    }
+
+    // Read a document as the import of a file would
+    fn read(
+        format: NetworkFormat,
+        contents: &str,
+        number_of_nodes: usize,
+        number_of_gateways: usize,
+    ) -> Result<(NodesVec, Option<ClusterMap>), String> {
+        let mut graph = Graph::default();
+        match format {
+            NetworkFormat::Adjacency => read_adjacency(contents, &mut graph),
+            NetworkFormat::EdgeList => read_edge_list(contents, &mut graph),
+            NetworkFormat::Dot => read_dot(contents, &mut graph),
+            NetworkFormat::GraphMl => read_graphml(contents, &mut graph),
+            NetworkFormat::Json => read_json(contents, &mut graph),
+        }?;
+        graph.into_network(number_of_nodes, number_of_gateways, &mut derive_rng(0, &[]))
+    }
+
+    #[test]
+    fn exported_networks_are_imported_as_they_were() {
+        let nodes = network();
+        let directory = std::env::temp_dir().join(format!("io-round-trip-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for format in NetworkFormat::ALL {
+            let path = directory.join(format!("network.{}", format.label()));
+            let description = "geometric network\nseed=5 <&> \"quoted\"";
+            fs::write(&path, write_network(&nodes, format, description)).unwrap();
+            let config = FileConfig {
+                path,
+                format: Some(format),
+            };
+            let (imported, clusters) =
+                import(&config, nodes.len(), 4, &mut derive_rng(0, &[])).unwrap();
+            assert_eq!(imported.len(), nodes.len());
+            for node in nodes.iter() {
+                let other = &imported[node.id];
+                assert_eq!(other.kind, node.kind, "{}", format.label());
+                let mut neighbors = imported.neighbors(node.id).to_vec();
+                neighbors.sort_unstable();
+                let mut expected = nodes.neighbors(node.id).to_vec();
+                expected.sort_unstable();
+                assert_eq!(neighbors, expected, "{}", format.label());
+                assert_eq!(other.max_possible_neighbors, expected.len());
+            }
+            // The adjacency list only has the links and kinds
+            if format == NetworkFormat::Adjacency {
+                assert!(clusters.is_none());
+                assert!(imported.iter().all(|node| node.position.is_none()));
+                continue;
+            }
+            let clusters = clusters.unwrap();
+            for node in nodes.iter() {
+                assert_eq!(
+                    imported[node.id].position,
+                    node.position,
+                    "{}",
+                    format.label()
+                );
+                let mut gateways = clusters.gateways(node.id).to_vec();
+                gateways.sort_unstable();
+                let mut expected = nodes.clusters().gateways(node.id).to_vec();
+                expected.sort_unstable();
+                assert_eq!(gateways, expected, "{}", format.label());
+            }
+        }
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn malformed_dot_is_rejected() {
+        let unterminated = "graph network {\n  0 [kind=\"gateway];\n}\n";
+        assert_eq!(
+            read(NetworkFormat::Dot, unterminated, 1, 1).unwrap_err(),
+            "unterminated string"
+        );
+        let escaped = "graph network { 0 [kind=\"gateway\\\"]; }";
+        assert_eq!(
+            read(NetworkFormat::Dot, escaped, 1, 1).unwrap_err(),
+            "unterminated string"
+        );
+        let unclosed = "graph network { 0 [kind=gateway]; 0 -- 1;";
+        assert_eq!(
+            read(NetworkFormat::Dot, unclosed, 2, 1).unwrap_err(),
+            "missing closing }"
+        );
+        assert_eq!(
+            read(NetworkFormat::Dot, "network { 0; }", 1, 0).unwrap_err(),
+            "expected a graph or digraph"
+        );
+        let dangling = "graph network { 0 -- { 1 } }";
+        assert!(read(NetworkFormat::Dot, dangling, 2, 0)
+            .unwrap_err()
+            .starts_with("link from 0"));
+    }
+
+    #[test]
+    fn unknown_node_ids_are_rejected() {
+        // A link to an undeclared node adds it, which the node count or the kinds then refuse
+        let edges = "# node 0 kind=gateway\n# node 1 kind=constrained\n0 1\n1 7\n";
+        assert_eq!(
+            read(NetworkFormat::EdgeList, edges, 3, 1).unwrap_err(),
+            "node 7 has no kind while other nodes have one"
+        );
+        let dot = "graph network { 0 [kind=gateway]; 1 [kind=constrained]; 0 -- 1; 1 -- 7; }";
+        assert_eq!(
+            read(NetworkFormat::Dot, dot, 2, 1).unwrap_err(),
+            "the file has 3 nodes but number_of_nodes is 2"
+        );
+        let json = r#"{"nodes": [{"id": 0, "kind": "gateway"},
+            {"id": 1, "kind": "constrained", "cluster": [9]}], "links": [[0, 1]]}"#;
+        assert_eq!(
+            read(NetworkFormat::Json, json, 2, 1).unwrap_err(),
+            "node 1 joins unknown node 9"
+        );
+        let member = r#"[{"id": "a", "kind": "gateway"},
+            {"id": "b", "kind": "constrained", "cluster": "b"}]"#;
+        assert!(read(NetworkFormat::Json, member, 2, 1)
+            .unwrap_err()
+            .starts_with("node b cannot be a member of node b"));
+    }
+
+    #[test]
+    fn malformed_graphml_is_rejected() {
+        let document = |data: &str| {
+            format!(
+                "<graphml><key id=\"k\" for=\"node\" attr.name=\"kind\"/><graph>\
+                 <node id=\"0\"><data key=\"k\">{}</data></node></graph></graphml>",
+                data
+            )
+        };
+        // Unknown or invalid entities are kept as they are, and then make an invalid value
+        for data in ["gate&bogus;way", "gateway&#xZZ;", "gateway&amp"] {
+            let error = read(NetworkFormat::GraphMl, &document(data), 1, 1).unwrap_err();
+            assert!(error.contains("unknown kind"), "{}", error);
+        }
+        let (nodes, _) =
+            read(NetworkFormat::GraphMl, &document("gate&#119;a&#x79;"), 1, 1).unwrap();
+        assert_eq!(nodes[0].kind, NodeType::Gateway);
+        assert_eq!(unescape("&lt;&amp;&gt;&quot;&apos;"), "<&>\"'");
+
+        assert_eq!(
+            read(NetworkFormat::GraphMl, "<graphml><node id=\"0\"", 1, 0).unwrap_err(),
+            "unterminated tag"
+        );
+        assert_eq!(
+            read(NetworkFormat::GraphMl, "<node id=0/>", 1, 0).unwrap_err(),
+            "attribute id is not quoted"
+        );
+        assert_eq!(
+            read(NetworkFormat::GraphMl, "<node id=\"0/>", 1, 0).unwrap_err(),
+            "attribute id is not closed"
+        );
+        assert_eq!(
+            read(NetworkFormat::GraphMl, "<edge source=\"0\"/>", 1, 0).unwrap_err(),
+            "edge without source or target"
+        );
+    }
+
+    #[test]
+    fn malformed_json_and_coordinates_are_rejected() {
+        assert!(read(NetworkFormat::Json, "{\"nodes\": [", 1, 0).is_err());
+        assert_eq!(
+            read(NetworkFormat::Json, "[{\"kind\": \"gateway\"}]", 1, 1).unwrap_err(),
+            "node 0 has no id"
+        );
+        assert_eq!(
+            read(NetworkFormat::Json, "[{\"id\": 0, \"x\": \"far\"}]", 1, 0).unwrap_err(),
+            "node 0 has an invalid coordinate \"far\""
+        );
+        assert_eq!(
+            read(NetworkFormat::Json, "[{\"id\": 0, \"x\": 1.0}]", 1, 0).unwrap_err(),
+            "node 0 has a coordinate without the others"
+        );
+        assert_eq!(
+            read(NetworkFormat::Adjacency, "0\n", 1, 0).unwrap_err(),
+            "line 1 has no kind"
+        );
+    }
+}
@@ -0,0 +1,455 @@
+use std::{collections::HashSet, fmt, path::PathBuf};
+
+use rand::{seq::SliceRandom, Rng};
+use serde::Deserialize;
+
+use crate::{
+    rng::{derive_rng, TOPOLOGY_STREAM},
+    scenario::TopologyConfig,
+    Node, NodeType, NodesVec,
+};
+use clusters::ClusterMap;
+
+pub mod analysis;
+pub mod bounded;
+pub mod clusters;
+pub mod geometric;
+pub mod io;
+pub mod random;
+pub mod structured;
+
+// Generator of the network, selected with `model` in the topology table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyModel {
+    // Random graph within the neighbor bounds
+    #[default]
+    Bounded,
+    // Nodes placed in an area and linked within radio range, see [topology.geometric]
+    Geometric,
+    // The models below take their parameters from the table of the same name
+    ErdosRenyi,
+    BarabasiAlbert,
+    WattsStrogatz,
+    Lattice,
+    Tree,
+    // Gateways linked to a central gateway, constrained nodes linked to one gateway each
+    StarOfStars,
+    // Network read from a file, see [topology.file]
+    File,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TopologyError {
+    DegreeBounds {
+        min: usize,
+        max: usize,
+        number_of_nodes: usize,
+    },
+    Gateways {
+        number_of_gateways: usize,
+        number_of_nodes: usize,
+    },
+    // Every node must have the same degree but their sum cannot be even
+    OddDegreeSum {
+        degree: usize,
+        number_of_nodes: usize,
+    },
+    Unsatisfied {
+        node: usize,
+        degree: usize,
+        min: usize,
+    },
+    InvalidParameter {
+        field: &'static str,
+        reason: String,
+    },
+    MissingParameters(TopologyModel),
+    Positions {
+        path: PathBuf,
+        reason: String,
+    },
+    Import {
+        path: PathBuf,
+        reason: String,
+    },
+    Disconnected {
+        components: usize,
+        largest: usize,
+        number_of_nodes: usize,
+    },
+    // Constrained nodes without a path to any gateway
+    Unreachable {
+        nodes: usize,
+        first: usize,
+    },
+}
+
+// Number of nodes of each degree, indexed by degree
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DegreeDistribution {
+    counts: Vec<usize>,
+}
+
+// Generate the network described by the scenario from the topology stream of the master seed
+pub fn generate(config: &TopologyConfig, seed: u64) -> Result<NodesVec, TopologyError> {
+    validate(config)?;
+    let rng = &mut derive_rng(seed, &[TOPOLOGY_STREAM]);
+    let number_of_nodes = config.number_of_nodes as usize;
+    let number_of_gateways = config.number_of_gateways as usize;
+    if number_of_gateways > number_of_nodes {
+        return Err(TopologyError::Gateways {
+            number_of_gateways,
+            number_of_nodes,
+        });
+    }
+    let missing = || TopologyError::MissingParameters(config.model);
+    let mut file_clusters = None;
+    let mut network = match config.model {
+        TopologyModel::Bounded => bounded::generate(
+            number_of_nodes,
+            number_of_gateways,
+            config.number_of_min_possible_neighbors as usize,
+            config.number_of_max_possible_neighbors as usize,
+            rng,
+        )?,
+        TopologyModel::Geometric => {
+            let geometric = config.geometric.as_ref().ok_or_else(missing)?;
+            geometric::generate(number_of_nodes, number_of_gateways, geometric, rng)?
+        }
+        TopologyModel::ErdosRenyi => {
+            let parameters = config.erdos_renyi.as_ref().ok_or_else(missing)?;
+            let edges = random::erdos_renyi(number_of_nodes, parameters, rng);
+            network(
+                random_kinds(number_of_nodes, number_of_gateways, rng),
+                &edges,
+            )
+        }
+        TopologyModel::BarabasiAlbert => {
+            let parameters = config.barabasi_albert.as_ref().ok_or_else(missing)?;
+            let edges = random::barabasi_albert(number_of_nodes, parameters, rng);
+            network(
+                random_kinds(number_of_nodes, number_of_gateways, rng),
+                &edges,
+            )
+        }
+        TopologyModel::WattsStrogatz => {
+            let parameters = config.watts_strogatz.as_ref().ok_or_else(missing)?;
+            let edges = random::watts_strogatz(number_of_nodes, parameters, rng);
+            network(
+                random_kinds(number_of_nodes, number_of_gateways, rng),
+                &edges,
+            )
+        }
+        TopologyModel::Lattice => {
+            let edges = structured::lattice(number_of_nodes, &config.lattice.unwrap_or_default());
+            network(
+                random_kinds(number_of_nodes, number_of_gateways, rng),
+                &edges,
+            )
+        }
+        // Gateways at the top levels of the tree
+        TopologyModel::Tree => {
+            let edges = structured::tree(number_of_nodes, &config.tree.unwrap_or_default());
+            network(leading_kinds(number_of_nodes, number_of_gateways), &edges)
+        }
+        TopologyModel::StarOfStars => {
+            let edges = structured::star_of_stars(number_of_nodes, number_of_gateways);
+            network(leading_kinds(number_of_nodes, number_of_gateways), &edges)
+        }
+        TopologyModel::File => {
+            let file = config.file.as_ref().ok_or_else(missing)?;
+            let (network, clusters) = io::import(file, number_of_nodes, number_of_gateways, rng)?;
+            file_clusters = clusters;
+            network
+        }
+    };
+    // Clusters given by an imported file are kept as they are
+    let clusters = file_clusters.unwrap_or_else(|| {
+        ClusterMap::assign(
+            &network,
+            &config.clusters,
+            config.number_of_gateway_members as usize,
+            rng,
+        )
+    });
+    network.set_clusters(clusters);
+    Ok(network)
+}
+
+// Check the parameters of the selected model
+pub fn validate(config: &TopologyConfig) -> Result<(), TopologyError> {
+    let number_of_nodes = config.number_of_nodes as usize;
+    config.clusters.validate(
+        number_of_nodes,
+        config.number_of_gateways as usize,
+        config.number_of_gateway_members as usize,
+    )?;
+    let missing = || TopologyError::MissingParameters(config.model);
+    match config.model {
+        TopologyModel::Bounded => Ok(()),
+        TopologyModel::Geometric => config.geometric.as_ref().ok_or_else(missing)?.validate(),
+        TopologyModel::ErdosRenyi => config.erdos_renyi.ok_or_else(missing)?.validate(),
+        TopologyModel::BarabasiAlbert => config
+            .barabasi_albert
+            .ok_or_else(missing)?
+            .validate(number_of_nodes),
+        TopologyModel::WattsStrogatz => config
+            .watts_strogatz
+            .ok_or_else(missing)?
+            .validate(number_of_nodes),
+        TopologyModel::Lattice => config.lattice.unwrap_or_default().validate(number_of_nodes),
+        TopologyModel::Tree => config.tree.unwrap_or_default().validate(),
+        TopologyModel::StarOfStars => {
+            if config.number_of_gateways == 0 {
+                return Err(TopologyError::InvalidParameter {
+                    field: "number_of_gateways",
+                    reason: "the star of stars model needs at least one gateway".to_string(),
+                });
+            }
+            Ok(())
+        }
+        TopologyModel::File => config.file.as_ref().map(|_| ()).ok_or_else(missing),
+    }
+}
+
+// Node kinds with the gateways at random places
+fn random_kinds<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    number_of_gateways: usize,
+    rng: &mut R,
+) -> Vec<NodeType> {
+    let mut kinds = leading_kinds(number_of_nodes, number_of_gateways);
+    kinds.shuffle(rng);
+    kinds
+}
+
+fn leading_kinds(number_of_nodes: usize, number_of_gateways: usize) -> Vec<NodeType> {
+    (0..number_of_nodes)
+        .map(|id| {
+            if id < number_of_gateways {
+                NodeType::Gateway
+            } else {
+                NodeType::Constrained
+            }
+        })
+        .collect()
+}
+
+// Network of nodes with the given kinds, each allowed as many neighbors as it got links
+fn network(kinds: Vec<NodeType>, edges: &[(usize, usize)]) -> NodesVec {
+    let mut degrees = vec![0; kinds.len()];
+    for (a, b) in edges.iter() {
+        degrees[*a] += 1;
+        degrees[*b] += 1;
+    }
+    let nodes = kinds
+        .into_iter()
+        .zip(degrees)
+        .enumerate()
+        .map(|(id, (kind, degree))| Node::new(id, kind, degree))
+        .collect();
+    NodesVec::from_edges(nodes, edges)
+}
+
+impl TopologyModel {
+    pub fn label(&self) -> &'static str {
+        match self {
+            TopologyModel::Bounded => "bounded",
+            TopologyModel::Geometric => "geometric",
+            TopologyModel::ErdosRenyi => "erdos_renyi",
+            TopologyModel::BarabasiAlbert => "barabasi_albert",
+            TopologyModel::WattsStrogatz => "watts_strogatz",
+            TopologyModel::Lattice => "lattice",
+            TopologyModel::Tree => "tree",
+            TopologyModel::StarOfStars => "star_of_stars",
+            TopologyModel::File => "file",
+        }
+    }
+}
+
+impl DegreeDistribution {
+    pub fn of(nodes: &NodesVec) -> Self {
+        let mut counts = vec![];
+        for node in nodes.iter() {
+            let degree = nodes.degree(node.id);
+            if degree >= counts.len() {
+                counts.resize(degree + 1, 0);
+            }
+            counts[degree] += 1;
+        }
+        Self { counts }
+    }
+
+    pub fn count(&self, degree: usize) -> usize {
+        self.counts.get(degree).copied().unwrap_or(0)
+    }
+
+    pub fn nodes(&self) -> usize {
+        self.counts.iter().sum()
+    }
+
+    pub fn min(&self) -> Option<usize> {
+        self.counts.iter().position(|count| *count > 0)
+    }
+
+    pub fn max(&self) -> Option<usize> {
+        self.counts.iter().rposition(|count| *count > 0)
+    }
+
+    pub fn mean(&self) -> f64 {
+        let links: usize = self
+            .counts
+            .iter()
+            .enumerate()
+            .map(|(degree, count)| degree * count)
+            .sum();
+        links as f64 / self.nodes() as f64
+    }
+
+    // Degrees with at least one node and their node counts, by increasing degree
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.counts
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, count)| *count > 0)
+    }
+}
+
+// e.g. "min=2 max=4 mean=3.1 counts=2:10,3:70,4:20"
+impl fmt::Display for DegreeDistribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (min, max) = match (self.min(), self.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return write!(f, "no nodes"),
+        };
+        let counts: Vec<String> = self
+            .iter()
+            .map(|(degree, count)| format!("{}:{}", degree, count))
+            .collect();
+        write!(
+            f,
+            "min={} max={} mean={} counts={}",
+            min,
+            max,
+            self.mean(),
+            counts.join(",")
+        )
+    }
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyError::DegreeBounds {
+                min,
+                max,
+                number_of_nodes,
+            } => write!(
+                f,
+                "cannot give every node between {} and {} neighbors in a network of {} nodes",
+                min, max, number_of_nodes
+            ),
+            TopologyError::Gateways {
+                number_of_gateways,
+                number_of_nodes,
+            } => write!(
+                f,
+                "{} gateways exceed the {} nodes of the network",
+                number_of_gateways, number_of_nodes
+            ),
+            TopologyError::OddDegreeSum {
+                degree,
+                number_of_nodes,
+            } => write!(
+                f,
+                "{} nodes cannot all have {} neighbors, the number of link ends would be odd",
+                number_of_nodes, degree
+            ),
+            TopologyError::Unsatisfied { node, degree, min } => write!(
+                f,
+                "node {} only got {} neighbors out of the {} required, widen the degree bounds",
+                node, degree, min
+            ),
+            TopologyError::InvalidParameter { field, reason } => {
+                write!(f, "invalid value for {}: {}", field, reason)
+            }
+            TopologyError::MissingParameters(model) => write!(
+                f,
+                "the {} model needs a [topology.{}] table",
+                model.label(),
+                model.label()
+            ),
+            TopologyError::Positions { path, reason } => {
+                write!(f, "cannot read positions {}: {}", path.display(), reason)
+            }
+            TopologyError::Import { path, reason } => {
+                write!(f, "cannot import network {}: {}", path.display(), reason)
+            }
+            TopologyError::Disconnected {
+                components,
+                largest,
+                number_of_nodes,
+            } => write!(
+                f,
+                "the network is split in {} components, the largest has {} of the {} nodes",
+                components, largest, number_of_nodes
+            ),
+            TopologyError::Unreachable { nodes, first } => write!(
+                f,
+                "{} constrained nodes cannot reach a gateway, node {} among them",
+                nodes, first
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+// Undirected links in the order they were made, with a set to reject duplicates
+#[derive(Default)]
+struct Links {
+    edges: Vec<(usize, usize)>,
+    set: HashSet<(usize, usize)>,
+}
+
+impl Links {
+    fn key(a: usize, b: usize) -> (usize, usize) {
+        (a.min(b), a.max(b))
+    }
+
+    fn len(&self) -> usize {
+        self.edges.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.edges.is_empty()
+    }
+
+    fn contains(&self, a: usize, b: usize) -> bool {
+        self.set.contains(&Self::key(a, b))
+    }
+
+    // Add a link unless it is a self loop or already exists
+    fn insert(&mut self, a: usize, b: usize) -> bool {
+        if a == b || !self.set.insert(Self::key(a, b)) {
+            return false;
+        }
+        self.edges.push((a, b));
+        true
+    }
+
+    fn swap_remove(&mut self, index: usize) {
+        let (a, b) = self.edges.swap_remove(index);
+        self.set.remove(&Self::key(a, b));
+    }
+
+    // Replace a link with a new one, which must not exist yet
+    fn replace(&mut self, index: usize, a: usize, b: usize) {
+        let (old_a, old_b) = self.edges[index];
+        self.set.remove(&Self::key(old_a, old_b));
+        self.set.insert(Self::key(a, b));
+        self.edges[index] = (a, b);
+    }
+}
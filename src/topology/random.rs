@@ -0,0 +1,287 @@
+use rand::Rng;
+use serde::Deserialize;
+
+use super::{Links, TopologyError};
+
+// G(n, p): every pair of nodes is linked independently with the same probability
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErdosRenyiConfig {
+    pub probability: f64,
+}
+
+// Scale free network grown by preferential attachment: every new node links to `links` existing
+// nodes picked with a probability proportional to their degree
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BarabasiAlbertConfig {
+    pub links: usize,
+}
+
+// Small world network: a ring where every node is linked to its `neighbors` closest nodes, whose
+// links are then moved to a random node with the rewiring probability
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WattsStrogatzConfig {
+    pub neighbors: usize,
+    pub rewiring: f64,
+}
+
+fn probability(field: &'static str, value: f64) -> Result<(), TopologyError> {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(TopologyError::InvalidParameter {
+            field,
+            reason: format!("{} is not a probability", value),
+        });
+    }
+    Ok(())
+}
+
+impl ErdosRenyiConfig {
+    pub fn validate(&self) -> Result<(), TopologyError> {
+        probability("probability", self.probability)
+    }
+}
+
+impl BarabasiAlbertConfig {
+    pub fn validate(&self, number_of_nodes: usize) -> Result<(), TopologyError> {
+        if self.links == 0 || self.links >= number_of_nodes {
+            return Err(TopologyError::InvalidParameter {
+                field: "links",
+                reason: format!(
+                    "must be between 1 and {} for a network of {} nodes",
+                    number_of_nodes.saturating_sub(1),
+                    number_of_nodes
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl WattsStrogatzConfig {
+    pub fn validate(&self, number_of_nodes: usize) -> Result<(), TopologyError> {
+        if self.neighbors == 0 || self.neighbors % 2 == 1 || self.neighbors >= number_of_nodes {
+            return Err(TopologyError::InvalidParameter {
+                field: "neighbors",
+                reason: format!(
+                    "must be an even number between 2 and {} for a network of {} nodes",
+                    number_of_nodes.saturating_sub(1),
+                    number_of_nodes
+                ),
+            });
+        }
+        probability("rewiring", self.rewiring)
+    }
+}
+
+// Pairs are visited by jumping over a geometric number of pairs at a time (Batagelj & Brandes,
+// 2005), which takes time linear in the number of nodes and links instead of all the pairs
+pub fn erdos_renyi<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    config: &ErdosRenyiConfig,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    let mut edges = vec![];
+    if config.probability <= 0.0 {
+        return edges;
+    }
+    let log_miss = (1.0 - config.probability).ln();
+    // Pair (v, w) with w < v, starting before the first pair (1, 0)
+    let (mut v, mut w) = (1, -1i64);
+    while v < number_of_nodes {
+        let draw: f64 = rng.gen();
+        w += 1 + ((1.0 - draw).ln() / log_miss).floor() as i64;
+        while w >= v as i64 && v < number_of_nodes {
+            w -= v as i64;
+            v += 1;
+        }
+        if v < number_of_nodes {
+            edges.push((w as usize, v));
+        }
+    }
+    edges
+}
+
+pub fn barabasi_albert<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    config: &BarabasiAlbertConfig,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    // Start from a complete graph so that the first nodes also have `links` links
+    let initial = (config.links + 1).min(number_of_nodes);
+    let mut edges = vec![];
+    for a in 0..initial {
+        for b in a + 1..initial {
+            edges.push((a, b));
+        }
+    }
+    // Every node appears once per link end, so picking from it favors nodes by their degree
+    let mut ends: Vec<usize> = edges.iter().flat_map(|(a, b)| [*a, *b]).collect();
+    let mut targets = Vec::with_capacity(config.links);
+    for node in initial..number_of_nodes {
+        targets.clear();
+        while targets.len() < config.links {
+            let target = ends[rng.gen_range(0..ends.len())];
+            if !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+        for target in targets.iter() {
+            edges.push((*target, node));
+            ends.extend([*target, node]);
+        }
+    }
+    edges
+}
+
+pub fn watts_strogatz<R: Rng + ?Sized>(
+    number_of_nodes: usize,
+    config: &WattsStrogatzConfig,
+    rng: &mut R,
+) -> Vec<(usize, usize)> {
+    let mut links = Links::default();
+    for offset in 1..=config.neighbors / 2 {
+        for a in 0..number_of_nodes {
+            links.insert(a, (a + offset) % number_of_nodes);
+        }
+    }
+    let mut degrees = vec![config.neighbors; number_of_nodes];
+
+    // Move the far end of each ring link to a node that is not already a neighbor
+    for index in 0..links.len() {
+        if rng.gen::<f64>() >= config.rewiring {
+            continue;
+        }
+        let (a, b) = links.edges[index];
+        if degrees[a] >= number_of_nodes - 1 {
+            continue;
+        }
+        let c = loop {
+            let c = rng.gen_range(0..number_of_nodes);
+            if c != a && !links.contains(a, c) {
+                break c;
+            }
+        };
+        links.replace(index, a, c);
+        degrees[b] -= 1;
+        degrees[c] += 1;
+    }
+    links.edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{rng::derive_rng, scenario::TopologyConfig, topology, NodeType};
+
+    // Degree of every node, checking that the links have no self loops or duplicates
+    fn degrees(number_of_nodes: usize, edges: &[(usize, usize)]) -> Vec<usize> {
+        let mut links = Links::default();
+        let mut degrees = vec![0; number_of_nodes];
+        for (a, b) in edges.iter().copied() {
+            assert_ne!(a, b);
+            assert!(links.insert(a, b), "{}-{} is linked twice", a, b);
+            degrees[a] += 1;
+            degrees[b] += 1;
+        }
+        degrees
+    }
+
+    #[test]
+    fn erdos_renyi_links_the_expected_share_of_pairs() {
+        let rng = &mut derive_rng(1, &[]);
+        let config = ErdosRenyiConfig { probability: 0.05 };
+        let edges = erdos_renyi(300, &config, rng);
+        degrees(300, &edges);
+        assert!(edges.iter().all(|(a, b)| a < b && *b < 300));
+        // 44850 pairs: 2242.5 links expected, with a standard deviation of 46
+        assert!(
+            (2012..=2473).contains(&edges.len()),
+            "{} links",
+            edges.len()
+        );
+
+        let none = erdos_renyi(20, &ErdosRenyiConfig { probability: 0.0 }, rng);
+        assert!(none.is_empty());
+        let all = erdos_renyi(20, &ErdosRenyiConfig { probability: 1.0 }, rng);
+        assert!(degrees(20, &all).iter().all(|degree| *degree == 19));
+    }
+
+    #[test]
+    fn barabasi_albert_adds_links_per_node() {
+        let rng = &mut derive_rng(2, &[]);
+        let config = BarabasiAlbertConfig { links: 3 };
+        let edges = barabasi_albert(200, &config, rng);
+        // A complete graph of 4 nodes, then 3 links for each of the 196 others
+        assert_eq!(edges.len(), 6 + 196 * 3);
+        assert!(degrees(200, &edges).iter().all(|degree| *degree >= 3));
+    }
+
+    #[test]
+    fn watts_strogatz_keeps_the_number_of_links() {
+        let rng = &mut derive_rng(3, &[]);
+        let ring = watts_strogatz(
+            50,
+            &WattsStrogatzConfig {
+                neighbors: 4,
+                rewiring: 0.0,
+            },
+            rng,
+        );
+        assert!(degrees(50, &ring).iter().all(|degree| *degree == 4));
+        assert!(ring.contains(&(49, 0)) && ring.contains(&(48, 0)));
+
+        let rewired = watts_strogatz(
+            50,
+            &WattsStrogatzConfig {
+                neighbors: 4,
+                rewiring: 0.3,
+            },
+            rng,
+        );
+        assert_eq!(rewired.len(), 100);
+        assert_ne!(degrees(50, &rewired), vec![4; 50]);
+    }
+
+    #[test]
+    fn random_models_place_the_gateways() {
+        for (model, table) in [
+            ("erdos_renyi", "erdos_renyi = { probability = 0.1 }"),
+            ("barabasi_albert", "barabasi_albert = { links = 2 }"),
+            (
+                "watts_strogatz",
+                "watts_strogatz = { neighbors = 4, rewiring = 0.2 }",
+            ),
+        ] {
+            let config: TopologyConfig = toml::from_str(&format!(
+                r#"
+                number_of_nodes = 80
+                number_of_gateways = 6
+                number_of_min_possible_neighbors = 0
+                number_of_max_possible_neighbors = 0
+                number_of_gateway_members = 10
+                model = "{}"
+                {}
+                "#,
+                model, table
+            ))
+            .unwrap();
+            for seed in [5, 6] {
+                let nodes = topology::generate(&config, seed).unwrap();
+                assert_eq!(nodes.len(), 80);
+                let gateways = nodes
+                    .iter()
+                    .filter(|node| node.kind == NodeType::Gateway)
+                    .count();
+                assert_eq!(gateways, 6, "{} with seed {}", model, seed);
+                for node in nodes.iter() {
+                    assert_eq!(nodes.degree(node.id), node.max_possible_neighbors);
+                    for neighbor in nodes.neighbors(node.id) {
+                        assert!(nodes.neighbors(*neighbor).contains(&node.id));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use serde::Deserialize;
+
+use super::{Links, TopologyError};
+
+// Rectangular grid filled row by row, every node linked to the nodes next to it. A torus also
+// links the last node of each row and column to the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct LatticeConfig {
+    // As many as the rows of a square grid by default
+    pub columns: Option<usize>,
+    pub torus: bool,
+}
+
+// Complete tree where every node has `children` children, filled level by level
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct TreeConfig {
+    pub children: usize,
+}
+
+impl Default for TreeConfig {
+    fn default() -> Self {
+        Self { children: 2 }
+    }
+}
+
+impl LatticeConfig {
+    pub fn columns(&self, number_of_nodes: usize) -> usize {
+        self.columns
+            .unwrap_or_else(|| (number_of_nodes as f64).sqrt().ceil() as usize)
+            .max(1)
+    }
+
+    pub fn validate(&self, number_of_nodes: usize) -> Result<(), TopologyError> {
+        if self.columns == Some(0) {
+            return Err(TopologyError::InvalidParameter {
+                field: "columns",
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        let columns = self.columns(number_of_nodes);
+        if self.torus && !number_of_nodes.is_multiple_of(columns) {
+            return Err(TopologyError::InvalidParameter {
+                field: "torus",
+                reason: format!(
+                    "{} nodes do not fill rows of {} columns",
+                    number_of_nodes, columns
+                ),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl TreeConfig {
+    pub fn validate(&self) -> Result<(), TopologyError> {
+        if self.children == 0 {
+            return Err(TopologyError::InvalidParameter {
+                field: "children",
+                reason: "must be greater than 0".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+pub fn lattice(number_of_nodes: usize, config: &LatticeConfig) -> Vec<(usize, usize)> {
+    let columns = config.columns(number_of_nodes);
+    // Small grids would link the same nodes twice or a node to itself once wrapped
+    let mut links = Links::default();
+    for id in 0..number_of_nodes {
+        let column = id % columns;
+        if column + 1 < columns && id + 1 < number_of_nodes {
+            links.insert(id, id + 1);
+        } else if config.torus {
+            links.insert(id, id - column);
+        }
+        if id + columns < number_of_nodes {
+            links.insert(id, id + columns);
+        } else if config.torus {
+            links.insert(id, column);
+        }
+    }
+    links.edges
+}
+
+pub fn tree(number_of_nodes: usize, config: &TreeConfig) -> Vec<(usize, usize)> {
+    (1..number_of_nodes)
+        .map(|id| ((id - 1) / config.children, id))
+        .collect()
+}
+
+// The gateways are the centers of the stars and are all linked to the first one, constrained nodes
+// are spread evenly over them
+pub fn star_of_stars(number_of_nodes: usize, number_of_gateways: usize) -> Vec<(usize, usize)> {
+    let mut edges: Vec<(usize, usize)> = (1..number_of_gateways).map(|id| (0, id)).collect();
+    edges.extend(
+        (number_of_gateways..number_of_nodes)
+            .map(|id| ((id - number_of_gateways) % number_of_gateways, id)),
+    );
+    edges
+}